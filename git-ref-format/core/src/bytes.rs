@@ -0,0 +1,430 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    borrow::{Borrow, Cow},
+    convert::TryFrom,
+    fmt::{self, Display},
+    iter::FromIterator,
+    ops::Deref,
+    str::Utf8Error,
+    string::FromUtf8Error,
+};
+
+use bstr::{BStr, BString, ByteSlice as _};
+
+use crate::{check, RefStr, RefString};
+
+const CHECK_OPTS: check::Options = check::Options {
+    allow_pattern: false,
+    allow_onelevel: true,
+};
+
+/// A byte-oriented [`RefStr`].
+///
+/// `git` allows ref names to be arbitrary bytes, as long as they pass
+/// [`git-check-ref-format`]. Unlike [`RefStr`], a [`BRefStr`] does not require
+/// its contents to be valid UTF-8, and can thus represent any ref name found in
+/// a repository.
+///
+/// A [`RefStr`] can always be converted to a [`BRefStr`], while the reverse
+/// conversion succeeds _iff_ the bytes are valid UTF-8.
+///
+/// [`git-check-ref-format`]: https://git-scm.com/docs/git-check-ref-format
+#[repr(transparent)]
+#[derive(Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub struct BRefStr([u8]);
+
+impl BRefStr {
+    pub fn try_from_bytes(b: &[u8]) -> Result<&BRefStr, check::Error> {
+        check::ref_format_bytes(CHECK_OPTS, b).map(|()| BRefStr::from_bytes(b))
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    #[inline]
+    pub fn as_bstr(&self) -> &BStr {
+        self.0.as_bstr()
+    }
+
+    #[inline]
+    pub fn to_bref_string(&self) -> BRefString {
+        self.to_owned()
+    }
+
+    /// Convert to a [`RefStr`], if `self` is valid UTF-8.
+    #[inline]
+    pub fn to_refstr(&self) -> Result<&RefStr, Utf8Error> {
+        <&RefStr>::try_from(self)
+    }
+
+    pub fn strip_prefix<P>(&self, base: P) -> Option<&BRefStr>
+    where
+        P: AsRef<BRefStr>,
+    {
+        self._strip_prefix(base.as_ref())
+    }
+
+    fn _strip_prefix(&self, base: &BRefStr) -> Option<&BRefStr> {
+        self.0
+            .strip_prefix(base.as_bytes())
+            .and_then(|s| s.strip_prefix(b"/"))
+            .map(Self::from_bytes)
+    }
+
+    /// Join `other` onto `self`, yielding a new [`BRefString`].
+    pub fn join<R>(&self, other: R) -> BRefString
+    where
+        R: AsRef<BRefStr>,
+    {
+        self._join(other.as_ref())
+    }
+
+    fn _join(&self, other: &BRefStr) -> BRefString {
+        let mut buf = self.to_bref_string();
+        buf.push(other);
+        buf
+    }
+
+    /// Iterate over the '/'-separated components of `self`.
+    ///
+    /// Each component is a valid [`BRefStr`] on its own, except if it is
+    /// "@".
+    pub fn iter(&self) -> impl Iterator<Item = &BStr> {
+        self.0.split_str("/").map(|c| c.as_bstr())
+    }
+
+    pub(crate) fn from_bytes(b: &[u8]) -> &BRefStr {
+        unsafe { &*(b as *const [u8] as *const BRefStr) }
+    }
+}
+
+impl Deref for BRefStr {
+    type Target = BStr;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.as_bstr()
+    }
+}
+
+impl AsRef<[u8]> for BRefStr {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<BStr> for BRefStr {
+    #[inline]
+    fn as_ref(&self) -> &BStr {
+        self
+    }
+}
+
+impl AsRef<BRefStr> for &BRefStr {
+    #[inline]
+    fn as_ref(&self) -> &BRefStr {
+        self
+    }
+}
+
+impl AsRef<BRefStr> for RefStr {
+    #[inline]
+    fn as_ref(&self) -> &BRefStr {
+        self.into()
+    }
+}
+
+impl AsRef<BRefStr> for RefString {
+    #[inline]
+    fn as_ref(&self) -> &BRefStr {
+        self.as_refstr().into()
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for &'a BRefStr {
+    type Error = check::Error;
+
+    #[inline]
+    fn try_from(b: &'a [u8]) -> Result<Self, Self::Error> {
+        BRefStr::try_from_bytes(b)
+    }
+}
+
+impl<'a> TryFrom<&'a BStr> for &'a BRefStr {
+    type Error = check::Error;
+
+    #[inline]
+    fn try_from(b: &'a BStr) -> Result<Self, Self::Error> {
+        BRefStr::try_from_bytes(b.as_bytes())
+    }
+}
+
+impl<'a> From<&'a RefStr> for &'a BRefStr {
+    #[inline]
+    fn from(rs: &'a RefStr) -> Self {
+        BRefStr::from_bytes(rs.as_bytes())
+    }
+}
+
+impl<'a> TryFrom<&'a BRefStr> for &'a RefStr {
+    type Error = Utf8Error;
+
+    #[inline]
+    fn try_from(rs: &'a BRefStr) -> Result<Self, Self::Error> {
+        std::str::from_utf8(rs.as_bytes()).map(RefStr::from_str)
+    }
+}
+
+impl<'a> From<&'a BRefStr> for Cow<'a, BRefStr> {
+    #[inline]
+    fn from(rs: &'a BRefStr) -> Cow<'a, BRefStr> {
+        Cow::Borrowed(rs)
+    }
+}
+
+impl fmt::Debug for BRefStr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_bstr(), f)
+    }
+}
+
+/// Lossy, see [`BStr`].
+impl Display for BRefStr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self.as_bstr(), f)
+    }
+}
+
+/// The owned version of [`BRefStr`].
+#[derive(Clone, Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub struct BRefString(Vec<u8>);
+
+impl BRefString {
+    #[inline]
+    pub fn as_bref_str(&self) -> &BRefStr {
+        self
+    }
+
+    /// Convert to a [`RefString`], if `self` is valid UTF-8.
+    ///
+    /// The error retains the bytes, so no information is lost either way.
+    pub fn into_refstring(self) -> Result<RefString, FromUtf8Error> {
+        RefString::try_from(self)
+    }
+
+    /// Join `other` onto `self` in place.
+    ///
+    /// This is a consuming version of [`BRefString::push`] which can be
+    /// chained.
+    pub fn and<R>(mut self, other: R) -> Self
+    where
+        R: AsRef<BRefStr>,
+    {
+        self.push(other);
+        self
+    }
+
+    pub fn push<R>(&mut self, other: R)
+    where
+        R: AsRef<BRefStr>,
+    {
+        self.0.push(b'/');
+        self.0.extend_from_slice(other.as_ref().as_bytes());
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> bool {
+        match self.0.rfind_byte(b'/') {
+            None => false,
+            Some(idx) => {
+                self.0.truncate(idx);
+                true
+            },
+        }
+    }
+
+    #[inline]
+    pub fn into_bstring(self) -> BString {
+        self.into()
+    }
+
+    #[inline]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Deref for BRefString {
+    type Target = BRefStr;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.borrow()
+    }
+}
+
+impl AsRef<BRefStr> for BRefString {
+    #[inline]
+    fn as_ref(&self) -> &BRefStr {
+        self
+    }
+}
+
+impl AsRef<BStr> for BRefString {
+    #[inline]
+    fn as_ref(&self) -> &BStr {
+        self.as_bstr()
+    }
+}
+
+impl AsRef<[u8]> for BRefString {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Borrow<BRefStr> for BRefString {
+    #[inline]
+    fn borrow(&self) -> &BRefStr {
+        BRefStr::from_bytes(&self.0)
+    }
+}
+
+impl ToOwned for BRefStr {
+    type Owned = BRefString;
+
+    #[inline]
+    fn to_owned(&self) -> Self::Owned {
+        BRefString(self.0.to_owned())
+    }
+}
+
+impl TryFrom<&[u8]> for BRefString {
+    type Error = check::Error;
+
+    #[inline]
+    fn try_from(b: &[u8]) -> Result<Self, Self::Error> {
+        BRefStr::try_from_bytes(b).map(ToOwned::to_owned)
+    }
+}
+
+impl TryFrom<Vec<u8>> for BRefString {
+    type Error = check::Error;
+
+    #[inline]
+    fn try_from(b: Vec<u8>) -> Result<Self, Self::Error> {
+        check::ref_format_bytes(CHECK_OPTS, &b).map(|()| BRefString(b))
+    }
+}
+
+impl TryFrom<BString> for BRefString {
+    type Error = check::Error;
+
+    #[inline]
+    fn try_from(b: BString) -> Result<Self, Self::Error> {
+        Self::try_from(Vec::from(b))
+    }
+}
+
+impl From<RefString> for BRefString {
+    #[inline]
+    fn from(rs: RefString) -> Self {
+        Self(String::from(rs).into_bytes())
+    }
+}
+
+impl TryFrom<BRefString> for RefString {
+    type Error = FromUtf8Error;
+
+    #[inline]
+    fn try_from(rs: BRefString) -> Result<Self, Self::Error> {
+        String::from_utf8(rs.0).map(RefString)
+    }
+}
+
+impl<'a> From<&'a BRefString> for Cow<'a, BRefStr> {
+    #[inline]
+    fn from(rs: &'a BRefString) -> Cow<'a, BRefStr> {
+        Cow::Borrowed(rs.as_bref_str())
+    }
+}
+
+impl<'a> From<BRefString> for Cow<'a, BRefStr> {
+    #[inline]
+    fn from(rs: BRefString) -> Cow<'a, BRefStr> {
+        Cow::Owned(rs)
+    }
+}
+
+impl From<BRefString> for BString {
+    #[inline]
+    fn from(rs: BRefString) -> Self {
+        BString::from(rs.0)
+    }
+}
+
+impl From<BRefString> for Vec<u8> {
+    #[inline]
+    fn from(rs: BRefString) -> Self {
+        rs.0
+    }
+}
+
+impl fmt::Debug for BRefString {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_bref_str(), f)
+    }
+}
+
+/// Lossy, see [`BStr`].
+impl Display for BRefString {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self.as_bref_str(), f)
+    }
+}
+
+impl<A> FromIterator<A> for BRefString
+where
+    A: AsRef<BRefStr>,
+{
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = A>,
+    {
+        let mut buf = Vec::new();
+        for c in iter {
+            buf.extend_from_slice(c.as_ref().as_bytes());
+            buf.push(b'/');
+        }
+        assert!(!buf.is_empty(), "empty iterator");
+        buf.truncate(buf.len() - 1);
+
+        Self(buf)
+    }
+}
+
+impl<A> Extend<A> for BRefString
+where
+    A: AsRef<BRefStr>,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for x in iter {
+            self.push(x)
+        }
+    }
+}
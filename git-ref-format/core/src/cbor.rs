@@ -15,7 +15,7 @@ use minicbor::{
 };
 
 use crate::{
-    refspec::{PatternStr, PatternString},
+    refspec::{PatternStr, PatternString, Refspec},
     Namespaced,
     Qualified,
     RefStr,
@@ -115,3 +115,19 @@ impl<'a> Encode for Namespaced<'a> {
         self.as_str().encode(e)
     }
 }
+
+impl<'de> Decode<'de> for Refspec {
+    #[inline]
+    fn decode(d: &mut Decoder<'de>) -> Result<Self, decode::Error> {
+        d.str()
+            .and_then(|s| Self::try_from(s).map_err(|e| decode::Error::Custom(Box::new(e))))
+    }
+}
+
+impl Encode for Refspec {
+    #[inline]
+    fn encode<W: Write>(&self, e: &mut Encoder<W>) -> Result<(), encode::Error<W::Error>> {
+        e.str(&self.to_string())?;
+        Ok(())
+    }
+}
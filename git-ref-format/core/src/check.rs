@@ -3,6 +3,8 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use std::iter;
+
 use thiserror::Error;
 
 pub struct Options {
@@ -44,46 +46,56 @@ pub enum Error {
 }
 
 /// Validate that a string slice is a valid refname.
+#[inline]
 pub fn ref_format(opts: Options, s: &str) -> Result<(), Error> {
+    ref_format_bytes(opts, s.as_bytes())
+}
+
+/// Validate that a byte slice is a valid refname.
+///
+/// Like `git`, this does not require the input to be valid UTF-8: all
+/// restrictions apply to ASCII characters only.
+pub fn ref_format_bytes(opts: Options, s: &[u8]) -> Result<(), Error> {
     match s {
-        "" => Err(Error::Empty),
-        "@" => Err(Error::LoneAt),
-        "." => Err(Error::StartsDot),
+        b"" => Err(Error::Empty),
+        b"@" => Err(Error::LoneAt),
+        b"." => Err(Error::StartsDot),
         _ => {
             let mut globs = 0usize;
             let mut parts = 0usize;
 
-            for x in s.split('/') {
+            for x in s.split(|b| *b == b'/') {
                 if x.is_empty() {
                     return Err(Error::Slash);
                 }
 
                 parts += 1;
 
-                if x.ends_with(".lock") {
+                if x.ends_with(b".lock") {
                     return Err(Error::DotLock);
                 }
 
-                let last_char = x.chars().count() - 1;
-                for (i, y) in x.chars().zip(x.chars().cycle().skip(1)).enumerate() {
+                let last_byte = x.len() - 1;
+                let next = x.iter().copied().skip(1).map(Some).chain(iter::once(None));
+                for (i, y) in x.iter().copied().zip(next).enumerate() {
                     match y {
-                        ('.', '.') => return Err(Error::DotDot),
-                        ('@', '{') => return Err(Error::AtOpenBrace),
+                        (b'.', Some(b'.')) => return Err(Error::DotDot),
+                        (b'@', Some(b'{')) => return Err(Error::AtOpenBrace),
 
-                        ('\0', _) => return Err(Error::InvalidChar('\0')),
-                        ('\\', _) => return Err(Error::InvalidChar('\\')),
-                        ('~', _) => return Err(Error::InvalidChar('~')),
-                        ('^', _) => return Err(Error::InvalidChar('^')),
-                        (':', _) => return Err(Error::InvalidChar(':')),
-                        ('?', _) => return Err(Error::InvalidChar('?')),
-                        ('[', _) => return Err(Error::InvalidChar('[')),
+                        (b'\0', _) => return Err(Error::InvalidChar('\0')),
+                        (b'\\', _) => return Err(Error::InvalidChar('\\')),
+                        (b'~', _) => return Err(Error::InvalidChar('~')),
+                        (b'^', _) => return Err(Error::InvalidChar('^')),
+                        (b':', _) => return Err(Error::InvalidChar(':')),
+                        (b'?', _) => return Err(Error::InvalidChar('?')),
+                        (b'[', _) => return Err(Error::InvalidChar('[')),
 
-                        ('*', _) => globs += 1,
+                        (b'*', _) => globs += 1,
 
-                        ('.', _) if i == 0 => return Err(Error::StartsDot),
-                        ('.', _) if i == last_char => return Err(Error::EndsDot),
+                        (b'.', _) if i == 0 => return Err(Error::StartsDot),
+                        (b'.', _) if i == last_byte => return Err(Error::EndsDot),
 
-                        (' ', _) => return Err(Error::Space),
+                        (b' ', _) => return Err(Error::Space),
 
                         (z, _) if z.is_ascii_control() => return Err(Error::Control),
 
@@ -104,3 +116,59 @@ pub fn ref_format(opts: Options, s: &str) -> Result<(), Error> {
         },
     }
 }
+
+/// Normalize a refname like `git check-ref-format --normalize` does, and
+/// validate the result.
+///
+/// Normalization removes leading and trailing slashes, and collapses
+/// consecutive slashes into one.
+pub fn normalize(opts: Options, s: &str) -> Result<String, Error> {
+    let normalized = s
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    ref_format(opts, &normalized)?;
+    Ok(normalized)
+}
+
+/// Implementation of [`crate::RefString::sanitize`].
+///
+/// The result passes [`ref_format`] with `allow_onelevel: true` and
+/// `allow_pattern: false`.
+pub(crate) fn sanitize(s: &str) -> Result<String, Error> {
+    const REPLACEMENT: char = '-';
+
+    let forbidden = |c: char| {
+        c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '[' | '\\' | '*')
+    };
+
+    let mut components = Vec::new();
+    for x in s.split('/') {
+        let mut x = x
+            .chars()
+            .map(|c| if forbidden(c) { REPLACEMENT } else { c })
+            .collect::<String>();
+        while x.contains("..") {
+            x = x.replace("..", ".");
+        }
+        x = x.replace("@{", "-{");
+        loop {
+            let trimmed = x.trim_matches('.');
+            let trimmed = trimmed.strip_suffix(".lock").unwrap_or(trimmed);
+            if trimmed.len() == x.len() {
+                break;
+            }
+            x = trimmed.to_owned();
+        }
+        if !x.is_empty() {
+            components.push(x);
+        }
+    }
+
+    match components.join("/") {
+        s if s.is_empty() => Err(Error::Empty),
+        s if s == "@" => Ok(REPLACEMENT.to_string()),
+        s => Ok(s),
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Classification of ref names according to the layout used by Radicle.
+//!
+//! A Radicle monorepo stores the refs of each project under a [`gitnamespace`],
+//! the refs of tracked peers under `refs/remotes/<peer>`, and some metadata
+//! in dedicated hierarchies. For example:
+//!
+//! ```text
+//! refs/namespaces/<urn>/refs/heads/main
+//! refs/namespaces/<urn>/refs/rad/id
+//! refs/namespaces/<urn>/refs/remotes/<peer>/heads/main
+//! refs/namespaces/<urn>/refs/remotes/<peer>/cobs/<type>/<id>
+//! ```
+//!
+//! [`Layout::classify`] turns any [`RefStr`] into a [`Layout`], which can be
+//! turned back into the same [`RefString`] using [`Layout::to_refstring`].
+//!
+//! [`gitnamespace`]: https://git-scm.com/docs/gitnamespaces
+
+use std::fmt::{self, Display};
+
+use crate::{
+    name::{self, str},
+    Component,
+    RefStr,
+    RefString,
+};
+
+/// The structure of a ref name.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Layout<'a> {
+    /// `refs/namespaces/<namespace>/<inner>`. Namespaces may be nested, and
+    /// `inner` may be [`Layout::Other`], eg. for the namespace's `HEAD`.
+    Namespaced {
+        namespace: Component<'a>,
+        inner: Box<Layout<'a>>,
+    },
+    /// `refs/remotes/<peer>/<category>`
+    Remote {
+        peer: Component<'a>,
+        category: Category<'a>,
+    },
+    /// `refs/<category>`
+    Local(Category<'a>),
+    /// Anything not starting with "refs/", eg. `HEAD`.
+    Other(&'a RefStr),
+}
+
+impl<'a> Layout<'a> {
+    /// Classify the ref `r`.
+    ///
+    /// This never fails: refs which don't follow the layout in some part are
+    /// represented by the [`Layout::Other`] or [`Category::Other`] variants.
+    pub fn classify(r: &'a RefStr) -> Self {
+        Self::refs(r).unwrap_or(Self::Other(r))
+    }
+
+    fn refs(r: &'a RefStr) -> Option<Self> {
+        let rest = refstr(r.as_str().strip_prefix("refs/")?)?;
+        let classified = match split(rest) {
+            (str::NAMESPACES, Some(tail)) => match split(tail) {
+                (namespace, Some(inner)) => {
+                    component(namespace).map(|namespace| Self::Namespaced {
+                        namespace,
+                        inner: Box::new(Self::classify(inner)),
+                    })
+                },
+                _ => None,
+            },
+            (str::REMOTES, Some(tail)) => match split(tail) {
+                (peer, Some(category)) => component(peer).map(|peer| Self::Remote {
+                    peer,
+                    category: Category::classify(category),
+                }),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        Some(classified.unwrap_or_else(|| Self::Local(Category::classify(rest))))
+    }
+
+    /// The outermost namespace, if any.
+    pub fn namespace(&self) -> Option<&Component<'a>> {
+        match self {
+            Self::Namespaced { namespace, .. } => Some(namespace),
+            _ => None,
+        }
+    }
+
+    /// The layout with all namespaces removed.
+    pub fn strip_namespaces(&self) -> &Self {
+        match self {
+            Self::Namespaced { inner, .. } => inner.strip_namespaces(),
+            _ => self,
+        }
+    }
+
+    /// The remote peer, if this is (a possibly namespaced) remote ref.
+    pub fn peer(&self) -> Option<&Component<'a>> {
+        match self.strip_namespaces() {
+            Self::Remote { peer, .. } => Some(peer),
+            _ => None,
+        }
+    }
+
+    /// The category, if this is (a possibly namespaced) local or remote ref.
+    pub fn category(&self) -> Option<&Category<'a>> {
+        match self.strip_namespaces() {
+            Self::Remote { category, .. } | Self::Local(category) => Some(category),
+            _ => None,
+        }
+    }
+
+    pub fn to_refstring(&self) -> RefString {
+        match self {
+            Self::Namespaced { namespace, inner } => name::REFS
+                .join(name::NAMESPACES)
+                .and(namespace)
+                .and(inner.to_refstring()),
+            Self::Remote { peer, category } => name::REFS
+                .join(name::REMOTES)
+                .and(peer)
+                .and(category.to_refstring()),
+            Self::Local(category) => name::REFS.join(category.to_refstring()),
+            Self::Other(r) => r.to_ref_string(),
+        }
+    }
+}
+
+impl<'a> From<&'a RefStr> for Layout<'a> {
+    #[inline]
+    fn from(r: &'a RefStr) -> Self {
+        Self::classify(r)
+    }
+}
+
+impl From<&Layout<'_>> for RefString {
+    #[inline]
+    fn from(layout: &Layout) -> Self {
+        layout.to_refstring()
+    }
+}
+
+impl From<Layout<'_>> for RefString {
+    #[inline]
+    fn from(layout: Layout) -> Self {
+        layout.to_refstring()
+    }
+}
+
+impl Display for Layout<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_refstring())
+    }
+}
+
+/// The category of a local or remote ref, and the remaining name.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Category<'a> {
+    /// `heads/<name>`
+    Heads(&'a RefStr),
+    /// `tags/<name>`
+    Tags(&'a RefStr),
+    /// `notes/<name>`
+    Notes(&'a RefStr),
+    /// `rad/id`
+    RadId,
+    /// `rad/self`
+    RadSelf,
+    /// `rad/signed_refs`
+    RadSignedRefs,
+    /// `rad/ids/<id>`
+    RadIds(Component<'a>),
+    /// `cobs/<typename>/<id>`
+    Cobs {
+        typename: Component<'a>,
+        id: Component<'a>,
+    },
+    /// Any other category, including its name.
+    Other(&'a RefStr),
+}
+
+impl<'a> Category<'a> {
+    /// Classify `r`, which is the part of a ref following `refs/` or
+    /// `refs/remotes/<peer>/`.
+    pub fn classify(r: &'a RefStr) -> Self {
+        Self::known(r).unwrap_or(Self::Other(r))
+    }
+
+    fn known(r: &'a RefStr) -> Option<Self> {
+        match split(r) {
+            (str::HEADS, Some(name)) => Some(Self::Heads(name)),
+            (str::TAGS, Some(name)) => Some(Self::Tags(name)),
+            (str::NOTES, Some(name)) => Some(Self::Notes(name)),
+            (str::RAD, Some(name)) => match split(name) {
+                (str::ID, None) => Some(Self::RadId),
+                (str::SELF, None) => Some(Self::RadSelf),
+                (str::SIGNED_REFS, None) => Some(Self::RadSignedRefs),
+                (str::IDS, Some(id)) => Option::from(id).map(Self::RadIds),
+                _ => None,
+            },
+            (str::COBS, Some(name)) => match split(name) {
+                (typename, Some(id)) => component(typename)
+                    .zip(Option::from(id))
+                    .map(|(typename, id)| Self::Cobs { typename, id }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn to_refstring(&self) -> RefString {
+        match self {
+            Self::Heads(name) => name::HEADS.join(name),
+            Self::Tags(name) => name::TAGS.join(name),
+            Self::Notes(name) => name::NOTES.join(name),
+            Self::RadId => name::RAD.join(name::ID),
+            Self::RadSelf => name::RAD.join(name::SELF),
+            Self::RadSignedRefs => name::RAD.join(name::SIGNED_REFS),
+            Self::RadIds(id) => name::RAD.join(name::IDS).and(id),
+            Self::Cobs { typename, id } => name::COBS.join(typename).and(id),
+            Self::Other(r) => r.to_ref_string(),
+        }
+    }
+}
+
+impl Display for Category<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_refstring())
+    }
+}
+
+/// Split off the first component of `r`.
+///
+/// The remainder is `None` if there is none, or if it is not a valid
+/// [`RefStr`] on its own (eg. "@").
+fn split(r: &RefStr) -> (&str, Option<&RefStr>) {
+    match r.as_str().split_once('/') {
+        None => (r.as_str(), None),
+        Some((head, tail)) => (head, refstr(tail)),
+    }
+}
+
+fn refstr(s: &str) -> Option<&RefStr> {
+    RefStr::try_from_str(s).ok()
+}
+
+fn component(s: &str) -> Option<Component<'_>> {
+    refstr(s).and_then(Option::from)
+}
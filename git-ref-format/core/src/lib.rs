@@ -3,12 +3,26 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+#[cfg(feature = "bstr")]
+mod bytes;
+#[cfg(feature = "bstr")]
+pub use bytes::{BRefStr, BRefString};
+
 mod check;
-pub use check::{ref_format as check_ref_format, Error, Options};
+pub use check::{
+    normalize as normalize_ref_format,
+    ref_format as check_ref_format,
+    ref_format_bytes as check_ref_format_bytes,
+    Error,
+    Options,
+};
 
 mod deriv;
 pub use deriv::{Namespaced, Qualified};
 
+#[cfg(feature = "link-literals")]
+pub mod layout;
+
 pub mod lit;
 
 pub mod name;
@@ -19,6 +33,8 @@ pub use name::{Component, RefStr, RefString};
 pub mod refspec;
 pub use refspec::DuplicateGlob;
 
+pub mod rev;
+
 #[cfg(feature = "minicbor")]
 mod cbor;
 #[cfg(feature = "serde")]
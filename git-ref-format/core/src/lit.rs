@@ -10,8 +10,10 @@ use crate::{name, Qualified, RefStr};
 /// Types implementing [`Lit`] must be [`name::Component`]s, and provide a
 /// conversion from a component _iff_ the component's [`RefStr`] representation
 /// is equal to [`Lit::NAME`]. Because these morphisms can only be guaranteed
-/// axiomatically, the trait can not currently be implemented by types outside
-/// of this crate.
+/// axiomatically, the trait can not be implemented directly by types outside
+/// of this crate. Instead, the `RefLit` derive macro and the `lit!` macro
+/// (enabled by the `"macro"` feature) generate implementations whose
+/// [`Lit::NAME`] is validated at compile time.
 ///
 /// [`Lit`] types are useful for efficiently creating known-valid [`Qualified`]
 /// refs, and sometimes for pattern matching.
@@ -36,6 +38,22 @@ mod sealed {
     pub trait Sealed {}
 }
 
+/// Support for the `RefLit` derive macro. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    pub use super::sealed::Sealed;
+
+    /// # Safety
+    ///
+    /// `s` must be a valid [`name::Component`].
+    #[inline]
+    pub const unsafe fn component_unchecked(s: &'static str) -> &'static RefStr {
+        RefStr::from_str(s)
+    }
+}
+
 /// All known literal [`RefStr`]s.
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
 pub enum KnownLit {
@@ -297,9 +297,20 @@ impl Display for RefStr {
 }
 
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
-pub struct RefString(String);
+pub struct RefString(pub(crate) String);
 
 impl RefString {
+    /// Turn arbitrary input, eg. a branch name typed by a user, into a valid
+    /// [`RefString`].
+    ///
+    /// Forbidden characters are replaced by `-`, runs of dots are collapsed,
+    /// and empty components, leading and trailing dots, and `.lock` suffixes
+    /// are removed. The only error returned is [`check::Error::Empty`], if
+    /// nothing is left of the input.
+    pub fn sanitize(s: &str) -> Result<Self, check::Error> {
+        check::sanitize(s).map(Self)
+    }
+
     #[inline]
     pub fn as_refstr(&self) -> &RefStr {
         self
@@ -18,6 +18,9 @@ use crate::{check, RefStr, RefString};
 mod iter;
 pub use iter::{Component, Components, Iter};
 
+mod spec;
+pub use spec::{fetch_map, MapError, Mapping, ParseError, Refspec};
+
 pub const STAR: &PatternStr = PatternStr::from_str("*");
 
 const CHECK_OPTS: check::Options = check::Options {
@@ -53,6 +56,42 @@ impl PatternStr {
         buf
     }
 
+    /// Match `name` against this pattern, as `git` does for the source side
+    /// of a refspec.
+    ///
+    /// The `*`, if any, matches any sequence of characters, including the
+    /// empty sequence and `/` separators. Eg. `refs/heads/feature-*` matches
+    /// both `refs/heads/feature-a` and `refs/heads/feature-a/b`, capturing
+    /// `a` and `a/b` respectively. A pattern without a `*` matches only a
+    /// ref string equal to it, with an empty [`Capture`].
+    pub fn matches<'a>(&self, name: &'a RefStr) -> Option<Capture<'a>> {
+        let name = name.as_str();
+        match self.0.split_once('*') {
+            None if &self.0 == name => Some(Capture("")),
+            None => None,
+            Some((prefix, suffix)) => name
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))
+                .map(Capture),
+        }
+    }
+
+    /// Substitute the `*` of this pattern by `capture`, as `git` does for the
+    /// destination side of a refspec.
+    ///
+    /// A pattern without a `*` is returned as a [`RefString`] verbatim.
+    ///
+    /// # Errors
+    ///
+    /// The substitution can yield an invalid ref string even though both the
+    /// pattern and the name the capture was obtained from are valid. Eg.
+    /// capturing `.x` from `refs/heads/a.x` via `refs/heads/a*`, and
+    /// substituting it into `refs/remotes/origin/*` would start a component
+    /// with a `.`.
+    pub fn expand(&self, capture: &Capture) -> Result<RefString, check::Error> {
+        RefString::try_from(self.0.replacen('*', capture.as_str(), 1))
+    }
+
     #[inline]
     pub fn iter(&self) -> Iter {
         self.0.split('/')
@@ -255,6 +294,32 @@ impl TryFrom<String> for PatternString {
     }
 }
 
+/// The part of a ref string matched by the `*` of a pattern, obtained via
+/// [`PatternStr::matches`].
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub struct Capture<'a>(&'a str);
+
+impl<'a> Capture<'a> {
+    #[inline]
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl AsRef<str> for Capture<'_> {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl Display for Capture<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("more than one '*' encountered")]
 pub struct DuplicateGlob;
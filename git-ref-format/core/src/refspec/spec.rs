@@ -0,0 +1,337 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use super::{PatternStr, PatternString};
+use crate::{check, RefStr, RefString};
+
+/// The rules by which a refspec source without a `*` is matched against ref
+/// names, in order of precedence. Same as `git rev-parse` uses.
+const DWIM_RULES: &[(&str, &str)] = &[
+    ("", ""),
+    ("refs/", ""),
+    ("refs/tags/", ""),
+    ("refs/heads/", ""),
+    ("refs/remotes/", ""),
+    ("refs/remotes/", "/HEAD"),
+];
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("empty refspec")]
+    Empty,
+    #[error("invalid source")]
+    Src(#[source] check::Error),
+    #[error("invalid destination")]
+    Dst(#[source] check::Error),
+    #[error("negative refspecs can not be forced")]
+    ForcedNegative,
+    #[error("negative refspecs can not have a destination")]
+    NegativeDst,
+    #[error("source and destination must either both be patterns, or neither")]
+    PatternMismatch,
+}
+
+/// A refspec, as understood by [`git-fetch`] and [`git-push`].
+///
+/// Parsed from one of the forms:
+///
+/// * `[+]<src>[:<dst>]`
+/// * `[+]:<dst>`, deleting `<dst>` when pushing
+/// * `^<src>`, excluding the refs matching `<src>`
+///
+/// `<src>` and `<dst>` are [`PatternStr`]s, where either both or neither
+/// contain a `*`.
+///
+/// [`git-fetch`]: https://git-scm.com/docs/git-fetch
+/// [`git-push`]: https://git-scm.com/docs/git-push
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub enum Refspec {
+    /// Map refs matching `src` to `dst`. If `dst` is `None`, the matching
+    /// refs are fetched, but not stored under any name.
+    Map {
+        force: bool,
+        src: PatternString,
+        dst: Option<PatternString>,
+    },
+    /// Delete `dst` on the remote.
+    Delete { force: bool, dst: PatternString },
+    /// Exclude refs matching `src`.
+    Negative { src: PatternString },
+}
+
+impl Refspec {
+    /// `true` if the refspec is prefixed with a `+`, ie. allows
+    /// non-fast-forward updates.
+    pub fn is_force(&self) -> bool {
+        match self {
+            Self::Map { force, .. } | Self::Delete { force, .. } => *force,
+            Self::Negative { .. } => false,
+        }
+    }
+
+    /// The source pattern, if any.
+    pub fn src(&self) -> Option<&PatternStr> {
+        match self {
+            Self::Map { src, .. } | Self::Negative { src } => Some(src),
+            Self::Delete { .. } => None,
+        }
+    }
+
+    /// The destination pattern, if any.
+    pub fn dst(&self) -> Option<&PatternStr> {
+        match self {
+            Self::Map { dst, .. } => dst.as_deref(),
+            Self::Delete { dst, .. } => Some(dst),
+            Self::Negative { .. } => None,
+        }
+    }
+
+    /// Match the ref `name` against the source of this refspec.
+    ///
+    /// Unlike [`PatternStr::matches`], a source without a `*` also matches
+    /// `name` if it is an abbreviation of it (eg. `main` matches
+    /// `refs/heads/main`). See [`fetch_map`] for how ambiguities are
+    /// resolved.
+    fn src_rule(&self, name: &RefStr) -> Option<usize> {
+        let src = self.src()?.as_str();
+        let name = name.as_str();
+        DWIM_RULES.iter().position(|(prefix, suffix)| {
+            name.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))
+                .map(|rest| rest == src)
+                .unwrap_or(false)
+        })
+    }
+}
+
+impl FromStr for Refspec {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = |s: &str| PatternString::try_from(s);
+
+        let (force, spec) = match s.strip_prefix('+') {
+            Some(spec) => (true, spec),
+            None => (false, s),
+        };
+        if let Some(src) = spec.strip_prefix('^') {
+            if force {
+                return Err(ParseError::ForcedNegative);
+            }
+            if src.contains(':') {
+                return Err(ParseError::NegativeDst);
+            }
+            let src = pattern(src).map_err(ParseError::Src)?;
+            return Ok(Self::Negative { src });
+        }
+
+        match spec.split_once(':') {
+            None if spec.is_empty() => Err(ParseError::Empty),
+            None => Ok(Self::Map {
+                force,
+                src: pattern(spec).map_err(ParseError::Src)?,
+                dst: None,
+            }),
+            Some(("", "")) => Err(ParseError::Empty),
+            Some(("", dst)) => Ok(Self::Delete {
+                force,
+                dst: pattern(dst).map_err(ParseError::Dst)?,
+            }),
+            Some((src, dst)) => {
+                let src = pattern(src).map_err(ParseError::Src)?;
+                // `<src>:` is the same as `<src>`
+                let dst = if dst.is_empty() {
+                    None
+                } else {
+                    let dst = pattern(dst).map_err(ParseError::Dst)?;
+                    if src.contains('*') != dst.contains('*') {
+                        return Err(ParseError::PatternMismatch);
+                    }
+                    Some(dst)
+                };
+                Ok(Self::Map { force, src, dst })
+            },
+        }
+    }
+}
+
+impl TryFrom<&str> for Refspec {
+    type Error = ParseError;
+
+    #[inline]
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Refspec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_force() {
+            f.write_str("+")?;
+        }
+        match self {
+            Self::Map { src, dst, .. } => {
+                f.write_str(src)?;
+                if let Some(dst) = dst {
+                    write!(f, ":{}", dst)?;
+                }
+                Ok(())
+            },
+            Self::Delete { dst, .. } => write!(f, ":{}", dst),
+            Self::Negative { src } => write!(f, "^{}", src),
+        }
+    }
+}
+
+/// A ref selected by [`fetch_map`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mapping<'a> {
+    /// The ref name on the remote.
+    pub src: &'a RefStr,
+    /// The local ref name to store `src` under, if any.
+    pub dst: Option<RefString>,
+    /// `true` if `dst` may be updated in a non-fast-forward way.
+    pub force: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum MapError {
+    #[error("refspec {spec} maps {src} to an invalid ref name")]
+    Expand {
+        spec: Refspec,
+        src: RefString,
+        #[source]
+        source: check::Error,
+    },
+    #[error("both {a} and {b} map to {dst}")]
+    Conflict {
+        dst: RefString,
+        a: RefString,
+        b: RefString,
+    },
+}
+
+/// Select the refs to fetch from the remote refs `refs`, as per `specs`.
+///
+/// Like [`git-fetch`], this:
+///
+/// * matches patterns using [`PatternStr::matches`]
+/// * matches a source without a `*` to the ref with the highest precedence of
+///   which it is an abbreviation (eg. `main` prefers `refs/tags/main` over
+///   `refs/heads/main`), or to no ref at all
+/// * removes the refs matching any [`Refspec::Negative`] source
+/// * collapses mappings of the same `src` to the same `dst`, which are forced
+///   if any of them is
+///
+/// It is an error for different `src` refs to map to the same `dst`, or for a
+/// `src` to map to an invalid ref name (eg. a `dst` pattern for a `src` which
+/// is not a pattern).
+/// [`Refspec::Delete`] refspecs only apply to pushes, and are ignored.
+///
+/// The result is ordered by refspec, and then in the order of `refs`.
+///
+/// [`git-fetch`]: https://git-scm.com/docs/git-fetch
+pub fn fetch_map<'a, I>(specs: &[Refspec], refs: I) -> Result<Vec<Mapping<'a>>, MapError>
+where
+    I: IntoIterator<Item = &'a RefStr>,
+{
+    let refs = refs.into_iter().collect::<Vec<_>>();
+    let excluded = |name: &RefStr| {
+        specs.iter().any(|spec| match spec {
+            Refspec::Negative { src } if src.contains('*') => src.matches(name).is_some(),
+            Refspec::Negative { .. } => spec.src_rule(name).is_some(),
+            Refspec::Map { .. } | Refspec::Delete { .. } => false,
+        })
+    };
+
+    let mut mappings: Vec<Mapping<'a>> = Vec::new();
+    let mut by_dst: BTreeMap<RefString, usize> = BTreeMap::new();
+    for spec in specs {
+        let (force, src, dst) = match spec {
+            Refspec::Map { force, src, dst } => (*force, src, dst),
+            Refspec::Delete { .. } | Refspec::Negative { .. } => continue,
+        };
+
+        let matched = if src.contains('*') {
+            refs.iter()
+                .filter_map(|name| src.matches(name).map(|capture| (*name, Some(capture))))
+                .collect::<Vec<_>>()
+        } else {
+            refs.iter()
+                .filter_map(|name| spec.src_rule(name).map(|rule| (rule, *name)))
+                .min_by_key(|(rule, _)| *rule)
+                .map(|(_, name)| (name, None))
+                .into_iter()
+                .collect()
+        };
+
+        for (name, capture) in matched {
+            if excluded(name) {
+                continue;
+            }
+            let dst = match (dst, capture) {
+                (None, _) => None,
+                (Some(dst), Some(capture)) => {
+                    Some(dst.expand(&capture).map_err(|source| MapError::Expand {
+                        spec: spec.clone(),
+                        src: name.to_owned(),
+                        source,
+                    })?)
+                },
+                // `dst` may contain a `*` if `src` doesn't, as the fields of
+                // `Refspec::Map` are public
+                (Some(dst), None) => Some(
+                    RefStr::try_from_str(dst)
+                        .map_err(|source| MapError::Expand {
+                            spec: spec.clone(),
+                            src: name.to_owned(),
+                            source,
+                        })?
+                        .to_owned(),
+                ),
+            };
+
+            let mapping = Mapping {
+                src: name,
+                dst,
+                force,
+            };
+            let existing = match &mapping.dst {
+                Some(dst) => by_dst.get(dst).copied(),
+                None => mappings
+                    .iter()
+                    .position(|m| m.dst.is_none() && m.src == name),
+            };
+            match existing {
+                None => {
+                    if let Some(dst) = &mapping.dst {
+                        by_dst.insert(dst.clone(), mappings.len());
+                    }
+                    mappings.push(mapping);
+                },
+                Some(i) if mappings[i].src == name => mappings[i].force |= force,
+                Some(i) => {
+                    return Err(MapError::Conflict {
+                        dst: mapping.dst.expect("conflicts have a destination"),
+                        a: mappings[i].src.to_owned(),
+                        b: name.to_owned(),
+                    })
+                },
+            }
+        }
+    }
+
+    Ok(mappings)
+}
@@ -0,0 +1,313 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Revision expressions, as described in [`gitrevisions`].
+//!
+//! An [`Expr`] is parsed from a string using [`FromStr`], and can be resolved
+//! against a repository by implementing [`Resolve`].
+//!
+//! [`gitrevisions`]: https://git-scm.com/docs/gitrevisions
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::{RefStr, RefString};
+
+mod parse;
+pub use parse::ParseError;
+
+/// A revision expression.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Expr<R = Rev> {
+    /// A single revision.
+    Rev(R),
+    /// `^<rev>`: exclude the commits reachable from `<rev>`.
+    Exclude(R),
+    /// `<from>..<to>`: the commits reachable from `to`, but not from `from`.
+    ///
+    /// An omitted side defaults to `HEAD`.
+    Range { from: R, to: R },
+    /// `<left>...<right>`: the commits reachable from either `left` or
+    /// `right`, but not from both.
+    ///
+    /// An omitted side defaults to `HEAD`.
+    SymmetricDifference { left: R, right: R },
+    /// `<rev>^@`: all parents of `<rev>`.
+    Parents(R),
+    /// `<rev>^!`: `<rev>`, excluding all of its parents.
+    Only(R),
+    /// `<rev>^-<n>`: `<rev>`, excluding its `n`th parent.
+    ExcludeParent(R, usize),
+}
+
+impl<R> Expr<R> {
+    /// Apply `f` to all revisions in the expression.
+    pub fn try_map<S, E, F>(self, mut f: F) -> Result<Expr<S>, E>
+    where
+        F: FnMut(R) -> Result<S, E>,
+    {
+        Ok(match self {
+            Self::Rev(r) => Expr::Rev(f(r)?),
+            Self::Exclude(r) => Expr::Exclude(f(r)?),
+            Self::Range { from, to } => Expr::Range {
+                from: f(from)?,
+                to: f(to)?,
+            },
+            Self::SymmetricDifference { left, right } => Expr::SymmetricDifference {
+                left: f(left)?,
+                right: f(right)?,
+            },
+            Self::Parents(r) => Expr::Parents(f(r)?),
+            Self::Only(r) => Expr::Only(f(r)?),
+            Self::ExcludeParent(r, n) => Expr::ExcludeParent(f(r)?, n),
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse::expr(s)
+    }
+}
+
+impl<R: Display> Display for Expr<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rev(r) => write!(f, "{}", r),
+            Self::Exclude(r) => write!(f, "^{}", r),
+            Self::Range { from, to } => write!(f, "{}..{}", from, to),
+            Self::SymmetricDifference { left, right } => write!(f, "{}...{}", left, right),
+            Self::Parents(r) => write!(f, "{}^@", r),
+            Self::Only(r) => write!(f, "{}^!", r),
+            Self::ExcludeParent(r, n) => write!(f, "{}^-{}", r, n),
+        }
+    }
+}
+
+/// A single revision.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Rev {
+    pub base: Base,
+    /// `@{...}` following the base.
+    pub reflog: Option<Reflog>,
+    pub ops: Vec<Op>,
+    /// `:<path>`: the blob or tree at `path` in the tree of the revision.
+    pub path: Option<String>,
+}
+
+impl Rev {
+    /// `HEAD`
+    pub fn head() -> Self {
+        Self::from(Base::Name(RefStr::from_str("HEAD").to_owned()))
+    }
+}
+
+impl From<Base> for Rev {
+    fn from(base: Base) -> Self {
+        Self {
+            base,
+            reflog: None,
+            ops: Vec::new(),
+            path: None,
+        }
+    }
+}
+
+impl FromStr for Rev {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse::rev(s)
+    }
+}
+
+impl Display for Rev {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.base)?;
+        if let Some(reflog) = &self.reflog {
+            write!(f, "@{{{}}}", reflog)?;
+        }
+        for op in &self.ops {
+            write!(f, "{}", op)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, ":{}", path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The starting point of a [`Rev`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Base {
+    /// A ref name (eg. `HEAD`, `main`, `origin/main`, `refs/tags/v1`), or a
+    /// (possibly abbreviated) object id. `@` is parsed as `HEAD`.
+    Name(RefString),
+    /// Nothing before `@{...}`, which refers to the current branch.
+    CurrentBranch,
+    /// `@{-<n>}`: the `n`th branch checked out before the current one.
+    PreviousCheckout(usize),
+    /// `:/<regex>`: the youngest commit reachable from any ref whose message
+    /// matches `regex`.
+    Search(String),
+    /// `:[<stage>:]<path>`: the blob at `path` in the index.
+    Index { stage: u8, path: String },
+}
+
+impl Display for Base {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Name(name) => f.write_str(name),
+            Self::CurrentBranch => Ok(()),
+            Self::PreviousCheckout(n) => write!(f, "@{{-{}}}", n),
+            Self::Search(regex) => write!(f, ":/{}", regex),
+            Self::Index { stage: 0, path } => write!(f, ":{}", path),
+            Self::Index { stage, path } => write!(f, ":{}:{}", stage, path),
+        }
+    }
+}
+
+/// The `@{...}` suffix of a ref.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Reflog {
+    /// `@{<n>}`: the `n`th prior value of the ref.
+    Entry(usize),
+    /// `@{<date>}`: the value of the ref at the given point in time, in any
+    /// format `git` understands.
+    Date(String),
+    /// `@{upstream}` or `@{u}`
+    Upstream,
+    /// `@{push}`
+    Push,
+}
+
+impl Display for Reflog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Entry(n) => write!(f, "{}", n),
+            Self::Date(date) => f.write_str(date),
+            Self::Upstream => f.write_str("upstream"),
+            Self::Push => f.write_str("push"),
+        }
+    }
+}
+
+/// A suffix operation on a revision.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Op {
+    /// `^<n>`: the `n`th parent. `^0` is the commit itself.
+    Parent(usize),
+    /// `~<n>`: the `n`th generation ancestor, following first parents.
+    Ancestor(usize),
+    /// `^{<kind>}`
+    Peel(Peel),
+    /// `^{/<regex>}`: the youngest commit reachable from the revision whose
+    /// message matches `regex`.
+    Search(String),
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Parent(n) => write!(f, "^{}", n),
+            Self::Ancestor(n) => write!(f, "~{}", n),
+            Self::Peel(peel) => write!(f, "^{{{}}}", peel),
+            Self::Search(regex) => write!(f, "^{{/{}}}", regex),
+        }
+    }
+}
+
+/// What to peel a revision to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Peel {
+    /// `^{}`: peel tags until a non-tag object is found.
+    Tags,
+    /// `^{object}`: the object must exist, but may be of any kind.
+    Object,
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl Peel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tags => "",
+            Self::Object => "object",
+            Self::Commit => "commit",
+            Self::Tree => "tree",
+            Self::Blob => "blob",
+            Self::Tag => "tag",
+        }
+    }
+}
+
+impl Display for Peel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Resolution of revisions against a repository.
+///
+/// Implementors provide the primitive lookups, while [`Resolve::resolve`]
+/// and [`Resolve::resolve_expr`] drive the evaluation of parsed revisions.
+/// Lookups which the backend doesn't support should return an error.
+pub trait Resolve {
+    /// The resolved value, typically an object id.
+    type Output;
+    type Error;
+
+    /// Resolve the [`Base`] of a revision, applying `reflog` if given.
+    fn base(&mut self, base: &Base, reflog: Option<&Reflog>) -> Result<Self::Output, Self::Error>;
+
+    /// The `n`th parent of the commit `rev` points to, peeling tags first.
+    /// `n` is never zero.
+    fn parent(&mut self, rev: Self::Output, n: usize) -> Result<Self::Output, Self::Error>;
+
+    fn peel(&mut self, rev: Self::Output, peel: Peel) -> Result<Self::Output, Self::Error>;
+
+    /// The youngest commit reachable from `rev` whose message matches
+    /// `regex`.
+    fn search(&mut self, rev: Self::Output, regex: &str) -> Result<Self::Output, Self::Error>;
+
+    /// The entry at `path` in the tree `rev` points to, peeling commits and
+    /// tags first.
+    fn path(&mut self, rev: Self::Output, path: &str) -> Result<Self::Output, Self::Error>;
+
+    fn resolve(&mut self, rev: &Rev) -> Result<Self::Output, Self::Error> {
+        let mut out = self.base(&rev.base, rev.reflog.as_ref())?;
+        for op in &rev.ops {
+            out = match op {
+                Op::Parent(0) => self.peel(out, Peel::Commit)?,
+                Op::Parent(n) => self.parent(out, *n)?,
+                Op::Ancestor(n) => {
+                    let mut out = self.peel(out, Peel::Commit)?;
+                    for _ in 0..*n {
+                        out = self.parent(out, 1)?;
+                    }
+                    out
+                },
+                Op::Peel(peel) => self.peel(out, *peel)?,
+                Op::Search(regex) => self.search(out, regex)?,
+            };
+        }
+        match &rev.path {
+            None => Ok(out),
+            Some(path) => self.path(out, path),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) -> Result<Expr<Self::Output>, Self::Error> {
+        expr.clone().try_map(|rev| self.resolve(&rev))
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+use super::{Base, Expr, Op, Peel, Reflog, Rev};
+use crate::{check, RefString};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("empty revision")]
+    Empty,
+    #[error("invalid ref name")]
+    Name(#[source] check::Error),
+    #[error("invalid number {0:?}")]
+    Number(String),
+    #[error("unterminated '{{'")]
+    Unterminated,
+    #[error("unknown object kind {0:?}")]
+    Peel(String),
+    #[error("unexpected {0:?}")]
+    Unexpected(String),
+}
+
+pub(super) fn expr(s: &str) -> Result<Expr, ParseError> {
+    if let Some(rev) = s.strip_prefix('^') {
+        return self::rev(rev).map(Expr::Exclude);
+    }
+
+    let head = &s[..head_len(s)];
+    if let Some(i) = find_dots(head) {
+        let or_head = |s: &str| {
+            if s.is_empty() {
+                Ok(Rev::head())
+            } else {
+                self::rev(s)
+            }
+        };
+        return match s[i..].strip_prefix("...") {
+            Some(right) => Ok(Expr::SymmetricDifference {
+                left: or_head(&s[..i])?,
+                right: or_head(right)?,
+            }),
+            None => Ok(Expr::Range {
+                from: or_head(&s[..i])?,
+                to: or_head(&s[i + 2..])?,
+            }),
+        };
+    }
+
+    // Suffixes which apply to the whole expression can't be followed by a
+    // path
+    if head.len() == s.len() {
+        if let Some(rev) = s.strip_suffix("^@") {
+            return self::rev(rev).map(Expr::Parents);
+        }
+        if let Some(rev) = s.strip_suffix("^!") {
+            return self::rev(rev).map(Expr::Only);
+        }
+        if let Some(i) = s.rfind("^-") {
+            let n = &s[i + 2..];
+            if n.bytes().all(|b| b.is_ascii_digit()) {
+                return Ok(Expr::ExcludeParent(self::rev(&s[..i])?, number(n)?));
+            }
+        }
+    }
+
+    self::rev(s).map(Expr::Rev)
+}
+
+pub(super) fn rev(s: &str) -> Result<Rev, ParseError> {
+    if s.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    if let Some(rest) = s.strip_prefix(':') {
+        if let Some(regex) = rest.strip_prefix('/') {
+            return Ok(Rev::from(Base::Search(regex.to_owned())));
+        }
+        let (stage, path) = match rest.as_bytes() {
+            [stage @ b'0'..=b'3', b':', ..] => (stage - b'0', &rest[2..]),
+            _ => (0, rest),
+        };
+        return Ok(Rev::from(Base::Index {
+            stage,
+            path: path.to_owned(),
+        }));
+    }
+
+    let (name, mut rest) = s.split_at(name_len(s));
+    let (base, reflog) = match rest.strip_prefix("@{") {
+        None => (base(name)?, None),
+        Some(braced) => {
+            let close = braced.find('}').ok_or(ParseError::Unterminated)?;
+            let inner = &braced[..close];
+            rest = &braced[close + 1..];
+            match inner.strip_prefix('-') {
+                Some(n) if name.is_empty() => (Base::PreviousCheckout(number(n)?), None),
+                _ if name.is_empty() => (Base::CurrentBranch, Some(reflog(inner)?)),
+                _ => (base(name)?, Some(reflog(inner)?)),
+            }
+        },
+    };
+
+    let mut ops = Vec::new();
+    let mut path = None;
+    loop {
+        if let Some(braced) = rest.strip_prefix("^{") {
+            let close = braced.find('}').ok_or(ParseError::Unterminated)?;
+            let inner = &braced[..close];
+            rest = &braced[close + 1..];
+            let op = match inner {
+                "" => Op::Peel(Peel::Tags),
+                "object" => Op::Peel(Peel::Object),
+                "commit" => Op::Peel(Peel::Commit),
+                "tree" => Op::Peel(Peel::Tree),
+                "blob" => Op::Peel(Peel::Blob),
+                "tag" => Op::Peel(Peel::Tag),
+                _ => match inner.strip_prefix('/') {
+                    Some(regex) => Op::Search(regex.to_owned()),
+                    None => return Err(ParseError::Peel(inner.to_owned())),
+                },
+            };
+            ops.push(op);
+            continue;
+        }
+
+        let mut chars = rest.chars();
+        let op: fn(usize) -> Op = match chars.next() {
+            None => break,
+            Some('^') => Op::Parent,
+            Some('~') => Op::Ancestor,
+            Some(':') => {
+                path = Some(chars.as_str().to_owned());
+                break;
+            },
+            Some(_) => return Err(ParseError::Unexpected(rest.to_owned())),
+        };
+        let rest1 = chars.as_str();
+        let digits = rest1
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest1.len());
+        ops.push(op(number(&rest1[..digits])?));
+        rest = &rest1[digits..];
+    }
+
+    Ok(Rev {
+        base,
+        reflog,
+        ops,
+        path,
+    })
+}
+
+fn base(name: &str) -> Result<Base, ParseError> {
+    match name {
+        "" => Err(ParseError::Empty),
+        "@" => Ok(Rev::head().base),
+        _ => RefString::try_from(name)
+            .map(Base::Name)
+            .map_err(ParseError::Name),
+    }
+}
+
+fn reflog(inner: &str) -> Result<Reflog, ParseError> {
+    if inner.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if inner.bytes().all(|b| b.is_ascii_digit()) {
+        return number(inner).map(Reflog::Entry);
+    }
+    match inner.to_ascii_lowercase().as_str() {
+        "u" | "upstream" => Ok(Reflog::Upstream),
+        "push" => Ok(Reflog::Push),
+        _ => Ok(Reflog::Date(inner.to_owned())),
+    }
+}
+
+/// Parse a decimal number, where the empty string means `1`.
+fn number(s: &str) -> Result<usize, ParseError> {
+    if s.is_empty() {
+        return Ok(1);
+    }
+    s.parse().map_err(|_| ParseError::Number(s.to_owned()))
+}
+
+/// The length of the ref name at the start of `s`, ie. up to the first
+/// suffix.
+fn name_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    bytes
+        .iter()
+        .enumerate()
+        .position(|(i, b)| match b {
+            b'^' | b'~' | b':' => true,
+            b'@' => bytes.get(i + 1) == Some(&b'{'),
+            _ => false,
+        })
+        .unwrap_or(s.len())
+}
+
+/// The length of `s` up to the first `:` which is not enclosed in braces.
+fn head_len(s: &str) -> usize {
+    let mut depth = 0usize;
+    for (i, b) in s.bytes().enumerate() {
+        match b {
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b':' if depth == 0 => return i,
+            _ => {},
+        }
+    }
+    s.len()
+}
+
+/// The position of the first `..` in `s` which is not enclosed in braces.
+fn find_dots(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    for (i, b) in bytes.iter().enumerate() {
+        match b {
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b'.' if depth == 0 && bytes.get(i + 1) == Some(&b'.') => return Some(i),
+            _ => {},
+        }
+    }
+    None
+}
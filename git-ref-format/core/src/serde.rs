@@ -8,7 +8,7 @@ use std::convert::TryFrom;
 use ::serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    refspec::{PatternStr, PatternString},
+    refspec::{PatternStr, PatternString, Refspec},
     Namespaced,
     Qualified,
     RefStr,
@@ -142,3 +142,24 @@ impl Serialize for Namespaced<'_> {
         serializer.serialize_str(self.as_str())
     }
 }
+
+impl<'de> Deserialize<'de> for Refspec {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+            .and_then(|s: &str| Self::try_from(s).map_err(de::Error::custom))
+    }
+}
+
+impl Serialize for Refspec {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
@@ -11,7 +11,20 @@ use std::convert::TryInto;
 use proc_macro::TokenStream;
 use proc_macro_error::abort;
 use quote::quote;
-use syn::{parse_macro_input, LitStr};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    spanned::Spanned as _,
+    Attribute,
+    Data,
+    DataStruct,
+    DeriveInput,
+    Fields,
+    Ident,
+    LitStr,
+    Token,
+    Visibility,
+};
 
 use git_ref_format_core::{refspec::PatternStr, Component, Error, RefStr};
 
@@ -120,3 +133,141 @@ pub fn pattern(input: TokenStream) -> TokenStream {
         },
     }
 }
+
+/// Derive [`git_ref_format_core::lit::Lit`] for a unit struct.
+///
+/// The name of the literal is given by the `#[ref_lit("...")]` attribute. It
+/// must be a valid [`git_ref_format_core::Component`], which is checked at
+/// compile time.
+///
+/// # Example
+///
+/// ```no_run
+/// use git_ref_format::{lit::RefLit, name, Qualified};
+///
+/// #[derive(Clone, Copy, RefLit)]
+/// #[ref_lit("drafts")]
+/// struct Drafts;
+///
+/// assert_eq!(
+///     "refs/drafts/wip",
+///     Qualified::from_components(Drafts, name::component!("wip"), None).as_str()
+/// )
+/// ```
+#[proc_macro_error]
+#[proc_macro_derive(RefLit, attributes(ref_lit))]
+pub fn ref_lit(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match &input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Unit,
+            ..
+        }) => {},
+        _ => abort!(
+            input.ident.span(),
+            "RefLit can only be derived for unit structs"
+        ),
+    }
+    if !input.generics.params.is_empty() {
+        abort!(
+            input.generics.span(),
+            "RefLit can not be derived for generic types"
+        );
+    }
+
+    let attr = input
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("ref_lit"))
+        .unwrap_or_else(|| abort!(input.ident.span(), "missing #[ref_lit(\"...\")] attribute"));
+    let name: LitStr = attr
+        .parse_args()
+        .unwrap_or_else(|e| abort!(e.span(), "expected #[ref_lit(\"...\")]: {}", e));
+
+    lit_impl(&input.ident, &name)
+}
+
+/// Define a unit struct implementing [`git_ref_format_core::lit::Lit`].
+///
+/// `lit!(pub Drafts = "drafts")` is a shorthand for:
+///
+/// ```no_run
+/// #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash, RefLit)]
+/// #[ref_lit("drafts")]
+/// pub struct Drafts;
+/// ```
+///
+/// Attributes, such as doc comments, may precede the visibility.
+#[proc_macro_error]
+#[proc_macro]
+pub fn lit(input: TokenStream) -> TokenStream {
+    let LitDef {
+        attrs,
+        vis,
+        ident,
+        name,
+    } = parse_macro_input!(input as LitDef);
+
+    let mut expand = TokenStream::from(quote! {
+        #(#attrs)*
+        #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
+        #vis struct #ident;
+    });
+    expand.extend(lit_impl(&ident, &name));
+    expand
+}
+
+struct LitDef {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    ident: Ident,
+    name: LitStr,
+}
+
+impl Parse for LitDef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let name = input.parse()?;
+        input.parse::<Option<Token![;]>>()?;
+
+        Ok(Self {
+            attrs,
+            vis,
+            ident,
+            name,
+        })
+    }
+}
+
+fn lit_impl(ident: &Ident, lit: &LitStr) -> TokenStream {
+    let val = lit.value();
+
+    let name: Result<&RefStr, Error> = val.as_str().try_into();
+    match name {
+        Ok(name) => {
+            let comp: Option<Component> = name.into();
+            if comp.is_none() {
+                abort!(lit.span(), "component contains a '/'");
+            }
+
+            let expand = quote! {
+                impl ::git_ref_format::lit::Lit for #ident {
+                    const SELF: Self = #ident;
+                    const NAME: &'static ::git_ref_format::RefStr = unsafe {
+                        ::git_ref_format::lit::__private::component_unchecked(#val)
+                    };
+                }
+                impl ::git_ref_format::lit::__private::Sealed for #ident {}
+            };
+            TokenStream::from(expand)
+        },
+
+        Err(e) => {
+            abort!(lit.span(), "invalid refname literal: {}", e);
+        },
+    }
+}
@@ -24,7 +24,9 @@
 //! [`check_ref_format`]). Both types are referred to as "ref strings".
 //!
 //! Note that this implies that ref names must be valid UTF-8, which git itself
-//! doesn't require.
+//! doesn't require. The `"bstr"` feature enables the byte-oriented
+//! [`BRefStr`] and [`BRefString`] types, which can represent any ref name git
+//! accepts, and convert losslessly to ref strings if they are valid UTF-8.
 //!
 //! Ref strings can be iterated over, either yielding `&str` or [`Component`]. A
 //! [`Component`] is guaranteed to not contain a '/' separator, and can thus
@@ -38,7 +40,8 @@
 //!
 //! The `"macro"` feature enables the `refstring!` and `component!` macros,
 //! which can be convenient to construct compile-time validated [`RefString`]s
-//! respectively [`Component`]s.
+//! respectively [`Component`]s. It also enables the `lit::RefLit` derive macro
+//! and the `lit::lit!` macro, which allow to define custom [`lit::Lit`] types.
 //!
 //! ## Refspec Patterns
 //!
@@ -52,9 +55,25 @@
 //! string to a refspec pattern, but not the other way round. Refspec patterns
 //! are commonly used for mapping remote to local refs (cf. [`git-fetch`]).
 //!
+//! Such a mapping is performed by matching a ref string against a pattern using
+//! [`refspec::PatternStr::matches`], and substituting the resulting
+//! [`refspec::Capture`] into another pattern using
+//! [`refspec::PatternStr::expand`].
+//!
+//! Full refspecs, including forced, deleting and negative ones, are represented
+//! by [`refspec::Refspec`]. [`refspec::fetch_map`] evaluates a list of them
+//! against a set of refs like [`git-fetch`] does.
+//!
 //! The `"macro"` feature enables the `refspec::pattern!` macro, which
 //! constructs a compile-time validated [`refspec::PatternString`].
 //!
+//! ## Revision Expressions
+//!
+//! The [`rev`] module parses revision expressions as understood by
+//! [`git-rev-parse`] (eg. `main~3`, `v1.0^{commit}`, `origin/main..feature`)
+//! into a typed [`rev::Expr`]. Resolving them to objects is left to the
+//! [`rev::Resolve`] trait, which can be implemented for any repository backend.
+//!
 //! ## Structured Ref Strings
 //!
 //! Ref strings may be [`Qualified`], which essentially means that they start
@@ -66,6 +85,10 @@
 //! (namespaces can be nested). [`Namespaced`] refs are also [`Qualified`], and
 //! can have their namespace(s) stripped.
 //!
+//! The `"link-literals"` feature enables the [`layout`] module, which
+//! classifies ref strings according to the conventions used by Radicle, eg.
+//! `refs/namespaces/<urn>/refs/remotes/<peer>/cobs/<type>/<id>`.
+//!
 //! # On Git Ref Name Conventions
 //!
 //! Git references are essentially path names pointing to their traditional
@@ -119,14 +142,16 @@
 //!
 //! [`git-check-ref-format`]: https://git-scm.com/docs/git-check-ref-format
 //! [`git-fetch`]: https://git-scm.com/docs/git-fetch
+//! [`git-rev-parse`]: https://git-scm.com/docs/git-rev-parse
 //! [git-remote]: https://git-scm.com/docs/git-remote
 //! [`gitnamespaces`]: https://git-scm.com/docs/gitnamespaces
 #[cfg(feature = "percent-encoding")]
 pub use git_ref_format_core::PercentEncode;
 pub use git_ref_format_core::{
     check_ref_format,
-    lit,
+    check_ref_format_bytes,
     name::component,
+    normalize_ref_format,
     Component,
     DuplicateGlob,
     Error,
@@ -136,6 +161,19 @@ pub use git_ref_format_core::{
     RefStr,
     RefString,
 };
+#[cfg(feature = "bstr")]
+pub use git_ref_format_core::{BRefStr, BRefString};
+
+#[cfg(feature = "link-literals")]
+pub use git_ref_format_core::layout;
+pub use git_ref_format_core::rev;
+
+pub mod lit {
+    pub use git_ref_format_core::lit::*;
+
+    #[cfg(any(feature = "macro", feature = "git-ref-format-macro"))]
+    pub use git_ref_format_macro::{lit, RefLit};
+}
 
 pub mod name {
     pub use git_ref_format_core::name::*;
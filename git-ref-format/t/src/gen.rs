@@ -99,3 +99,94 @@ pub fn invalid() -> impl Strategy<Value = String> {
         }),
     ]
 }
+
+/// A valid refspec string, in any of the forms accepted by `Refspec`.
+pub fn refspec() -> impl Strategy<Value = String> {
+    let with_glob = || (valid(), with_glob()).prop_map(|(pre, glob)| format!("{}/{}", pre, glob));
+    let force = || any::<bool>().prop_map(|force| if force { "+" } else { "" });
+
+    prop_oneof![
+        (force(), valid(), valid()).prop_map(|(f, src, dst)| format!("{}{}:{}", f, src, dst)),
+        (force(), with_glob(), with_glob())
+            .prop_map(|(f, src, dst)| format!("{}{}:{}", f, src, dst)),
+        (force(), valid()).prop_map(|(f, src)| format!("{}{}", f, src)),
+        (force(), valid()).prop_map(|(f, dst)| format!("{}:{}", f, dst)),
+        with_glob().prop_map(|src| format!("^{}", src)),
+    ]
+}
+
+/// A single revision in the form it is displayed in, ie. with explicit
+/// numbers for `^` and `~`.
+pub fn rev() -> impl Strategy<Value = String> {
+    let op = prop_oneof![
+        (0..3usize).prop_map(|n| format!("^{}", n)),
+        (0..10usize).prop_map(|n| format!("~{}", n)),
+        prop_oneof![
+            Just(""),
+            Just("object"),
+            Just("commit"),
+            Just("tree"),
+            Just("blob"),
+            Just("tag")
+        ]
+        .prop_map(|kind| format!("^{{{}}}", kind)),
+    ];
+    let reflog = prop::option::of(prop_oneof![
+        (0..100usize).prop_map(|n| n.to_string()),
+        Just("upstream".to_owned()),
+        Just("push".to_owned()),
+    ]);
+
+    (valid(), reflog, prop::collection::vec(op, 0..5)).prop_map(|(name, reflog, ops)| {
+        let mut rev = name;
+        if let Some(reflog) = reflog {
+            rev.push_str(&format!("@{{{}}}", reflog));
+        }
+        rev.push_str(&ops.concat());
+        rev
+    })
+}
+
+/// A revision expression in the form it is displayed in.
+pub fn rev_expr() -> impl Strategy<Value = String> {
+    prop_oneof![
+        rev(),
+        (rev(), trivial()).prop_map(|(rev, path)| format!("{}:{}", rev, path)),
+        rev().prop_map(|rev| format!("^{}", rev)),
+        (rev(), rev()).prop_map(|(from, to)| format!("{}..{}", from, to)),
+        (rev(), rev()).prop_map(|(left, right)| format!("{}...{}", left, right)),
+        rev().prop_map(|rev| format!("{}^@", rev)),
+        rev().prop_map(|rev| format!("{}^!", rev)),
+        (rev(), 1..3usize).prop_map(|(rev, n)| format!("{}^-{}", rev, n)),
+    ]
+}
+
+/// A ref following the Radicle layout, possibly with nested namespaces.
+pub fn layout() -> impl Strategy<Value = String> {
+    let category = prop_oneof![
+        valid().prop_map(|name| format!("heads/{}", name)),
+        valid().prop_map(|name| format!("tags/{}", name)),
+        valid().prop_map(|name| format!("notes/{}", name)),
+        Just("rad/id".to_owned()),
+        Just("rad/self".to_owned()),
+        Just("rad/signed_refs".to_owned()),
+        trivial().prop_map(|id| format!("rad/ids/{}", id)),
+        (trivial(), trivial()).prop_map(|(ty, id)| format!("cobs/{}/{}", ty, id)),
+        valid(),
+    ];
+    let remote = prop::option::of(trivial());
+    let namespaces = prop::collection::vec(trivial(), 0..3);
+
+    (namespaces, remote, category).prop_map(|(namespaces, remote, category)| {
+        let mut name = String::new();
+        for ns in namespaces {
+            name.push_str(&format!("refs/namespaces/{}/", ns));
+        }
+        name.push_str("refs/");
+        if let Some(peer) = remote {
+            name.push_str(&format!("remotes/{}/", peer));
+        }
+        name.push_str(&category);
+        name
+    })
+}
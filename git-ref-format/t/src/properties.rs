@@ -6,8 +6,12 @@ use proptest::prelude::*;
 
 use crate::gen;
 
+mod bytes;
+mod layout;
 mod name;
 mod pattern;
+mod refspec;
+mod rev;
 
 proptest! {
     #[test]
@@ -0,0 +1,51 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::convert::TryFrom;
+
+use git_ref_format::{check_ref_format, BRefStr, BRefString, RefStr, RefString};
+use proptest::prelude::*;
+
+use crate::gen;
+
+proptest! {
+    #[test]
+    fn valid_utf8(input in gen::valid()) {
+        let bytes = BRefStr::try_from_bytes(input.as_bytes()).unwrap();
+        let refstr = RefStr::try_from_str(&input).unwrap();
+        assert_eq!(Ok(refstr), bytes.to_refstr());
+        assert_eq!(
+            RefString::try_from(BRefString::from(refstr.to_owned())).unwrap(),
+            refstr.to_owned()
+        )
+    }
+
+    #[test]
+    fn invalid_utf8(input in gen::invalid()) {
+        assert_eq!(
+            check_ref_format(opts(), &input).map_err(|e| e.to_string()),
+            BRefStr::try_from_bytes(input.as_bytes())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        )
+    }
+
+    #[test]
+    fn non_utf8(input in gen::valid(), byte in 0x80u8..) {
+        let mut bytes = input.into_bytes();
+        bytes.push(byte);
+        let brefstring = BRefString::try_from(bytes.clone()).unwrap();
+        assert!(brefstring.to_refstr().is_err());
+        assert_eq!(
+            bytes,
+            brefstring.into_refstring().unwrap_err().into_bytes()
+        )
+    }
+}
+
+fn opts() -> git_ref_format::Options {
+    git_ref_format::Options {
+        allow_onelevel: true,
+        allow_pattern: false,
+    }
+}
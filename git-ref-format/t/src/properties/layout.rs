@@ -0,0 +1,23 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use git_ref_format::{layout::Layout, RefStr};
+use proptest::prelude::*;
+
+use crate::gen;
+
+proptest! {
+    #[test]
+    fn roundtrip(input in gen::valid()) {
+        let name = RefStr::try_from_str(&input).unwrap();
+        assert_eq!(name, Layout::classify(name).to_refstring().as_refstr())
+    }
+
+    #[test]
+    fn roundtrip_layout(input in gen::layout()) {
+        let name = RefStr::try_from_str(&input).unwrap();
+        let layout = Layout::classify(name);
+        assert!(layout.category().is_some());
+        assert_eq!(name, layout.to_refstring().as_refstr())
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::convert::TryFrom;
 
-use git_ref_format::{Error, RefStr, RefString};
+use git_ref_format::{normalize_ref_format, Error, Options, RefStr, RefString};
 use proptest::prelude::*;
 use test_helpers::roundtrip;
 
@@ -73,6 +73,37 @@ proptest! {
         )
     }
 
+    #[test]
+    fn sanitize(input in any::<String>()) {
+        match RefString::sanitize(&input) {
+            Ok(sane) => {
+                assert_matches!(RefString::try_from(sane.as_str()), Ok(_))
+            },
+            Err(e) => assert_matches!(e, Error::Empty),
+        }
+    }
+
+    #[test]
+    fn sanitize_invalid(input in gen::invalid()) {
+        if let Ok(sane) = RefString::sanitize(&input) {
+            assert_matches!(RefString::try_from(sane.as_str()), Ok(_))
+        }
+    }
+
+    #[test]
+    fn sanitize_valid_is_identity(input in gen::valid()) {
+        assert_eq!(input.as_str(), RefString::sanitize(&input).unwrap().as_str())
+    }
+
+    #[test]
+    fn normalize_slashes(input in prop::collection::vec(gen::trivial(), 1..20), slashes in "/{1,3}") {
+        let messy = format!("{}{}{}", slashes, input.join(&slashes), slashes);
+        assert_eq!(
+            input.join("/"),
+            normalize_ref_format(Options { allow_onelevel: true, allow_pattern: false }, &messy).unwrap()
+        )
+    }
+
     #[test]
     fn json(input in gen::valid()) {
        roundtrip::json(RefString::try_from(input).unwrap())
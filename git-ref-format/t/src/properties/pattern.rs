@@ -3,7 +3,7 @@
 
 use std::convert::TryFrom;
 
-use git_ref_format::{refspec, Error};
+use git_ref_format::{refspec, Error, RefString};
 use proptest::prelude::*;
 use test_helpers::roundtrip;
 
@@ -43,6 +43,24 @@ proptest! {
         )
     }
 
+    #[test]
+    fn matches_and_expands(
+        components in prop::collection::vec(gen::trivial(), 1..20),
+        glob in any::<prop::sample::Index>()
+    ) {
+        let name = RefString::try_from(components.join("/")).unwrap();
+        let glob = glob.index(components.len());
+        let pattern = {
+            let mut components = components.clone();
+            components[glob] = "*".to_owned();
+            refspec::PatternString::try_from(components.join("/")).unwrap()
+        };
+        let capture = pattern.matches(&name).unwrap();
+
+        assert_eq!(components[glob], capture.as_str());
+        assert_eq!(name, pattern.expand(&capture).unwrap())
+    }
+
     #[test]
     fn json(input in gen::with_glob()) {
         roundtrip::json(refspec::PatternString::try_from(input).unwrap())
@@ -0,0 +1,25 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use git_ref_format::refspec::Refspec;
+use proptest::prelude::*;
+use test_helpers::roundtrip;
+
+use crate::gen;
+
+proptest! {
+    #[test]
+    fn display(input in gen::refspec()) {
+        assert_eq!(input, input.parse::<Refspec>().unwrap().to_string())
+    }
+
+    #[test]
+    fn json(input in gen::refspec()) {
+        roundtrip::json(input.parse::<Refspec>().unwrap())
+    }
+
+    #[test]
+    fn cbor(input in gen::refspec()) {
+        roundtrip::cbor(input.parse::<Refspec>().unwrap())
+    }
+}
@@ -0,0 +1,14 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use git_ref_format::rev::Expr;
+use proptest::prelude::*;
+
+use crate::gen;
+
+proptest! {
+    #[test]
+    fn display(input in gen::rev_expr()) {
+        assert_eq!(input, input.parse::<Expr>().unwrap().to_string())
+    }
+}
@@ -1,7 +1,20 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use git_ref_format::{component, name, refname, refspec, Error, Qualified, RefStr, RefString};
+use git_ref_format::{
+    component,
+    lit,
+    name,
+    normalize_ref_format,
+    refname,
+    refspec,
+    rev,
+    Error,
+    Options,
+    Qualified,
+    RefStr,
+    RefString,
+};
 
 #[test]
 fn refname_macro_works() {
@@ -279,3 +292,484 @@ fn collect_pattern_duplicate_glob() {
         Err(refspec::DuplicateGlob)
     )
 }
+
+#[test]
+fn pattern_matches_within_component() {
+    let pat = refspec::pattern!("refs/heads/feature-*");
+    assert_eq!(
+        "a",
+        pat.matches(&refname!("refs/heads/feature-a"))
+            .unwrap()
+            .as_str()
+    );
+    assert_eq!(
+        "a/b",
+        pat.matches(&refname!("refs/heads/feature-a/b"))
+            .unwrap()
+            .as_str()
+    );
+    assert!(pat.matches(&refname!("refs/heads/main")).is_none())
+}
+
+#[test]
+fn pattern_matches_no_overlap() {
+    assert!(refspec::pattern!("refs/heads/a*a")
+        .matches(&refname!("refs/heads/a"))
+        .is_none())
+}
+
+#[test]
+fn pattern_matches_without_glob() {
+    let pat = refspec::pattern!("refs/heads/main");
+    assert_eq!("", pat.matches(name::REFS_HEADS_MAIN).unwrap().as_str());
+    assert!(pat.matches(name::REFS_HEADS_MASTER).is_none())
+}
+
+#[test]
+fn pattern_expand() {
+    let name = refname!("refs/heads/feature/x");
+    let capture = refspec::pattern!("refs/heads/*").matches(&name).unwrap();
+    assert_eq!(
+        "refs/remotes/origin/feature/x",
+        refspec::pattern!("refs/remotes/origin/*")
+            .expand(&capture)
+            .unwrap()
+            .as_str()
+    )
+}
+
+#[test]
+fn pattern_expand_invalid() {
+    let name = refname!("refs/heads/a.x");
+    let capture = refspec::pattern!("refs/heads/a*").matches(&name).unwrap();
+    assert_matches!(
+        refspec::pattern!("refs/remotes/origin/*").expand(&capture),
+        Err(Error::StartsDot)
+    )
+}
+
+#[test]
+fn refspec_parse() {
+    assert_eq!(
+        "refs/heads/*",
+        "+refs/heads/*:refs/remotes/origin/*"
+            .parse::<refspec::Refspec>()
+            .unwrap()
+            .src()
+            .unwrap()
+            .as_str()
+    );
+    assert_matches!(
+        "main".parse(),
+        Ok(refspec::Refspec::Map {
+            force: false,
+            dst: None,
+            ..
+        })
+    );
+    assert_matches!("main:".parse(), Ok(refspec::Refspec::Map { dst: None, .. }));
+    assert_matches!(
+        ":refs/heads/gone".parse(),
+        Ok(refspec::Refspec::Delete { force: false, .. })
+    );
+    assert_matches!(
+        "^refs/heads/wip/*".parse(),
+        Ok(refspec::Refspec::Negative { .. })
+    );
+}
+
+#[test]
+fn refspec_parse_invalid() {
+    use refspec::{ParseError, Refspec};
+
+    assert_matches!("".parse::<Refspec>(), Err(ParseError::Empty));
+    assert_matches!("+:".parse::<Refspec>(), Err(ParseError::Empty));
+    assert_matches!(
+        "+^refs/heads/x".parse::<Refspec>(),
+        Err(ParseError::ForcedNegative)
+    );
+    assert_matches!(
+        "^refs/heads/x:y".parse::<Refspec>(),
+        Err(ParseError::NegativeDst)
+    );
+    assert_matches!(
+        "refs/heads/*:refs/heads/main".parse::<Refspec>(),
+        Err(ParseError::PatternMismatch)
+    );
+    assert_matches!(
+        "refs/heads/a..b".parse::<Refspec>(),
+        Err(ParseError::Src(Error::DotDot))
+    );
+    assert_matches!(
+        "a:b c".parse::<Refspec>(),
+        Err(ParseError::Dst(Error::Space))
+    );
+}
+
+#[test]
+fn fetch_map() {
+    use refspec::Refspec;
+
+    let refs = [
+        refname!("refs/heads/main"),
+        refname!("refs/heads/wip/x"),
+        refname!("refs/heads/feature"),
+        refname!("refs/tags/feature"),
+        refname!("refs/tags/v1"),
+    ];
+    let specs = [
+        "+refs/heads/*:refs/remotes/origin/*",
+        "^refs/heads/wip/*",
+        "refs/tags/*:refs/tags/*",
+        "feature",
+        "+refs/heads/main:refs/remotes/origin/main",
+    ]
+    .iter()
+    .map(|s| s.parse::<Refspec>().unwrap())
+    .collect::<Vec<_>>();
+
+    let mapped = refspec::fetch_map(&specs, refs.iter().map(|r| r.as_refstr()))
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.src.as_str(), m.dst.map(String::from), m.force))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            (
+                "refs/heads/main",
+                Some("refs/remotes/origin/main".to_owned()),
+                true
+            ),
+            (
+                "refs/heads/feature",
+                Some("refs/remotes/origin/feature".to_owned()),
+                true
+            ),
+            (
+                "refs/tags/feature",
+                Some("refs/tags/feature".to_owned()),
+                false
+            ),
+            ("refs/tags/v1", Some("refs/tags/v1".to_owned()), false),
+            ("refs/tags/feature", None, false),
+        ],
+        mapped
+    )
+}
+
+#[test]
+fn fetch_map_conflict() {
+    use refspec::{MapError, Refspec};
+
+    let refs = [refname!("refs/heads/a"), refname!("refs/tags/a")];
+    let specs = [
+        "refs/heads/a:refs/remotes/origin/a",
+        "refs/tags/a:refs/remotes/origin/a",
+    ]
+    .iter()
+    .map(|s| s.parse::<Refspec>().unwrap())
+    .collect::<Vec<_>>();
+
+    assert_matches!(
+        refspec::fetch_map(&specs, refs.iter().map(|r| r.as_refstr())),
+        Err(MapError::Conflict { .. })
+    )
+}
+
+#[test]
+fn fetch_map_pattern_mismatch() {
+    use refspec::{MapError, Refspec};
+
+    let refs = [refname!("refs/heads/main")];
+    let specs = [Refspec::Map {
+        force: false,
+        src: refspec::pattern!("refs/heads/main").to_owned(),
+        dst: Some(refspec::pattern!("refs/remotes/origin/*").to_owned()),
+    }];
+
+    assert_matches!(
+        refspec::fetch_map(&specs, refs.iter().map(|r| r.as_refstr())),
+        Err(MapError::Expand { .. })
+    )
+}
+
+#[test]
+fn normalize() {
+    let opts = || Options {
+        allow_onelevel: false,
+        allow_pattern: false,
+    };
+    assert_eq!(
+        "refs/heads/main",
+        normalize_ref_format(opts(), "//refs//heads///main/").unwrap()
+    );
+    assert_matches!(normalize_ref_format(opts(), "/main/"), Err(Error::OneLevel));
+    assert_matches!(normalize_ref_format(opts(), "///"), Err(Error::Empty));
+}
+
+#[test]
+fn sanitize() {
+    assert_eq!(
+        "feature/fix-the-thing",
+        RefString::sanitize("feature//fix the thing")
+            .unwrap()
+            .as_str()
+    );
+    assert_eq!(
+        "a.b/c/d",
+        RefString::sanitize("..a...b./.c.lock/d.lock.lock")
+            .unwrap()
+            .as_str()
+    );
+    assert_eq!("x-{1}", RefString::sanitize("x@{1}").unwrap().as_str());
+    assert_eq!("-", RefString::sanitize("@").unwrap().as_str());
+    assert_eq!(
+        "tab-nul-",
+        RefString::sanitize("tab\tnul\0").unwrap().as_str()
+    );
+    assert_matches!(RefString::sanitize("/./../"), Err(Error::Empty));
+}
+
+#[test]
+fn at_open_brace_not_across_ends() {
+    assert_matches!(RefStr::try_from_str("{x@"), Ok(_));
+    assert_matches!(RefStr::try_from_str(".x."), Err(Error::StartsDot));
+}
+
+#[test]
+fn rev_parse() {
+    use rev::{Base, Expr, Op, Peel, Reflog, Rev};
+
+    let rev = |s: &str| s.parse::<Rev>().unwrap();
+
+    assert_eq!(
+        Rev {
+            base: Base::Name(refname!("main")),
+            reflog: None,
+            ops: vec![Op::Ancestor(3)],
+            path: None,
+        },
+        rev("main~3")
+    );
+    assert_eq!(vec![Op::Peel(Peel::Commit)], rev("v1.0^{commit}").ops);
+    assert_eq!(Some(Reflog::Entry(2)), rev("HEAD@{2}").reflog);
+    assert_eq!(Base::Search("fix bug".to_owned()), rev(":/fix bug").base);
+    assert_eq!(Some("src/lib.rs"), rev("main:src/lib.rs").path.as_deref());
+    assert_eq!(
+        vec![Op::Parent(1), Op::Parent(2), Op::Ancestor(1)],
+        rev("main^^2~").ops
+    );
+    assert_eq!(Base::Name(refname!("HEAD")), rev("@~1").base);
+    assert_eq!(Base::PreviousCheckout(1), rev("@{-1}").base);
+    assert_eq!(Some(Reflog::Upstream), rev("@{u}").reflog);
+    assert_eq!(
+        Base::Index {
+            stage: 2,
+            path: "README.md".to_owned()
+        },
+        rev(":2:README.md").base
+    );
+    assert_eq!(
+        Some(Reflog::Date("yesterday 10:00".to_owned())),
+        rev("main@{yesterday 10:00}").reflog
+    );
+
+    assert_eq!(
+        Expr::Range {
+            from: rev("origin/main"),
+            to: rev("feature"),
+        },
+        "origin/main..feature".parse().unwrap()
+    );
+    assert_eq!(
+        Expr::SymmetricDifference {
+            left: rev("main"),
+            right: Rev::head(),
+        },
+        "main...".parse().unwrap()
+    );
+    assert_eq!(Expr::Exclude(rev("main")), "^main".parse().unwrap());
+    assert_eq!(
+        Expr::ExcludeParent(rev("HEAD"), 1),
+        "HEAD^-".parse().unwrap()
+    );
+    assert_eq!(
+        Expr::Rev(rev("main:a..b")),
+        "main:a..b".parse::<Expr>().unwrap()
+    );
+}
+
+#[test]
+fn rev_parse_invalid() {
+    use rev::{ParseError, Rev};
+
+    assert_matches!("".parse::<Rev>(), Err(ParseError::Empty));
+    assert_matches!("main@{".parse::<Rev>(), Err(ParseError::Unterminated));
+    assert_matches!("main^{commit".parse::<Rev>(), Err(ParseError::Unterminated));
+    assert_matches!("main^{tarball}".parse::<Rev>(), Err(ParseError::Peel(_)));
+    assert_matches!("ma in".parse::<Rev>(), Err(ParseError::Name(_)));
+    assert_matches!("main^{}x".parse::<Rev>(), Err(ParseError::Unexpected(_)));
+}
+
+#[test]
+fn rev_resolve() {
+    use rev::{Base, Peel, Reflog, Resolve, Rev};
+
+    /// Resolves to a description of the path taken.
+    struct Trace;
+
+    impl Resolve for Trace {
+        type Output = String;
+        type Error = &'static str;
+
+        fn base(&mut self, base: &Base, _: Option<&Reflog>) -> Result<String, Self::Error> {
+            Ok(base.to_string())
+        }
+
+        fn parent(&mut self, rev: String, n: usize) -> Result<String, Self::Error> {
+            Ok(format!("parent({}, {})", rev, n))
+        }
+
+        fn peel(&mut self, rev: String, peel: Peel) -> Result<String, Self::Error> {
+            Ok(format!("peel({}, {})", rev, peel))
+        }
+
+        fn search(&mut self, _: String, _: &str) -> Result<String, Self::Error> {
+            Err("unsupported")
+        }
+
+        fn path(&mut self, rev: String, path: &str) -> Result<String, Self::Error> {
+            Ok(format!("path({}, {})", rev, path))
+        }
+    }
+
+    let resolve = |s: &str| Trace.resolve(&s.parse::<Rev>().unwrap());
+    assert_eq!(
+        Ok("parent(parent(peel(main, commit), 1), 1)".to_owned()),
+        resolve("main~2")
+    );
+    assert_eq!(
+        Ok("path(parent(main, 2), src)".to_owned()),
+        resolve("main^2:src")
+    );
+    assert_eq!(Ok("peel(v1, commit)".to_owned()), resolve("v1^0"));
+    assert_eq!(Err("unsupported"), resolve("main^{/fix}"));
+}
+
+#[test]
+fn layout_classify() {
+    use git_ref_format::layout::{Category, Layout};
+
+    let name = refname!("refs/namespaces/urn/refs/remotes/peer/cobs/issue/123");
+    let layout = Layout::classify(&name);
+    assert_eq!(Some("urn"), layout.namespace().map(|ns| ns.as_str()));
+    assert_eq!(Some("peer"), layout.peer().map(|peer| peer.as_str()));
+    assert_matches!(
+        layout.category(),
+        Some(Category::Cobs { typename, id }) if typename.as_str() == "issue" && id.as_str() == "123"
+    );
+    assert_eq!(name, layout.to_refstring());
+
+    let name = refname!("refs/heads/feature/x");
+    assert_matches!(
+        Layout::classify(&name),
+        Layout::Local(Category::Heads(branch)) if branch.as_str() == "feature/x"
+    );
+    let name = refname!("refs/rad/id");
+    assert_matches!(Layout::classify(&name), Layout::Local(Category::RadId));
+    let name = refname!("refs/remotes/origin/main");
+    assert_matches!(
+        Layout::classify(&name),
+        Layout::Remote { category: Category::Other(rest), .. } if rest.as_str() == "main"
+    );
+    let name = refname!("refs/rad/ids/a/b");
+    assert_matches!(Layout::classify(&name), Layout::Local(Category::Other(_)));
+    let name = refname!("refs/namespaces/urn");
+    assert_matches!(Layout::classify(&name), Layout::Local(Category::Other(_)));
+    let name = refname!("refs/namespaces/urn/HEAD");
+    let layout = Layout::classify(&name);
+    assert_matches!(
+        &layout,
+        Layout::Namespaced { namespace, inner }
+            if namespace.as_str() == "urn"
+                && matches!(inner.as_ref(), Layout::Other(head) if head.as_str() == "HEAD")
+    );
+    assert_eq!(None, layout.category());
+    assert_eq!(name, layout.to_refstring());
+    let name = refname!("refs/namespaces/a/refs/namespaces/b/HEAD");
+    assert_matches!(
+        Layout::classify(&name).strip_namespaces(),
+        Layout::Other(head) if head.as_str() == "HEAD"
+    );
+    let name = refname!("HEAD");
+    assert_matches!(Layout::classify(&name), Layout::Other(_));
+}
+
+#[test]
+fn bytes_latin1() {
+    use git_ref_format::{BRefStr, BRefString};
+
+    // "refs/heads/café" in Latin-1
+    let latin1 = b"refs/heads/caf\xe9";
+    let name = BRefStr::try_from_bytes(latin1).unwrap();
+    assert_eq!(&latin1[..], name.as_bytes());
+    assert!(name.to_refstr().is_err());
+    assert_eq!(
+        Some(&b"caf\xe9"[..]),
+        name.strip_prefix(BRefStr::try_from_bytes(b"refs/heads").unwrap())
+            .map(|s| s.as_bytes())
+    );
+
+    let utf8 = BRefString::from(refname!("refs/heads/main"));
+    assert_eq!(Ok(refname!("refs/heads/main")), utf8.into_refstring());
+}
+
+#[test]
+fn bytes_invalid() {
+    use git_ref_format::BRefStr;
+
+    assert_matches!(BRefStr::try_from_bytes(b""), Err(Error::Empty));
+    assert_matches!(
+        BRefStr::try_from_bytes(b"refs/heads/\xe9.lock"),
+        Err(Error::DotLock)
+    );
+    assert_matches!(
+        BRefStr::try_from_bytes(b"refs/heads/\xe9:x"),
+        Err(Error::InvalidChar(':'))
+    );
+    assert_matches!(
+        BRefStr::try_from_bytes(b"refs/heads/\xe9."),
+        Err(Error::EndsDot)
+    );
+}
+
+lit::lit!(
+    /// `refs/ci`
+    Ci = "ci"
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, lit::RefLit)]
+#[ref_lit("drafts")]
+struct Drafts;
+
+#[test]
+fn custom_lit() {
+    use lit::Lit as _;
+
+    assert_eq!("drafts", Drafts::NAME.as_str());
+    assert_eq!(
+        "refs/drafts/wip",
+        Qualified::from_components(Drafts, name::component!("wip"), None).as_str()
+    );
+    assert_eq!(
+        "refs/ci/builds/1",
+        Qualified::from_components(Ci, name::component!("builds"), Some(name::component!("1")))
+            .as_str()
+    );
+
+    let wip = refname!("refs/drafts/wip");
+    let wip = wip.qualified().unwrap();
+    let (_, drafts, _, _) = wip.non_empty_components();
+    assert_eq!(Some(Drafts), drafts.as_lit::<Drafts>());
+    assert_eq!(None, drafts.as_lit::<Ci>());
+}
@@ -0,0 +1,18 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_hash::ObjectId;
+
+/// Parse a hexadecimal SHA-1 object id.
+///
+/// Unlike [`ObjectId::from_hex`], this does not panic if `hex` has the right
+/// length but contains non-hex characters.
+pub(crate) fn object_id(hex: &[u8]) -> Option<ObjectId> {
+    if hex.len() == 40 && hex.iter().all(u8::is_ascii_hexdigit) {
+        ObjectId::from_hex(hex).ok()
+    } else {
+        None
+    }
+}
@@ -6,10 +6,14 @@
 #[macro_use]
 extern crate async_trait;
 
+mod hex;
+
 pub mod odb;
 pub mod protocol;
 pub mod refs;
 pub use refs::db as refdb;
+pub mod repository;
+pub use repository::Repository;
 #[cfg(feature = "git2")]
 pub mod service;
 
@@ -3,12 +3,25 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use std::{convert::TryInto as _, io};
+
 use git_hash::oid;
+use git_object::Kind;
 use thiserror::Error;
 
+pub mod alternates;
 pub mod backend;
+mod chunk;
+pub mod commit_graph;
+pub mod decode;
+pub mod fsck;
 pub mod index;
+mod lru;
+pub mod nonblocking;
+#[cfg(feature = "openmetrics")]
+pub mod openmetrics;
 pub mod pack;
+pub mod reach;
 pub mod window;
 
 pub use git_pack::{cache, data::Object};
@@ -20,8 +33,72 @@ pub enum Error {
 
     #[error(transparent)]
     Loose(#[from] git_odb::loose::find::Error),
+
+    #[error(transparent)]
+    LooseHeader(#[from] backend::error::Header),
+}
+
+/// The kind and size of an object, as returned by [`Odb::header`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub kind: Kind,
+    /// The size of the object's data in bytes, after resolving any deltas.
+    pub size: u64,
+}
+
+/// A reader over the data of an object, as returned by [`Odb::reader`].
+///
+/// Yields exactly [`Header::size`] bytes, or fails with
+/// [`io::ErrorKind::UnexpectedEof`] if the object data is truncated.
+pub struct Reader {
+    header: Header,
+    remaining: u64,
+    inner: Box<dyn io::Read + Send>,
+}
+
+impl Reader {
+    pub(crate) fn new<R>(header: Header, inner: R) -> Self
+    where
+        R: io::Read + Send + 'static,
+    {
+        Self {
+            header,
+            remaining: header.size,
+            inner: Box::new(inner),
+        }
+    }
+
+    pub fn header(&self) -> Header {
+        self.header
+    }
 }
 
+impl io::Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "object data is truncated",
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// An object database consisting of loose and packed objects.
+///
+/// Objects in [alternate] object directories are visible, as both
+/// [`backend::Loose`] and [`index::Shared`] follow alternates.
+///
+/// [alternate]: alternates
 pub struct Odb<I, D> {
     pub loose: backend::Loose,
     pub packed: backend::Packed<I, D>,
@@ -48,4 +125,35 @@ where
         }
         self.loose.try_find(id, buf).map_err(Into::into)
     }
+
+    /// Obtain the kind and size of the object `id`, without decompressing (or
+    /// delta-resolving) its data.
+    ///
+    /// This is considerably cheaper than [`Odb::find`] when the contents of
+    /// the object are not needed, eg. when listing a tree.
+    pub fn header(&self, id: impl AsRef<oid>) -> Result<Option<Header>, Error> {
+        let id = id.as_ref();
+        if self.packed.contains(id) {
+            return self.packed.header(id).map_err(Into::into);
+        }
+        self.loose.header(id).map_err(Into::into)
+    }
+
+    /// Open the object `id` for reading, without necessarily loading it into
+    /// memory in its entirety.
+    ///
+    /// Loose objects and non-delta packed objects are inflated incrementally
+    /// as the [`Reader`] is consumed. Deltified objects can only be resolved as
+    /// a whole, and are thus read into memory upfront, like [`Odb::find`] does.
+    pub fn reader(
+        &self,
+        id: impl AsRef<oid>,
+        cache: &mut impl cache::DecodeEntry,
+    ) -> Result<Option<Reader>, Error> {
+        let id = id.as_ref();
+        if self.packed.contains(id) {
+            return self.packed.reader(id, cache).map_err(Into::into);
+        }
+        self.loose.reader(id).map_err(Into::into)
+    }
 }
@@ -0,0 +1,189 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Support for [`objects/info/alternates`][alternates].
+//!
+//! [alternates]: https://git-scm.com/docs/gitrepository-layout#Documentation/gitrepository-layout.txt-objectsinfoalternates
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice as _;
+use tracing::{trace, warn};
+
+/// The maximum nesting depth of alternates, same as git's.
+pub const MAX_DEPTH: usize = 5;
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Alternates {
+        #[error("failed to read alternates file {path:?}")]
+        Read { path: PathBuf, source: io::Error },
+
+        #[error("malformed line in alternates file {path:?}: {line}")]
+        Malformed { path: PathBuf, line: String },
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+}
+
+/// Resolve the alternate object directories of the object directory
+/// `objects_dir`, recursively.
+///
+/// The result is in the order in which git would consult the alternates,
+/// and does not include `objects_dir` itself. Like git, alternates which do not
+/// exist are skipped, as well as alternates which were already seen (which
+/// includes cycles). Alternates nested deeper than [`MAX_DEPTH`] are ignored.
+pub fn resolve(objects_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, error::Alternates> {
+    let objects_dir = objects_dir.as_ref();
+    let mut seen = BTreeSet::new();
+    seen.insert(canonical(objects_dir)?);
+
+    let mut out = Vec::new();
+    resolve_rec(objects_dir, 0, &mut seen, &mut out)?;
+    Ok(out)
+}
+
+fn resolve_rec(
+    objects_dir: &Path,
+    depth: usize,
+    seen: &mut BTreeSet<PathBuf>,
+    out: &mut Vec<PathBuf>,
+) -> Result<(), error::Alternates> {
+    let path = objects_dir.join("info").join("alternates");
+    let alternates = match read(&path)? {
+        None => return Ok(()),
+        Some(alternates) => alternates,
+    };
+    if depth >= MAX_DEPTH && !alternates.is_empty() {
+        warn!(
+            "{}: ignoring alternate object stores, nesting too deep",
+            objects_dir.display()
+        );
+        return Ok(());
+    }
+
+    for alt in alternates {
+        let alt = if alt.is_relative() {
+            objects_dir.join(alt)
+        } else {
+            alt
+        };
+        let canonical = match fs::canonicalize(&alt) {
+            Ok(canonical) => canonical,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!(
+                    "object directory {} does not exist; check {}",
+                    alt.display(),
+                    path.display()
+                );
+                continue;
+            },
+            Err(e) => return Err(e.into()),
+        };
+        if !seen.insert(canonical.clone()) {
+            trace!("ignoring duplicate alternate {}", alt.display());
+            continue;
+        }
+
+        trace!("alternate {}", canonical.display());
+        out.push(canonical.clone());
+        resolve_rec(&canonical, depth + 1, seen, out)?;
+    }
+
+    Ok(())
+}
+
+/// Read the alternates file at `path`, if it exists.
+fn read(path: &Path) -> Result<Option<Vec<PathBuf>>, error::Alternates> {
+    let buf = match fs::read(path) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(error::Alternates::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+        },
+    };
+
+    let mut alternates = Vec::new();
+    for line in buf.lines() {
+        let line = line.trim();
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+        let line = if line[0] == b'"' {
+            unquote(line).ok_or_else(|| error::Alternates::Malformed {
+                path: path.to_path_buf(),
+                line: line.to_str_lossy().into_owned(),
+            })?
+        } else {
+            line.to_vec()
+        };
+        let alt = line.to_path().map_err(|_| error::Alternates::Malformed {
+            path: path.to_path_buf(),
+            line: line.to_str_lossy().into_owned(),
+        })?;
+        alternates.push(alt.to_path_buf());
+    }
+
+    Ok(Some(alternates))
+}
+
+/// Undo C-style quoting, as applied by git to paths containing "unusual"
+/// characters.
+fn unquote(quoted: &[u8]) -> Option<Vec<u8>> {
+    let inner = quoted.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    let mut out = Vec::with_capacity(inner.len());
+    let mut bytes = inner.iter().copied();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        let unescaped = match bytes.next()? {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'v' => 0x0b,
+            b'\\' => b'\\',
+            b'"' => b'"',
+            d @ b'0'..=b'3' => {
+                let mut ch = d - b'0';
+                for _ in 0..2 {
+                    match bytes.next()? {
+                        d @ b'0'..=b'7' => ch = (ch << 3) | (d - b'0'),
+                        _ => return None,
+                    }
+                }
+                ch
+            },
+            _ => return None,
+        };
+        out.push(unescaped);
+    }
+
+    Some(out)
+}
+
+fn canonical(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        // The primary object directory may not exist (yet)
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(path.to_path_buf()),
+        x => x,
+    }
+}
@@ -3,12 +3,203 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use std::{
+    fs,
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+};
+
+use git_features::zlib;
 use git_hash::oid;
+use git_object::Kind;
 use git_pack::{cache::DecodeEntry, data::Object};
 
-use super::{index, pack, window};
+use super::{alternates, index, pack, window, Header, Reader};
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Header {
+        #[error("malformed loose object header in {path:?}: {reason}")]
+        Malformed { path: PathBuf, reason: &'static str },
+
+        #[error("failed to inflate {path:?}")]
+        Inflate {
+            path: PathBuf,
+            source: zlib::inflate::Error,
+        },
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+}
+
+/// Loose objects of an object directory and its alternates.
+pub struct Loose {
+    stores: Vec<git_odb::loose::Store>,
+}
+
+impl Loose {
+    /// Loose objects in `objects_dir` and all its (transitive) alternates.
+    ///
+    /// See [`alternates::resolve`].
+    pub fn at(objects_dir: impl AsRef<Path>) -> Result<Self, alternates::error::Alternates> {
+        let objects_dir = objects_dir.as_ref();
+        let alternates = alternates::resolve(objects_dir)?;
+        let stores = Some(objects_dir.to_path_buf())
+            .into_iter()
+            .chain(alternates)
+            .map(git_odb::loose::Store::at)
+            .collect();
+
+        Ok(Self { stores })
+    }
+
+    /// The stores of each object directory, in lookup order.
+    pub fn stores(&self) -> &[git_odb::loose::Store] {
+        &self.stores
+    }
+
+    /// The object directories consulted, in lookup order.
+    pub fn objects_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.stores.iter().map(|store| store.path.as_path())
+    }
 
-pub type Loose = git_odb::loose::Store;
+    pub fn contains(&self, id: impl AsRef<oid>) -> bool {
+        let id = id.as_ref();
+        self.stores.iter().any(|store| store.contains(id))
+    }
+
+    pub fn try_find<'a>(
+        &self,
+        id: impl AsRef<oid>,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<Option<Object<'a>>, git_odb::loose::find::Error> {
+        let id = id.as_ref();
+        match self.stores.iter().find(|store| store.contains(id)) {
+            None => Ok(None),
+            Some(store) => store.try_find(id, buf),
+        }
+    }
+
+    /// Read the kind and size of the object `id`, inflating only the loose
+    /// object header.
+    pub fn header(&self, id: impl AsRef<oid>) -> Result<Option<Header>, error::Header> {
+        let hex = id.as_ref().to_owned().to_string();
+        for dir in self.objects_dirs() {
+            let path = dir.join(&hex[..2]).join(&hex[2..]);
+            match fs::File::open(&path) {
+                Ok(file) => return loose_header(&path, file).map(Some),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Open the object `id` for reading, inflating it incrementally.
+    pub fn reader(&self, id: impl AsRef<oid>) -> Result<Option<Reader>, error::Header> {
+        let hex = id.as_ref().to_owned().to_string();
+        for dir in self.objects_dirs() {
+            let path = dir.join(&hex[..2]).join(&hex[2..]);
+            match fs::File::open(&path) {
+                Ok(file) => return loose_reader(&path, file).map(Some),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Inflate `<kind> SP <size> NUL` from the start of the loose object `file`.
+fn loose_header(path: &Path, mut file: fs::File) -> Result<Header, error::Header> {
+    let malformed = |reason| error::Header::Malformed {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let mut inflate = zlib::Inflate::default();
+    let mut input = [0u8; 256];
+    // "commit " + 20 digits + NUL fits comfortably
+    let mut out = [0u8; 32];
+    let mut produced = 0;
+    let nul = loop {
+        if let Some(nul) = out[..produced].iter().position(|b| *b == 0) {
+            break nul;
+        }
+        if produced == out.len() {
+            return Err(malformed("header too long"));
+        }
+        let read = file.read(&mut input)?;
+        if read == 0 {
+            return Err(malformed("truncated header"));
+        }
+        let mut consumed = 0;
+        while consumed < read && produced < out.len() {
+            let (_, used, written) = inflate
+                .once(&input[consumed..read], &mut out[produced..])
+                .map_err(|source| error::Header::Inflate {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+            if used == 0 && written == 0 {
+                break;
+            }
+            consumed += used;
+            produced += written;
+        }
+    };
+
+    parse_header(path, &out[..nul])
+}
+
+/// Like [`loose_header`], but return a [`Reader`] over the remaining data.
+fn loose_reader(path: &Path, file: fs::File) -> Result<Reader, error::Header> {
+    let mut inflate = zlib::stream::inflate::ReadBoxed {
+        inner: io::BufReader::new(file),
+        decompressor: Box::new(zlib::Decompress::new(true)),
+    };
+    let mut header = Vec::with_capacity(32);
+    let mut byte = [0u8; 1];
+    while header.len() < 32 {
+        inflate.read_exact(&mut byte)?;
+        if byte[0] == 0 {
+            let header = parse_header(path, &header)?;
+            return Ok(Reader::new(header, inflate));
+        }
+        header.push(byte[0]);
+    }
+
+    Err(error::Header::Malformed {
+        path: path.to_path_buf(),
+        reason: "header too long",
+    })
+}
+
+/// Parse `<kind> SP <size>`.
+fn parse_header(path: &Path, header: &[u8]) -> Result<Header, error::Header> {
+    let malformed = |reason| error::Header::Malformed {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let sp = header
+        .iter()
+        .position(|b| *b == b' ')
+        .ok_or_else(|| malformed("missing space"))?;
+    let kind = Kind::from_bytes(&header[..sp]).map_err(|_| malformed("invalid kind"))?;
+    let size = std::str::from_utf8(&header[sp + 1..])
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .ok_or_else(|| malformed("invalid size"))?;
+
+    Ok(Header { kind, size })
+}
 
 pub struct Packed<I, D> {
     pub index: I,
@@ -33,4 +224,19 @@ where
         self.index
             .lookup(|info| self.data.get(info), id, buf, cache)
     }
+
+    pub fn header(
+        &self,
+        id: impl AsRef<oid>,
+    ) -> Result<Option<Header>, index::error::Lookup<pack::error::Data>> {
+        self.index.header(|info| self.data.get(info), id)
+    }
+
+    pub fn reader(
+        &self,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Reader>, index::error::Lookup<pack::error::Data>> {
+        self.index.reader(|info| self.data.get(info), id, cache)
+    }
 }
@@ -0,0 +1,132 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Helpers for git's chunk-based file formats, ie. [`multi-pack-index`] and
+//! [`commit-graph`].
+//!
+//! [`multi-pack-index`]: https://git-scm.com/docs/gitformat-pack#_multi_pack_index_midx_files_have_the_following_format
+//! [`commit-graph`]: https://git-scm.com/docs/commit-graph
+
+use std::{cmp::Ordering, convert::TryInto, ops::Range};
+
+use git_hash::oid;
+
+pub(crate) const HASH_LEN: usize = 20;
+
+const ENTRY_LEN: usize = 12;
+const FANOUT_LEN: usize = 256 * 4;
+
+/// The table of contents of a chunk file.
+pub(crate) struct Chunks(Vec<([u8; 4], Range<usize>)>);
+
+impl Chunks {
+    /// Read the table of `num_chunks` entries starting at `start`.
+    ///
+    /// All chunks must lie between the table and the trailing checksum.
+    pub fn read(data: &[u8], start: usize, num_chunks: usize) -> Result<Self, &'static str> {
+        let end = start + (num_chunks + 1) * ENTRY_LEN;
+        if data.len() < end + HASH_LEN {
+            return Err("truncated chunk table");
+        }
+        let mut chunks = Vec::with_capacity(num_chunks);
+        for i in 0..num_chunks {
+            let entry = start + i * ENTRY_LEN;
+            let id: [u8; 4] = data[entry..entry + 4].try_into().unwrap();
+            let ofs = be_u64(&data[entry + 4..entry + 12]) as usize;
+            let next = be_u64(&data[entry + 16..entry + 24]) as usize;
+            if ofs < end || ofs > next || next > data.len() - HASH_LEN {
+                return Err("chunk offset out of bounds");
+            }
+            chunks.push((id, ofs..next));
+        }
+
+        Ok(Self(chunks))
+    }
+
+    pub fn get(&self, id: &[u8]) -> Option<Range<usize>> {
+        self.0
+            .iter()
+            .find(|(cid, _)| cid == id)
+            .map(|(_, range)| range.clone())
+    }
+}
+
+/// A validated fanout table.
+///
+/// Entry `n` is the number of object ids whose first byte is `<= n`, so the
+/// entries must be non-decreasing.
+pub(crate) struct Fanout(Range<usize>);
+
+impl Fanout {
+    pub fn new(data: &[u8], range: Range<usize>) -> Result<Self, &'static str> {
+        if range.len() != FANOUT_LEN {
+            return Err("invalid fanout table size");
+        }
+        let monotonic = data[range.clone()]
+            .chunks(4)
+            .map(be_u32)
+            .try_fold(0, |prev, n| if n < prev { None } else { Some(n) })
+            .is_some();
+        if !monotonic {
+            return Err("fanout table is not monotonic");
+        }
+
+        Ok(Self(range))
+    }
+
+    /// The total number of object ids, ie. the last entry.
+    pub fn total(&self, data: &[u8]) -> u32 {
+        self.at(data, 255)
+    }
+
+    /// Binary search for `id` in the sorted table of object ids, where
+    /// `id_at` returns the id at a position.
+    pub fn search<'a, F>(&self, data: &[u8], id: &oid, id_at: F) -> Option<u32>
+    where
+        F: Fn(u32) -> &'a oid,
+    {
+        let id = id.as_bytes();
+        let first = id[0] as usize;
+        let mut lo = if first == 0 {
+            0
+        } else {
+            self.at(data, first - 1)
+        };
+        let mut hi = self.at(data, first);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match id_at(mid).as_bytes().cmp(id) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+
+        None
+    }
+
+    fn at(&self, data: &[u8], n: usize) -> u32 {
+        let start = self.0.start + n * 4;
+        be_u32(&data[start..start + 4])
+    }
+}
+
+/// Interpret `bytes` as a SHA-1 object id.
+///
+/// # Panics
+///
+/// If `bytes` is not [`HASH_LEN`] long. Callers validate table sizes when
+/// loading a file.
+pub(crate) fn oid_at(bytes: &[u8]) -> &oid {
+    oid::try_from(bytes).expect("slice of length 20")
+}
+
+pub(crate) fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().expect("slice of length 4"))
+}
+
+pub(crate) fn be_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().expect("slice of length 8"))
+}
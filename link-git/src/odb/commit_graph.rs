@@ -0,0 +1,409 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Reader for git's [`commit-graph`] files, including split commit-graph
+//! chains.
+//!
+//! Only the topological levels stored in the `CDAT` chunk are used as
+//! generation numbers. The corrected commit dates of the `GDAT` and `GDOV`
+//! chunks written by newer versions of git are ignored: topological levels
+//! are always present and remain valid for pruning reachability walks, they
+//! just prune less aggressively.
+//!
+//! [`commit-graph`]: https://git-scm.com/docs/commit-graph
+
+use std::{
+    fs,
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use git_hash::{oid, ObjectId};
+use tracing::trace;
+
+use super::chunk::{be_u32, oid_at, Chunks, Fanout, HASH_LEN};
+
+const SIGNATURE: &[u8] = b"CGPH";
+const HEADER_LEN: usize = 8;
+const COMMIT_DATA_LEN: usize = HASH_LEN + 16;
+
+const CHUNK_FANOUT: &[u8] = b"OIDF";
+const CHUNK_OIDS: &[u8] = b"OIDL";
+const CHUNK_COMMIT_DATA: &[u8] = b"CDAT";
+const CHUNK_EXTRA_EDGES: &[u8] = b"EDGE";
+const CHUNK_BASE_GRAPHS: &[u8] = b"BASE";
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const PARENT_EXTRA_EDGES: u32 = 0x8000_0000;
+const LAST_EDGE: u32 = 0x8000_0000;
+
+/// Generation number of commits which are not in the commit-graph.
+pub const GENERATION_INFINITY: u32 = u32::MAX;
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Open {
+        #[error("{path:?}: invalid signature")]
+        Signature { path: PathBuf },
+
+        #[error("{path:?}: unsupported version {version}")]
+        Version { path: PathBuf, version: u8 },
+
+        #[error("{path:?}: unsupported hash version {version}")]
+        HashVersion { path: PathBuf, version: u8 },
+
+        #[error("{path:?}: missing required chunk {chunk}")]
+        MissingChunk { path: PathBuf, chunk: String },
+
+        #[error("{path:?}: malformed: {reason}")]
+        Malformed { path: PathBuf, reason: &'static str },
+
+        #[error("commit-graph chain does not match the base graphs recorded in {path:?}")]
+        Chain { path: PathBuf },
+
+        #[error("{path:?}: too many commits in commit-graph chain")]
+        TooManyCommits { path: PathBuf },
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Commit {
+        #[error("position {0} out of bounds")]
+        OutOfBounds(u32),
+
+        #[error("{path:?}: malformed commit at position {pos}: {reason}")]
+        Malformed {
+            path: PathBuf,
+            pos: u32,
+            reason: &'static str,
+        },
+    }
+}
+
+/// Position of a commit in a [`Graph`].
+///
+/// Positions are global to all files in a split commit-graph chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position(pub u32);
+
+/// The data stored about a commit in the commit-graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<Position>,
+    /// The topological level of the commit, ie. one more than the maximum
+    /// generation of its parents (root commits have generation 1).
+    ///
+    /// Commit-graphs written by very old versions of git may store zero here,
+    /// in which case the generation is unknown.
+    pub generation: u32,
+    /// The committer time, in seconds since the epoch.
+    pub commit_time: u64,
+}
+
+/// A single commit-graph file.
+pub struct File {
+    path: PathBuf,
+    data: Vec<u8>,
+    num_commits: u32,
+    fanout: Fanout,
+    oids: Range<usize>,
+    commit_data: Range<usize>,
+    extra_edges: Option<Range<usize>>,
+    base_graphs: Vec<ObjectId>,
+}
+
+impl File {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, error::Open> {
+        let path = path.into();
+        let data = fs::read(&path)?;
+        Self::from_bytes(path, data)
+    }
+
+    fn from_bytes(path: PathBuf, data: Vec<u8>) -> Result<Self, error::Open> {
+        macro_rules! malformed {
+            ($reason:expr) => {
+                return Err(error::Open::Malformed {
+                    path,
+                    reason: $reason,
+                })
+            };
+        }
+
+        if data.len() < HEADER_LEN + HASH_LEN {
+            malformed!("file too short")
+        }
+        if &data[..4] != SIGNATURE {
+            return Err(error::Open::Signature { path });
+        }
+        match data[4] {
+            1 => {},
+            version => return Err(error::Open::Version { path, version }),
+        }
+        match data[5] {
+            1 => {},
+            version => return Err(error::Open::HashVersion { path, version }),
+        }
+        let num_chunks = data[6] as usize;
+        let num_base_graphs = data[7] as usize;
+
+        let chunks = match Chunks::read(&data, HEADER_LEN, num_chunks) {
+            Ok(chunks) => chunks,
+            Err(reason) => malformed!(reason),
+        };
+        let require = |id: &[u8]| {
+            chunks.get(id).ok_or_else(|| error::Open::MissingChunk {
+                path: path.clone(),
+                chunk: String::from_utf8_lossy(id).into_owned(),
+            })
+        };
+
+        let fanout = require(CHUNK_FANOUT)?;
+        let oids = require(CHUNK_OIDS)?;
+        let commit_data = require(CHUNK_COMMIT_DATA)?;
+        let extra_edges = chunks.get(CHUNK_EXTRA_EDGES);
+        let base = chunks.get(CHUNK_BASE_GRAPHS);
+
+        let fanout = match Fanout::new(&data, fanout) {
+            Ok(fanout) => fanout,
+            Err(reason) => malformed!(reason),
+        };
+        let num_commits = fanout.total(&data);
+        if oids.len() != num_commits as usize * HASH_LEN {
+            malformed!("object id table does not match fanout")
+        }
+        if commit_data.len() != num_commits as usize * COMMIT_DATA_LEN {
+            malformed!("commit data table does not match fanout")
+        }
+        let base_graphs = match base {
+            None if num_base_graphs == 0 => Vec::new(),
+            Some(base) if base.len() == num_base_graphs * HASH_LEN => data[base]
+                .chunks(HASH_LEN)
+                .map(|hash| oid_at(hash).to_owned())
+                .collect(),
+            _ => malformed!("base graphs chunk does not match header"),
+        };
+
+        Ok(Self {
+            path,
+            data,
+            num_commits,
+            fanout,
+            oids,
+            commit_data,
+            extra_edges,
+            base_graphs,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn num_commits(&self) -> u32 {
+        self.num_commits
+    }
+
+    /// The checksum over the file contents, as stored in the trailer.
+    pub fn checksum(&self) -> &oid {
+        oid_at(&self.data[self.data.len() - HASH_LEN..])
+    }
+
+    /// Local position of `id` in this file.
+    fn position(&self, id: &oid) -> Option<u32> {
+        self.fanout.search(&self.data, id, |pos| self.id_at(pos))
+    }
+
+    fn id_at(&self, pos: u32) -> &oid {
+        let start = self.oids.start + pos as usize * HASH_LEN;
+        oid_at(&self.data[start..start + HASH_LEN])
+    }
+
+    /// Commit data at local position `pos`. Parent positions are global, and
+    /// must be below `end`, the global position following this file.
+    fn commit_at(&self, pos: u32, end: u32) -> Result<Commit, &'static str> {
+        let start = self.commit_data.start + pos as usize * COMMIT_DATA_LEN;
+        let entry = &self.data[start..start + COMMIT_DATA_LEN];
+
+        let tree = oid_at(&entry[..HASH_LEN]).to_owned();
+        let parent1 = be_u32(&entry[HASH_LEN..HASH_LEN + 4]);
+        let parent2 = be_u32(&entry[HASH_LEN + 4..HASH_LEN + 8]);
+        let gen_and_time = be_u32(&entry[HASH_LEN + 8..HASH_LEN + 12]);
+        let time_lo = be_u32(&entry[HASH_LEN + 12..HASH_LEN + 16]);
+
+        let mut parents = Vec::new();
+        if parent1 != PARENT_NONE {
+            parents.push(Position(parent1));
+        }
+        if parent2 != PARENT_NONE {
+            if parent2 & PARENT_EXTRA_EDGES == 0 {
+                parents.push(Position(parent2));
+            } else {
+                self.extra_edges(parent2 & !PARENT_EXTRA_EDGES, &mut parents)?;
+            }
+        }
+        if parents.iter().any(|p| p.0 >= end) {
+            return Err("invalid parent position");
+        }
+
+        Ok(Commit {
+            tree,
+            parents,
+            generation: gen_and_time >> 2,
+            commit_time: (((gen_and_time & 0x3) as u64) << 32) | time_lo as u64,
+        })
+    }
+
+    fn extra_edges(&self, idx: u32, parents: &mut Vec<Position>) -> Result<(), &'static str> {
+        let edges = self
+            .extra_edges
+            .as_ref()
+            .ok_or("extra edges referenced, but no extra edges chunk")?;
+        let mut edges = self.data[edges.clone()]
+            .chunks_exact(4)
+            .skip(idx as usize)
+            .map(be_u32);
+        loop {
+            let edge = edges.next().ok_or("extra edges out of bounds")?;
+            parents.push(Position(edge & !LAST_EDGE));
+            if edge & LAST_EDGE != 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// A commit-graph, possibly consisting of a chain of [`File`]s.
+pub struct Graph {
+    /// Files in chain order, ie. the base graph first.
+    files: Vec<File>,
+    /// Global position of the first commit in each of `files`.
+    offsets: Vec<u32>,
+}
+
+impl Graph {
+    /// Open the commit-graph of the object directory `objects_dir`, if it
+    /// has one.
+    ///
+    /// A split commit-graph chain (`info/commit-graphs/commit-graph-chain`)
+    /// takes precedence over a single `info/commit-graph` file, as it does in
+    /// git.
+    pub fn open(objects_dir: impl AsRef<Path>) -> Result<Option<Self>, error::Open> {
+        let info = objects_dir.as_ref().join("info");
+
+        let chain_dir = info.join("commit-graphs");
+        let chain_path = chain_dir.join("commit-graph-chain");
+        match fs::read_to_string(&chain_path) {
+            Ok(chain) => {
+                trace!("reading commit-graph chain {}", chain_path.display());
+                let files = chain
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(|hash| File::open(chain_dir.join(format!("graph-{}.graph", hash))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return Self::from_files(files).map(Some);
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into()),
+        }
+
+        let single = info.join("commit-graph");
+        match File::open(&single) {
+            Ok(file) => Self::from_files(vec![file]).map(Some),
+            Err(error::Open::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn from_files(files: Vec<File>) -> Result<Self, error::Open> {
+        let mut offsets = Vec::with_capacity(files.len());
+        let mut total = 0u32;
+        for (i, file) in files.iter().enumerate() {
+            let expected = files[..i].iter().map(|f| f.checksum());
+            if file.base_graphs.len() != i
+                || !file.base_graphs.iter().map(|b| b.as_ref()).eq(expected)
+            {
+                return Err(error::Open::Chain {
+                    path: file.path.clone(),
+                });
+            }
+            offsets.push(total);
+            total =
+                total
+                    .checked_add(file.num_commits)
+                    .ok_or_else(|| error::Open::TooManyCommits {
+                        path: file.path.clone(),
+                    })?;
+        }
+
+        Ok(Self { files, offsets })
+    }
+
+    /// Total number of commits in the graph.
+    pub fn len(&self) -> u32 {
+        self.files.iter().map(|f| f.num_commits).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+
+    pub fn lookup(&self, id: impl AsRef<oid>) -> Option<Position> {
+        let id = id.as_ref();
+        self.files
+            .iter()
+            .zip(&self.offsets)
+            .rev()
+            .find_map(|(file, ofs)| file.position(id).map(|pos| Position(ofs + pos)))
+    }
+
+    pub fn contains(&self, id: impl AsRef<oid>) -> bool {
+        self.lookup(id).is_some()
+    }
+
+    /// The commit id at `pos`, or `None` if `pos` is out of bounds.
+    pub fn id_at(&self, pos: Position) -> Option<&oid> {
+        let (i, local) = self.locate(pos)?;
+        Some(self.files[i].id_at(local))
+    }
+
+    /// The commit at `pos`.
+    ///
+    /// The parent positions of the returned [`Commit`] are guaranteed to be in
+    /// bounds.
+    pub fn commit_at(&self, pos: Position) -> Result<Commit, error::Commit> {
+        let (i, local) = self.locate(pos).ok_or(error::Commit::OutOfBounds(pos.0))?;
+        let file = &self.files[i];
+        // Commits can only have parents in the same file, or in its bases
+        file.commit_at(local, self.offsets[i] + file.num_commits)
+            .map_err(|reason| error::Commit::Malformed {
+                path: file.path.clone(),
+                pos: pos.0,
+                reason,
+            })
+    }
+
+    /// Index into `files` and local position of `pos`.
+    fn locate(&self, pos: Position) -> Option<(usize, u32)> {
+        if pos.0 >= self.len() {
+            return None;
+        }
+        // Files without any commits share their offset with the next file.
+        // Picking the last file starting at or before `pos` skips them.
+        let i = self.offsets.partition_point(|ofs| *ofs <= pos.0) - 1;
+        Some((i, pos.0 - self.offsets[i]))
+    }
+}
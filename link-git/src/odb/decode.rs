@@ -0,0 +1,230 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! A [`DecodeEntry`] cache which can be shared between threads.
+
+use std::{
+    hash::{Hash as _, Hasher as _},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use git_object::Kind;
+use git_pack::cache::DecodeEntry;
+use parking_lot::Mutex;
+use rustc_hash::FxHasher;
+
+use super::lru::LruMap;
+
+mod metrics;
+pub use metrics::{Metrics, Stats, StatsView};
+
+/// Resource limits of a [`Sharded`] cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum total size in bytes of the decoded objects held by the cache.
+    pub bytes: u64,
+    /// Objects larger than this many bytes are not cached.
+    pub max_entry: u64,
+    /// Number of shards. Each shard is guarded by its own lock, and may hold
+    /// up to `bytes / shards` bytes.
+    pub shards: usize,
+}
+
+impl Default for Limits {
+    /// 256MiB, entries up to 16MiB, 32 shards
+    fn default() -> Self {
+        Self {
+            bytes: 256 * 1024 * 1024,
+            max_entry: 16 * 1024 * 1024,
+            shards: 32,
+        }
+    }
+}
+
+/// A size-bounded cache of decoded pack entries (typically delta bases),
+/// which can be shared between threads and repositories.
+///
+/// The cache is accessed through [`Handle`]s, obtained via [`Sharded::handle`],
+/// which implement [`DecodeEntry`]. Entries are keyed by the pack id and offset
+/// passed in by `git-pack`, as well as by the handle they were inserted
+/// through. The pack id is a CRC32 of the pack's path, and thus not unique
+/// across repositories: each call to [`Sharded::handle`] therefore yields a
+/// handle with its own namespace within the cache. Obtain one handle per
+/// [`super::Odb`], and clone it to use it from multiple threads -- clones share
+/// their entries, while separately obtained handles never see each other's
+/// entries.
+///
+/// The cache is divided into [`Limits::shards`] independently locked shards,
+/// each of which evicts its least recently used entries when its share of
+/// [`Limits::bytes`] would be exceeded.
+pub struct Sharded<M> {
+    limits: Limits,
+    shards: Box<[Mutex<Shard>]>,
+    handles: AtomicU64,
+    stats: M,
+}
+
+/// Handle id, pack id, offset
+type Key = (u64, u32, u64);
+
+#[derive(Default)]
+struct Shard {
+    entries: LruMap<Key, Entry>,
+}
+
+struct Entry {
+    data: Vec<u8>,
+    kind: Kind,
+    compressed_size: usize,
+}
+
+impl Shard {
+    fn get(&mut self, key: &Key, out: &mut Vec<u8>) -> Option<(Kind, usize)> {
+        let entry = self.entries.get(key)?;
+        out.clear();
+        out.extend_from_slice(&entry.data);
+        Some((entry.kind, entry.compressed_size))
+    }
+
+    fn put<M: Metrics>(&mut self, budget: u64, key: Key, entry: Entry, stats: &M) {
+        self.entries.remove(&key);
+        let size = entry.data.len() as u64;
+        while !self.entries.is_empty() && self.entries.bytes() + size > budget {
+            if self.entries.pop_oldest().is_some() {
+                stats.record_eviction();
+            }
+        }
+        self.entries.insert(key, entry, size);
+    }
+}
+
+impl Sharded<()> {
+    /// # Panics
+    ///
+    /// If `limits.shards` is zero.
+    pub fn new(limits: Limits) -> Self {
+        assert!(limits.shards > 0, "number of shards must be non-zero");
+        Self {
+            limits,
+            shards: (0..limits.shards)
+                .map(|_| Mutex::new(Shard::default()))
+                .collect(),
+            handles: AtomicU64::new(0),
+            stats: (),
+        }
+    }
+}
+
+impl Default for Sharded<()> {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl<M> Sharded<M>
+where
+    M: Metrics,
+{
+    pub fn with_stats(self) -> Sharded<Stats> {
+        self.with_metrics(Stats::default())
+    }
+
+    pub fn with_metrics<N: Metrics>(self, m: N) -> Sharded<N> {
+        Sharded {
+            limits: self.limits,
+            shards: self.shards,
+            handles: self.handles,
+            stats: m,
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn stats(&self) -> M::Snapshot {
+        let (entries, bytes) = self.shards.iter().fold((0, 0), |(entries, bytes), shard| {
+            let shard = shard.lock();
+            (entries + shard.entries.len(), bytes + shard.entries.bytes())
+        });
+        self.stats.snapshot(entries, bytes)
+    }
+
+    /// A new [`Handle`] to the cache, with its own namespace of entries.
+    pub fn handle(self: &Arc<Self>) -> Handle<M> {
+        Handle {
+            cache: Arc::clone(self),
+            id: self.handles.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn get(&self, key: Key, out: &mut Vec<u8>) -> Option<(Kind, usize)> {
+        match self.shard(&key).lock().get(&key, out) {
+            Some(hit) => {
+                self.stats.record_hit();
+                Some(hit)
+            },
+            None => {
+                self.stats.record_miss();
+                None
+            },
+        }
+    }
+
+    fn put(&self, key: Key, data: &[u8], kind: Kind, compressed_size: usize) {
+        let size = data.len() as u64;
+        let budget = self.limits.bytes / self.shards.len() as u64;
+        if size > self.limits.max_entry || size > budget {
+            return;
+        }
+
+        let entry = Entry {
+            data: data.to_vec(),
+            kind,
+            compressed_size,
+        };
+        self.shard(&key).lock().put(budget, key, entry, &self.stats)
+    }
+
+    fn shard(&self, key: &Key) -> &Mutex<Shard> {
+        let mut hasher = FxHasher::default();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+}
+
+/// An owned, cheaply cloneable [`DecodeEntry`] handle to a [`Sharded`] cache.
+///
+/// Clones of a handle share the same entries.
+pub struct Handle<M> {
+    cache: Arc<Sharded<M>>,
+    id: u64,
+}
+
+impl<M> Clone for Handle<M> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: Arc::clone(&self.cache),
+            id: self.id,
+        }
+    }
+}
+
+impl<M> DecodeEntry for Handle<M>
+where
+    M: Metrics,
+{
+    fn put(&mut self, pack_id: u32, offset: u64, data: &[u8], kind: Kind, compressed_size: usize) {
+        self.cache
+            .put((self.id, pack_id, offset), data, kind, compressed_size)
+    }
+
+    fn get(&mut self, pack_id: u32, offset: u64, out: &mut Vec<u8>) -> Option<(Kind, usize)> {
+        self.cache.get((self.id, pack_id, offset), out)
+    }
+}
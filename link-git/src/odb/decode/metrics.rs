@@ -0,0 +1,78 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tracing::trace;
+
+pub struct StatsView {
+    /// Total number of times a delta base was found in the cache.
+    pub cache_hits: usize,
+    /// Total number of times a delta base was not found in the cache.
+    pub cache_misses: usize,
+    /// Total number of entries which were evicted from the cache to make room
+    /// for others.
+    pub evictions: usize,
+    /// Number of entries currently held by the cache.
+    pub entries: usize,
+    /// Total size in bytes of the entries currently held by the cache.
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+pub struct Stats {
+    hits: AtomicUsize,
+    miss: AtomicUsize,
+    evict: AtomicUsize,
+}
+
+pub trait Metrics {
+    type Snapshot;
+
+    fn record_hit(&self);
+    fn record_miss(&self);
+    fn record_eviction(&self);
+
+    fn snapshot(&self, entries: usize, bytes: u64) -> Self::Snapshot;
+}
+
+impl Metrics for Stats {
+    type Snapshot = StatsView;
+
+    fn record_hit(&self) {
+        trace!("decode cache hit");
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_miss(&self) {
+        trace!("decode cache miss");
+        self.miss.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_eviction(&self) {
+        trace!("decode cache eviction");
+        self.evict.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, entries: usize, bytes: u64) -> Self::Snapshot {
+        StatsView {
+            cache_hits: self.hits.load(Ordering::Relaxed),
+            cache_misses: self.miss.load(Ordering::Relaxed),
+            evictions: self.evict.load(Ordering::Relaxed),
+            entries,
+            bytes,
+        }
+    }
+}
+
+impl Metrics for () {
+    type Snapshot = ();
+
+    fn record_hit(&self) {}
+    fn record_miss(&self) {}
+    fn record_eviction(&self) {}
+
+    fn snapshot(&self, _: usize, _: u64) -> Self::Snapshot {}
+}
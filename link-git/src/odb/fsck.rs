@@ -0,0 +1,619 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Integrity verification of an [`Odb`], akin to [`git-fsck`].
+//!
+//! Unlike most other operations, verification does not stop at the first
+//! error: all [`Problem`]s found are collected into a [`Report`].
+//!
+//! [`git-fsck`]: https://git-scm.com/docs/git-fsck
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+};
+
+use git_features::hash::Sha1;
+use git_hash::{oid, ObjectId};
+use git_object::Kind;
+use git_pack::{cache::DecodeEntry, data::ResolvedBase};
+use git_ref::{FullName, Target};
+use tracing::{debug, trace};
+
+use super::{index, pack, window, Odb};
+use crate::refdb;
+
+pub mod syntax;
+
+pub mod error {
+    use super::*;
+    use git_ref::file::iter::loose_then_packed;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Decode {
+        #[error(transparent)]
+        Entry(#[from] pack::error::Header),
+
+        #[error(transparent)]
+        Packed(#[from] git_pack::data::decode_entry::Error),
+
+        #[error(transparent)]
+        Loose(#[from] git_odb::loose::find::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Refs {
+        #[error(transparent)]
+        Io(#[from] io::Error),
+
+        #[error(transparent)]
+        Iter(#[from] loose_then_packed::Error),
+    }
+}
+
+/// What to verify.
+#[derive(Clone, Copy)]
+pub struct Options<'a> {
+    /// Verify the checksums of all pack and index files, and the consistency
+    /// of each index with its pack.
+    pub checksums: bool,
+    /// Decode and re-hash every packed object, and check its syntax.
+    pub packed: bool,
+    /// Decode and re-hash every loose object, and check its syntax.
+    pub loose: bool,
+    /// Check that all objects reachable from the refs in the given
+    /// [`refdb::Snapshot`] are present.
+    pub connectivity: Option<&'a refdb::Snapshot>,
+}
+
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Self {
+            checksums: true,
+            packed: true,
+            loose: true,
+            connectivity: None,
+        }
+    }
+}
+
+/// Where an object was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    Loose { path: PathBuf },
+    Packed { pack: PathBuf, ofs: u64 },
+}
+
+/// What refers to a missing object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Referrer {
+    Ref(FullName),
+    Object(ObjectId),
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    /// An I/O error prevented checking `path`.
+    Io { path: PathBuf, error: io::Error },
+    /// The trailing checksum of a pack file does not match its contents.
+    PackChecksum {
+        path: PathBuf,
+        expected: ObjectId,
+        actual: ObjectId,
+    },
+    /// The trailing checksum of a pack index file does not match its
+    /// contents.
+    IndexChecksum {
+        path: PathBuf,
+        expected: ObjectId,
+        actual: ObjectId,
+    },
+    /// The pack checksum recorded in a pack index does not match the pack.
+    IndexPackMismatch {
+        index: PathBuf,
+        pack: PathBuf,
+        expected: ObjectId,
+        actual: ObjectId,
+    },
+    /// The number of objects in a pack index does not match the pack header.
+    ObjectCount {
+        index: PathBuf,
+        pack: PathBuf,
+        in_index: u32,
+        in_pack: u32,
+    },
+    /// The pack or index file is too short or otherwise malformed.
+    Malformed { path: PathBuf, reason: &'static str },
+    /// The pack data file could not be loaded.
+    Open {
+        path: PathBuf,
+        error: pack::error::Data,
+    },
+    /// The object could not be decoded.
+    Decode {
+        id: ObjectId,
+        location: Location,
+        error: error::Decode,
+    },
+    /// The object's contents do not hash to its id.
+    Hash {
+        id: ObjectId,
+        actual: ObjectId,
+        location: Location,
+    },
+    /// The object is malformed.
+    Syntax {
+        id: ObjectId,
+        kind: Kind,
+        reason: &'static str,
+    },
+    /// An object reachable from a ref could not be looked up.
+    Lookup { id: ObjectId, error: super::Error },
+    /// An object reachable from a ref is missing.
+    Missing { id: ObjectId, referrer: Referrer },
+    /// An object is not of the kind its referrer claims it is.
+    KindMismatch {
+        id: ObjectId,
+        expected: Kind,
+        actual: Kind,
+        referrer: Referrer,
+    },
+    /// Reading refs failed.
+    Refs { error: error::Refs },
+}
+
+/// The outcome of [`Odb::verify`].
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Number of pack files verified.
+    pub packs: usize,
+    /// Number of packed objects decoded.
+    pub packed_objects: usize,
+    /// Number of loose objects decoded.
+    pub loose_objects: usize,
+    /// Number of refs considered for connectivity.
+    pub refs: usize,
+    /// Number of objects reached by the connectivity check.
+    pub reachable: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    /// `true` if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn problem(&mut self, p: Problem) {
+        debug!("fsck: {:?}", p);
+        self.problems.push(p)
+    }
+}
+
+impl<I, D> Odb<I, D>
+where
+    I: index::Index,
+    D: window::Cache,
+{
+    /// Verify the integrity of the object database.
+    ///
+    /// Errors which prevent verification altogether (such as failing to
+    /// enumerate the packs directory) are returned as `Err`, everything else
+    /// is reported in the [`Report`].
+    pub fn verify(
+        &self,
+        opts: Options,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Report, index::error::Discover> {
+        let mut report = Report::default();
+        let mut buf = Vec::new();
+
+        if opts.checksums || opts.packed {
+            for idx in self.packed.index.packs()? {
+                report.packs += 1;
+                if opts.checksums {
+                    verify_checksums(&idx, &mut report);
+                }
+                if opts.packed {
+                    self.verify_packed(&idx, &mut buf, cache, &mut report);
+                }
+            }
+        }
+
+        if opts.loose {
+            for store in self.loose.stores() {
+                verify_loose(store, &mut buf, &mut report);
+            }
+        }
+
+        if let Some(refs) = opts.connectivity {
+            self.verify_connectivity(refs, &mut buf, cache, &mut report);
+        }
+
+        Ok(report)
+    }
+
+    fn verify_packed(
+        &self,
+        idx: &pack::Index,
+        buf: &mut Vec<u8>,
+        cache: &mut impl DecodeEntry,
+        report: &mut Report,
+    ) {
+        let pack_path = idx.info.data_path.clone();
+        let data = match self.packed.data.get(&idx.info) {
+            Ok(data) => data,
+            Err(e) => {
+                report.problem(Problem::Open {
+                    path: pack_path,
+                    error: e,
+                });
+                return;
+            },
+        };
+        let pack = data.file();
+
+        trace!("verifying objects in {}", pack_path.display());
+        for (id, ofs) in idx.iter() {
+            let location = || Location::Packed {
+                pack: pack_path.clone(),
+                ofs,
+            };
+            report.packed_objects += 1;
+            // The index may point past the end of the pack, or into the
+            // middle of an entry
+            let kind = match data
+                .entry(ofs)
+                .map_err(error::Decode::from)
+                .and_then(|entry| {
+                    pack.decode_entry(
+                        entry,
+                        buf,
+                        |id, _| {
+                            idx.ofs(id)
+                                .and_then(|ofs| data.entry(ofs).ok())
+                                .map(ResolvedBase::InPack)
+                        },
+                        cache,
+                    )
+                    .map_err(error::Decode::from)
+                }) {
+                Ok(out) => out.kind,
+                Err(error) => {
+                    report.problem(Problem::Decode {
+                        id,
+                        location: location(),
+                        error,
+                    });
+                    continue;
+                },
+            };
+            verify_object(id, kind, buf, location, report);
+        }
+    }
+
+    fn verify_connectivity(
+        &self,
+        refs: &refdb::Snapshot,
+        buf: &mut Vec<u8>,
+        cache: &mut impl DecodeEntry,
+        report: &mut Report,
+    ) {
+        let mut todo = Vec::new();
+        match refs.iter(None::<&Path>) {
+            Err(e) => report.problem(Problem::Refs { error: e.into() }),
+            Ok(iter) => {
+                for r in iter {
+                    match r {
+                        Err(e) => report.problem(Problem::Refs { error: e.into() }),
+                        Ok(r) => {
+                            report.refs += 1;
+                            if let Target::Peeled(id) = r.target {
+                                todo.push((id, None, Referrer::Ref(r.name)));
+                            }
+                        },
+                    }
+                }
+            },
+        }
+
+        let mut seen = HashSet::new();
+        while let Some((id, expected, referrer)) = todo.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            // Blobs have no outgoing links, so there is no need to inflate them
+            if expected == Some(Kind::Blob) {
+                if self.contains(id) {
+                    report.reachable += 1;
+                } else {
+                    report.problem(Problem::Missing { id, referrer });
+                }
+                continue;
+            }
+
+            let obj = match self.find(id, buf, cache) {
+                Ok(Some(obj)) => obj,
+                Ok(None) => {
+                    report.problem(Problem::Missing { id, referrer });
+                    continue;
+                },
+                Err(error) => {
+                    report.problem(Problem::Lookup { id, error });
+                    continue;
+                },
+            };
+            report.reachable += 1;
+
+            let kind = obj.kind;
+            if let Some(expected) = expected {
+                if expected != kind {
+                    report.problem(Problem::KindMismatch {
+                        id,
+                        expected,
+                        actual: kind,
+                        referrer,
+                    });
+                    continue;
+                }
+            }
+            match syntax::check(kind, obj.data) {
+                Err(reason) => report.problem(Problem::Syntax { id, kind, reason }),
+                Ok(links) => {
+                    for link in links {
+                        // Submodule commits are not expected to be present
+                        if let Some(kind) = link.kind {
+                            todo.push((link.id, Some(kind), Referrer::Object(id)));
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Verify the loose objects of `store` only, so that objects shadowed by an
+/// earlier object directory are checked, too.
+fn verify_loose(store: &git_odb::loose::Store, buf: &mut Vec<u8>, report: &mut Report) {
+    trace!("verifying loose objects in {}", store.path.display());
+    for (id, path) in loose_objects(&store.path, report) {
+        let location = || Location::Loose { path: path.clone() };
+        report.loose_objects += 1;
+        match store.try_find(id, buf) {
+            Ok(Some(obj)) => verify_object(id, obj.kind, obj.data, location, report),
+            // Deleted concurrently
+            Ok(None) => {},
+            Err(e) => report.problem(Problem::Decode {
+                id,
+                location: location(),
+                error: e.into(),
+            }),
+        }
+    }
+}
+
+fn verify_object<L>(id: ObjectId, kind: Kind, data: &[u8], location: L, report: &mut Report)
+where
+    L: Fn() -> Location,
+{
+    let actual = hash_object(kind, data);
+    if actual != id {
+        report.problem(Problem::Hash {
+            id,
+            actual,
+            location: location(),
+        });
+        return;
+    }
+    if let Err(reason) = syntax::check(kind, data) {
+        report.problem(Problem::Syntax { id, kind, reason });
+    }
+}
+
+fn hash_object(kind: Kind, data: &[u8]) -> ObjectId {
+    let mut hasher = Sha1::default();
+    hasher.update(kind.as_bytes());
+    hasher.update(b" ");
+    hasher.update(data.len().to_string().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(data);
+    ObjectId::from(hasher.digest())
+}
+
+/// Verify pack and index trailers, and the consistency between the two.
+fn verify_checksums(idx: &pack::Index, report: &mut Report) {
+    let idx_path = idx.path().to_path_buf();
+    let pack_path = idx.info.data_path.clone();
+
+    let pack = match hash_file(&pack_path, 12) {
+        Err(error) => {
+            report.problem(Problem::Io {
+                path: pack_path,
+                error,
+            });
+            return;
+        },
+        Ok(None) => {
+            report.problem(Problem::Malformed {
+                path: pack_path,
+                reason: "pack file too short",
+            });
+            return;
+        },
+        Ok(Some(pack)) => pack,
+    };
+    if pack.actual != pack.trailer {
+        report.problem(Problem::PackChecksum {
+            path: pack_path.clone(),
+            expected: pack.trailer,
+            actual: pack.actual,
+        });
+    }
+    if &pack.head[..4] != b"PACK" {
+        report.problem(Problem::Malformed {
+            path: pack_path.clone(),
+            reason: "invalid pack signature",
+        });
+    }
+    let in_pack = u32::from_be_bytes([pack.head[8], pack.head[9], pack.head[10], pack.head[11]]);
+    let in_index = idx.num_objects();
+    if in_pack != in_index {
+        report.problem(Problem::ObjectCount {
+            index: idx_path.clone(),
+            pack: pack_path.clone(),
+            in_index,
+            in_pack,
+        });
+    }
+
+    // The index ends with the pack checksum, followed by its own checksum
+    match fs::read(&idx_path) {
+        Err(error) => report.problem(Problem::Io {
+            path: idx_path,
+            error,
+        }),
+        Ok(bytes) if bytes.len() < 40 => report.problem(Problem::Malformed {
+            path: idx_path,
+            reason: "index file too short",
+        }),
+        Ok(bytes) => {
+            let (content, trailer) = bytes.split_at(bytes.len() - 20);
+            let checksums = oid::try_from(trailer).and_then(|expected| {
+                Ok((expected, oid::try_from(&content[content.len() - 20..])?))
+            });
+            let (expected, recorded) = match checksums {
+                Ok((expected, recorded)) => (expected.to_owned(), recorded.to_owned()),
+                Err(_) => {
+                    report.problem(Problem::Malformed {
+                        path: idx_path,
+                        reason: "invalid checksum length",
+                    });
+                    return;
+                },
+            };
+
+            let mut hasher = Sha1::default();
+            hasher.update(content);
+            let actual = ObjectId::from(hasher.digest());
+            if actual != expected {
+                report.problem(Problem::IndexChecksum {
+                    path: idx_path.clone(),
+                    expected,
+                    actual,
+                });
+            }
+
+            if recorded != pack.trailer {
+                report.problem(Problem::IndexPackMismatch {
+                    index: idx_path,
+                    pack: pack_path,
+                    expected: recorded,
+                    actual: pack.trailer,
+                });
+            }
+        },
+    }
+}
+
+struct FileHash {
+    /// The first `head_len` bytes of the file.
+    head: Vec<u8>,
+    /// The checksum stored in the last 20 bytes of the file.
+    trailer: ObjectId,
+    /// The checksum computed over all but the last 20 bytes of the file.
+    actual: ObjectId,
+}
+
+/// Compute the SHA-1 over all but the last 20 bytes of the file at `path`,
+/// without reading it into memory all at once.
+///
+/// Returns `None` if the file is shorter than `head_len` plus the trailer.
+fn hash_file(path: &Path, head_len: usize) -> io::Result<Option<FileHash>> {
+    const TRAILER: usize = 20;
+
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len < head_len + TRAILER {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha1::default();
+    let mut head = vec![0; head_len];
+    file.read_exact(&mut head)?;
+    hasher.update(&head);
+
+    let mut remaining = len - head_len - TRAILER;
+    let mut chunk = vec![0; 64 * 1024];
+    while remaining > 0 {
+        let n = remaining.min(chunk.len());
+        file.read_exact(&mut chunk[..n])?;
+        hasher.update(&chunk[..n]);
+        remaining -= n;
+    }
+    let mut trailer = [0; TRAILER];
+    file.read_exact(&mut trailer)?;
+
+    Ok(Some(FileHash {
+        head,
+        trailer: ObjectId::from(trailer),
+        actual: ObjectId::from(hasher.digest()),
+    }))
+}
+
+/// Enumerate the loose objects in `objects_dir`.
+fn loose_objects(objects_dir: &Path, report: &mut Report) -> Vec<(ObjectId, PathBuf)> {
+    let mut objects = Vec::new();
+    let fanout = match fs::read_dir(objects_dir) {
+        Ok(iter) => iter,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return objects,
+        Err(error) => {
+            report.problem(Problem::Io {
+                path: objects_dir.to_path_buf(),
+                error,
+            });
+            return objects;
+        },
+    };
+    for entry in fanout {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                report.problem(Problem::Io {
+                    path: objects_dir.to_path_buf(),
+                    error,
+                });
+                continue;
+            },
+        };
+        let prefix = entry.file_name();
+        let prefix = match prefix.to_str() {
+            Some(p) if p.len() == 2 && p.bytes().all(|b| b.is_ascii_hexdigit()) => p.to_owned(),
+            _ => continue,
+        };
+        let dir = entry.path();
+        let iter = match fs::read_dir(&dir) {
+            Ok(iter) => iter,
+            Err(error) => {
+                report.problem(Problem::Io { path: dir, error });
+                continue;
+            },
+        };
+        for entry in iter.flatten() {
+            let name = entry.file_name();
+            let rest = match name.to_str() {
+                Some(rest) if rest.len() == 38 => rest,
+                _ => continue,
+            };
+            if let Some(id) = crate::hex::object_id(format!("{}{}", prefix, rest).as_bytes()) {
+                objects.push((id, entry.path()));
+            }
+        }
+    }
+
+    objects
+}
@@ -0,0 +1,207 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Syntax checks for commit, tree and tag objects, modelled after git's
+//! `fsck.c`.
+//!
+//! Besides validating the object, the checks return the object ids the object
+//! links to, which is what connectivity checking needs.
+
+use std::cmp::Ordering;
+
+use git_hash::{oid, ObjectId};
+use git_object::Kind;
+
+/// An outgoing edge of an object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub id: ObjectId,
+    /// The kind of object the link claims to point to. `None` for submodule
+    /// commits (which are not expected to be in the object database).
+    pub kind: Option<Kind>,
+}
+
+pub type Result<T> = std::result::Result<T, &'static str>;
+
+pub fn check(kind: Kind, data: &[u8]) -> Result<Vec<Link>> {
+    match kind {
+        Kind::Blob => Ok(Vec::new()),
+        Kind::Tree => tree(data),
+        Kind::Commit => commit(data),
+        Kind::Tag => tag(data),
+    }
+}
+
+pub fn commit(data: &[u8]) -> Result<Vec<Link>> {
+    let mut links = Vec::new();
+    let mut rest = data;
+
+    let tree = header(&mut rest, b"tree").ok_or("missing tree header")?;
+    links.push(Link {
+        id: hex_id(tree).ok_or("invalid tree id")?,
+        kind: Some(Kind::Tree),
+    });
+    while let Some(parent) = header(&mut rest, b"parent") {
+        links.push(Link {
+            id: hex_id(parent).ok_or("invalid parent id")?,
+            kind: Some(Kind::Commit),
+        });
+    }
+    let author = header(&mut rest, b"author").ok_or("missing author header")?;
+    ident(author).map_err(|_| "invalid author")?;
+    let committer = header(&mut rest, b"committer").ok_or("missing committer header")?;
+    ident(committer).map_err(|_| "invalid committer")?;
+
+    Ok(links)
+}
+
+pub fn tag(data: &[u8]) -> Result<Vec<Link>> {
+    let mut rest = data;
+
+    let object = header(&mut rest, b"object").ok_or("missing object header")?;
+    let id = hex_id(object).ok_or("invalid object id")?;
+    let typ = header(&mut rest, b"type").ok_or("missing type header")?;
+    let kind = Kind::from_bytes(typ).map_err(|_| "invalid type")?;
+    let name = header(&mut rest, b"tag").ok_or("missing tag header")?;
+    if name.is_empty() {
+        return Err("empty tag name");
+    }
+    // Very old tags don't have a tagger
+    if let Some(tagger) = header(&mut rest, b"tagger") {
+        ident(tagger).map_err(|_| "invalid tagger")?;
+    }
+
+    Ok(vec![Link {
+        id,
+        kind: Some(kind),
+    }])
+}
+
+pub fn tree(data: &[u8]) -> Result<Vec<Link>> {
+    let mut links = Vec::new();
+    let mut rest = data;
+    let mut prev: Option<(&[u8], bool)> = None;
+
+    while !rest.is_empty() {
+        let sp = rest
+            .iter()
+            .position(|b| *b == b' ')
+            .ok_or("truncated tree entry")?;
+        let mode = &rest[..sp];
+        rest = &rest[sp + 1..];
+        let nul = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("truncated tree entry")?;
+        let name = &rest[..nul];
+        rest = &rest[nul + 1..];
+        let id = rest
+            .get(..20)
+            .and_then(|id| oid::try_from(id).ok())
+            .ok_or("truncated tree entry")?
+            .to_owned();
+        rest = &rest[20..];
+
+        if mode.is_empty() || mode[0] == b'0' {
+            return Err("zero-padded or empty mode");
+        }
+        let kind = match mode {
+            b"40000" => Some(Kind::Tree),
+            b"100644" | b"100755" | b"120000" => Some(Kind::Blob),
+            b"160000" => None,
+            // Accepted by git for historical reasons
+            b"100664" => Some(Kind::Blob),
+            _ => return Err("invalid mode"),
+        };
+        if name.is_empty() {
+            return Err("empty file name");
+        }
+        if name == b"." || name == b".." {
+            return Err("file name is '.' or '..'");
+        }
+        if name.contains(&b'/') {
+            return Err("file name contains '/'");
+        }
+
+        let is_tree = kind == Some(Kind::Tree);
+        if let Some((prev_name, prev_is_tree)) = prev {
+            match tree_entry_cmp(prev_name, prev_is_tree, name, is_tree) {
+                Ordering::Less => {},
+                Ordering::Equal => return Err("duplicate entries"),
+                Ordering::Greater => return Err("entries not properly sorted"),
+            }
+        }
+        prev = Some((name, is_tree));
+
+        links.push(Link { id, kind });
+    }
+
+    Ok(links)
+}
+
+/// Order of tree entries: byte-wise, but trees sort as if their name had a
+/// trailing slash.
+fn tree_entry_cmp(a: &[u8], a_is_tree: bool, b: &[u8], b_is_tree: bool) -> Ordering {
+    let len = a.len().min(b.len());
+    match a[..len].cmp(&b[..len]) {
+        Ordering::Equal => {},
+        ord => return ord,
+    }
+    if a.len() == b.len() {
+        // Same name: git considers this a duplicate regardless of the mode
+        return Ordering::Equal;
+    }
+    let next = |name: &[u8], is_tree: bool| {
+        name.get(len)
+            .copied()
+            .or(if is_tree { Some(b'/') } else { None })
+            .unwrap_or(0)
+    };
+    next(a, a_is_tree).cmp(&next(b, b_is_tree))
+}
+
+/// If `rest` starts with `name SP value LF`, return `value` and advance `rest`
+/// past the line.
+fn header<'a>(rest: &mut &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    let line = rest.strip_prefix(name)?.strip_prefix(b" ")?;
+    let lf = line.iter().position(|b| *b == b'\n')?;
+    let value = &line[..lf];
+    *rest = &line[lf + 1..];
+    Some(value)
+}
+
+fn hex_id(hex: &[u8]) -> Option<ObjectId> {
+    crate::hex::object_id(hex)
+}
+
+/// Check `Name <email> timestamp tz`
+fn ident(ident: &[u8]) -> std::result::Result<(), ()> {
+    let lt = ident.iter().position(|b| *b == b'<').ok_or(())?;
+    let gt = ident.iter().position(|b| *b == b'>').ok_or(())?;
+    if gt < lt || ident[lt + 1..gt].contains(&b'<') {
+        return Err(());
+    }
+    if lt > 0 && ident[lt - 1] != b' ' {
+        return Err(());
+    }
+
+    let date = ident[gt + 1..].strip_prefix(b" ").ok_or(())?;
+    let sp = date.iter().position(|b| *b == b' ').ok_or(())?;
+    let (timestamp, tz) = (&date[..sp], &date[sp + 1..]);
+    if timestamp.is_empty() || !timestamp.iter().all(u8::is_ascii_digit) {
+        return Err(());
+    }
+    if timestamp.len() > 1 && timestamp[0] == b'0' {
+        return Err(());
+    }
+    match tz {
+        [b'+' | b'-', digits @ ..]
+            if digits.len() == 4 && digits.iter().all(u8::is_ascii_digit) =>
+        {
+            Ok(())
+        },
+        _ => Err(()),
+    }
+}
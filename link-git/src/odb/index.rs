@@ -4,6 +4,7 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs,
     io,
     path::{Path, PathBuf},
@@ -19,10 +20,14 @@ use git_pack::{
 use parking_lot::Mutex;
 use tracing::trace;
 
-use super::pack;
+use super::{alternates, pack, Header, Reader};
 
 pub use git_pack::index::File as IndexFile;
 
+pub mod midx;
+#[cfg(feature = "notify")]
+pub mod watch;
+
 mod metrics;
 pub use metrics::{Metrics, Stats, StatsView};
 
@@ -35,10 +40,22 @@ pub mod error {
         #[error(transparent)]
         Index(#[from] pack::error::Index),
 
+        #[error(transparent)]
+        Alternates(#[from] alternates::error::Alternates),
+
         #[error(transparent)]
         Io(#[from] io::Error),
     }
 
+    #[derive(Debug, Error)]
+    pub enum Multi {
+        #[error(transparent)]
+        Midx(#[from] midx::error::Open),
+
+        #[error(transparent)]
+        Discover(#[from] Discover),
+    }
+
     #[derive(Debug, Error)]
     pub enum Lookup<E> {
         #[error(transparent)]
@@ -47,8 +64,14 @@ pub mod error {
         #[error(transparent)]
         Reload(#[from] Discover),
 
+        #[error(transparent)]
+        ReloadMulti(#[from] Multi),
+
         #[error(transparent)]
         Decode(#[from] git_pack::data::decode_entry::Error),
+
+        #[error(transparent)]
+        Header(#[from] pack::error::Header),
     }
 }
 
@@ -64,6 +87,35 @@ pub trait Index {
     ) -> Result<Option<Object<'a>>, error::Lookup<E>>
     where
         F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>;
+
+    /// Like [`Index::lookup`], but only read the kind and size of the object.
+    ///
+    /// See [`pack::Data::header`].
+    fn header<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+    ) -> Result<Option<Header>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>;
+
+    /// Like [`Index::lookup`], but return a [`Reader`] over the object's data.
+    ///
+    /// See [`super::Odb::reader`].
+    fn reader<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Reader>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>;
+
+    /// All [`pack::Index`]es currently known.
+    ///
+    /// This is intended for exhaustive operations, such as verification, not
+    /// for lookups.
+    fn packs(&self) -> Result<Vec<Arc<pack::Index>>, error::Discover>;
 }
 
 /// An [`Index`] which can be shared between threads.
@@ -79,18 +131,27 @@ pub trait Index {
 ///
 /// * orders indices found in `GIT_DIR/objects/pack` by modification time, and
 ///   queries the more recent ones first
+/// * includes the packs of all [`alternates`] of `GIT_DIR/objects` in the same
+///   ordering
 /// * attempts to rescan `GIT_DIR/objects/pack` when an object id was _not_
 ///   found (assuming that this is due to a compaction)
+/// * ignores `.idx` files without a corresponding `.pack` file, which appear
+///   briefly while a pack is being written or deleted
 ///
 /// Unless a reload occurs, lookups are lock-free and mostly wait-free. Writes
-/// ([`Shared::push`], [`Shared::reload`]) are guarded by a [`Mutex`].
+/// ([`Shared::push`], [`Shared::reload`], [`Shared::sync`]) are guarded by a
+/// [`Mutex`].
+///
+/// With the `notify` feature enabled, [`Shared::watch`] can be used to keep the
+/// indices in sync with the filesystem as packs are added or removed by other
+/// processes.
 // TODO: consecutive lookups also tend to resolve to the same pack, so we could
 // remember the index into the `im::Vector` where we found a match and look
 // there first. This is what libgit2 does, but the heuristic is not necessarily
 // true when `Shared` is shared across multiple concurrent link replication
 // tasks; per-namespace packs are independent pre-compaction.
 pub struct Shared<M> {
-    pack_dir: PathBuf,
+    objects_dir: PathBuf,
     indices: ArcSwap<im::Vector<Arc<pack::Index>>>,
     write: Mutex<()>,
     stats: M,
@@ -98,11 +159,11 @@ pub struct Shared<M> {
 
 impl Shared<()> {
     pub fn open(git_dir: impl AsRef<Path>) -> Result<Self, error::Discover> {
-        let pack_dir = git_dir.as_ref().join("objects").join("pack");
-        let indices = discover(&pack_dir)?;
+        let objects_dir = git_dir.as_ref().join("objects");
+        let indices = discover(&objects_dir)?;
 
         Ok(Self {
-            pack_dir,
+            objects_dir,
             indices: ArcSwap::new(Arc::new(indices)),
             write: Mutex::new(()),
             stats: (),
@@ -120,7 +181,7 @@ where
 
     pub fn with_metrics<N: Metrics>(self, m: N) -> Shared<N> {
         Shared {
-            pack_dir: self.pack_dir,
+            objects_dir: self.objects_dir,
             indices: self.indices,
             write: self.write,
             stats: m,
@@ -154,7 +215,7 @@ where
     /// method, as [`Shared`] manages reloads automatically.
     pub fn reload(&self) -> Result<(), error::Discover> {
         let lock = self.write.lock();
-        let indices = discover(&self.pack_dir)?;
+        let indices = discover(&self.objects_dir)?;
         self.indices.store(Arc::new(indices));
         drop(lock);
 
@@ -163,6 +224,26 @@ where
         Ok(())
     }
 
+    /// Bring the in-memory indices in sync with the packs directory.
+    ///
+    /// Unlike [`Shared::reload`], indices which are already loaded are reused,
+    /// and only newly appeared ones are read from disk. Indices whose pack
+    /// files have disappeared are dropped.
+    ///
+    /// This is called by the watcher installed via `Shared::watch`, and counted
+    /// as an automatic reload.
+    pub fn sync(&self) -> Result<(), error::Discover> {
+        let lock = self.write.lock();
+        let current = self.indices.load_full();
+        let indices = discover_in(&pack_dirs(&self.objects_dir)?, |_| false, &current)?;
+        self.indices.store(Arc::new(indices));
+        drop(lock);
+
+        self.stats.record_auto_reload();
+
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.indices.load().is_empty()
     }
@@ -199,12 +280,319 @@ where
     ) -> Result<Option<Object<'a>>, error::Lookup<E>>
     where
         F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.locate(id.as_ref(), move |ofs, info, ofs_in_pack| {
+            load_obj(ofs, info, ofs_in_pack, pack_cache, buf, cache)
+        })
+    }
+
+    fn header<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+    ) -> Result<Option<Header>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.locate(id.as_ref(), |ofs, info, ofs_in_pack| {
+            load_header(ofs, info, ofs_in_pack, pack_cache)
+        })
+    }
+
+    pub fn reader<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Reader>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.locate(id.as_ref(), |ofs, info, ofs_in_pack| {
+            load_reader(ofs, info, ofs_in_pack, pack_cache, cache)
+        })
+    }
+
+    /// Find the pack containing `id`, and `load` the object from it.
+    fn locate<T, E, L>(&self, id: &oid, load: L) -> Result<Option<T>, error::Lookup<E>>
+    where
+        L: FnOnce(u64, &pack::Info, &dyn Fn(&oid) -> Option<u64>) -> Result<T, error::Lookup<E>>,
     {
         for i in 0..2 {
             for idx in self.indices.load().iter() {
-                if let Some(ofs) = idx.ofs(&id) {
+                if let Some(ofs) = idx.ofs(id) {
+                    self.stats.record_hit();
+                    return load(ofs, &idx.info, &|id: &oid| idx.ofs(id)).map(Some);
+                }
+            }
+
+            if i == 0 {
+                self.reload()?;
+            }
+        }
+
+        self.stats.record_miss();
+        Ok(None)
+    }
+}
+
+/// An [`Index`] backed by git's [`multi-pack-index`][midx].
+///
+/// A repository with many packs can avoid querying each pack index in turn by
+/// maintaining a multi-pack-index (eg. via `git multi-pack-index write`, or
+/// `git repack --write-midx`). Packs which are not covered by the
+/// multi-pack-index, for example because they were added after it was last
+/// written, are queried in the same order as by [`Shared`].
+///
+/// Reloads are handled like in [`Shared`]: if an object id was _not_ found,
+/// both the multi-pack-index and `GIT_DIR/objects/pack` are re-read once
+/// before giving up. Packs of [`alternates`] are also treated like in
+/// [`Shared`], ie. any multi-pack-index an alternate may have is ignored.
+///
+/// Note that the multi-pack-index records only one location for every object,
+/// even if it is contained in multiple packs. Bases of `REF_DELTA` objects
+/// are thus only resolved if the multi-pack-index happens to point to the same
+/// pack as the delta. Git itself prefers `OFS_DELTA`s, so this is rarely an
+/// issue in practice.
+///
+/// [midx]: https://git-scm.com/docs/multi-pack-index
+pub struct MultiPack<M> {
+    objects_dir: PathBuf,
+    state: ArcSwap<Multi>,
+    write: Mutex<()>,
+    stats: M,
+}
+
+struct Multi {
+    midx: Option<Arc<midx::File>>,
+    /// [`pack::Info`] of each pack covered by `midx`, indexed by pack id.
+    packs: Vec<pack::Info>,
+    /// Indices of the packs _not_ covered by `midx`.
+    rest: im::Vector<Arc<pack::Index>>,
+}
+
+impl Multi {
+    fn load(objects_dir: &Path) -> Result<Self, error::Multi> {
+        let pack_dir = objects_dir.join("pack");
+        let midx = midx::File::open(&pack_dir)?.map(Arc::new);
+        let (packs, rest) = match &midx {
+            None => (Vec::new(), discover(objects_dir)?),
+            Some(m) => {
+                let packs = m
+                    .pack_names()
+                    .iter()
+                    .map(|name| pack::Info::for_index(pack_dir.join(name)))
+                    .collect();
+                let covered = m
+                    .pack_names()
+                    .iter()
+                    .map(|name| name.as_str())
+                    .collect::<BTreeSet<_>>();
+                let rest = discover_in(
+                    &pack_dirs(objects_dir)?,
+                    |path| {
+                        path.parent() == Some(pack_dir.as_path())
+                            && path
+                                .file_name()
+                                .and_then(|name| name.to_str())
+                                .map(|name| covered.contains(name))
+                                .unwrap_or(false)
+                    },
+                    &im::Vector::new(),
+                )?;
+
+                (packs, rest)
+            },
+        };
+
+        Ok(Self { midx, packs, rest })
+    }
+
+    fn len(&self) -> usize {
+        self.packs.len() + self.rest.len()
+    }
+}
+
+impl MultiPack<()> {
+    pub fn open(git_dir: impl AsRef<Path>) -> Result<Self, error::Multi> {
+        let objects_dir = git_dir.as_ref().join("objects");
+        let state = Multi::load(&objects_dir)?;
+
+        Ok(Self {
+            objects_dir,
+            state: ArcSwap::new(Arc::new(state)),
+            write: Mutex::new(()),
+            stats: (),
+        })
+    }
+}
+
+impl<M> MultiPack<M>
+where
+    M: Metrics,
+{
+    pub fn with_stats(self) -> MultiPack<Stats> {
+        self.with_metrics(Stats::default())
+    }
+
+    pub fn with_metrics<N: Metrics>(self, m: N) -> MultiPack<N> {
+        MultiPack {
+            objects_dir: self.objects_dir,
+            state: self.state,
+            write: self.write,
+            stats: m,
+        }
+    }
+
+    pub fn stats(&self) -> M::Snapshot {
+        self.stats.snapshot(self.len())
+    }
+
+    /// Add a newly discovered [`pack::Index`].
+    ///
+    /// The index is considered after the multi-pack-index, but before any
+    /// other packs not covered by it. The same visibility caveats as for
+    /// [`Shared::push`] apply.
+    pub fn push(&self, idx: pack::Index) {
+        let lock = self.write.lock();
+        let state = self.state.load();
+        let mut rest = state.rest.clone();
+        rest.push_front(Arc::new(idx));
+        self.state.store(Arc::new(Multi {
+            midx: state.midx.clone(),
+            packs: state.packs.clone(),
+            rest,
+        }));
+        drop(lock);
+
+        self.stats.record_push()
+    }
+
+    /// Re-read the multi-pack-index and re-scan the packs directory.
+    ///
+    /// Like [`Shared::reload`], it is not required to call this method
+    /// explicitly.
+    pub fn reload(&self) -> Result<(), error::Multi> {
+        let lock = self.write.lock();
+        let state = Multi::load(&self.objects_dir)?;
+        self.state.store(Arc::new(state));
+        drop(lock);
+
+        self.stats.record_reload();
+
+        Ok(())
+    }
+
+    /// `true` if a multi-pack-index is currently in use.
+    pub fn has_midx(&self) -> bool {
+        self.state.load().midx.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The total number of packs known, whether covered by the
+    /// multi-pack-index or not.
+    pub fn len(&self) -> usize {
+        self.state.load().len()
+    }
+
+    fn contains(&self, id: impl AsRef<oid>) -> bool {
+        let id = id.as_ref();
+        for i in 0..2 {
+            let state = self.state.load();
+            if let Some(midx) = &state.midx {
+                if midx.contains(id) {
+                    self.stats.record_hit();
+                    self.stats.record_midx_hit();
+                    return true;
+                }
+            }
+            for idx in state.rest.iter() {
+                if idx.contains(id) {
+                    self.stats.record_hit();
+                    return true;
+                }
+            }
+
+            if i == 0 && self.reload().is_err() {
+                self.stats.record_miss();
+                return false;
+            }
+        }
+
+        self.stats.record_miss();
+        false
+    }
+
+    fn lookup<'a, F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        buf: &'a mut Vec<u8>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Object<'a>>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.locate(id.as_ref(), move |ofs, info, ofs_in_pack| {
+            load_obj(ofs, info, ofs_in_pack, pack_cache, buf, cache)
+        })
+    }
+
+    fn header<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+    ) -> Result<Option<Header>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.locate(id.as_ref(), |ofs, info, ofs_in_pack| {
+            load_header(ofs, info, ofs_in_pack, pack_cache)
+        })
+    }
+
+    pub fn reader<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Reader>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.locate(id.as_ref(), |ofs, info, ofs_in_pack| {
+            load_reader(ofs, info, ofs_in_pack, pack_cache, cache)
+        })
+    }
+
+    /// Find the pack containing `id`, and `load` the object from it.
+    fn locate<T, E, L>(&self, id: &oid, load: L) -> Result<Option<T>, error::Lookup<E>>
+    where
+        L: FnOnce(u64, &pack::Info, &dyn Fn(&oid) -> Option<u64>) -> Result<T, error::Lookup<E>>,
+    {
+        for i in 0..2 {
+            let state = self.state.load();
+            if let Some(midx) = &state.midx {
+                if let Some(midx::Entry { pack_id, ofs }) = midx.lookup(id) {
+                    if let Some(info) = state.packs.get(pack_id as usize) {
+                        self.stats.record_hit();
+                        self.stats.record_midx_hit();
+                        let ofs_in_pack = |id: &oid| {
+                            midx.lookup(id)
+                                .filter(|entry| entry.pack_id == pack_id)
+                                .map(|entry| entry.ofs)
+                        };
+                        return load(ofs, info, &ofs_in_pack).map(Some);
+                    }
+                }
+            }
+            for idx in state.rest.iter() {
+                if let Some(ofs) = idx.ofs(id) {
                     self.stats.record_hit();
-                    return load_obj(ofs, idx, pack_cache, buf, cache).map(Some);
+                    return load(ofs, &idx.info, &|id: &oid| idx.ofs(id)).map(Some);
                 }
             }
 
@@ -218,24 +606,26 @@ where
     }
 }
 
-fn load_obj<'a, F, E>(
+fn load_obj<'a, F, E, O>(
     ofs: u64,
-    idx: &pack::Index,
+    info: &pack::Info,
+    ofs_in_pack: O,
     pack_cache: F,
     buf: &'a mut Vec<u8>,
     cache: &mut impl DecodeEntry,
 ) -> Result<Object<'a>, error::Lookup<E>>
 where
     F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    O: Fn(&oid) -> Option<u64>,
 {
-    let data = pack_cache(&idx.info).map_err(error::Lookup::Lookup)?;
+    let data = pack_cache(info).map_err(error::Lookup::Lookup)?;
+    let entry = data.entry(ofs)?;
     let pack = data.file();
-    let entry = pack.entry(ofs);
     let obj = pack
         .decode_entry(
             entry,
             buf,
-            |id, _| idx.ofs(id).map(|ofs| ResolvedBase::InPack(pack.entry(ofs))),
+            |id, _| ofs_in_pack(id).map(|ofs| ResolvedBase::InPack(pack.entry(ofs))),
             cache,
         )
         .map(move |out| Object {
@@ -247,41 +637,120 @@ where
     Ok(obj)
 }
 
-fn discover(pack_dir: impl AsRef<Path>) -> Result<im::Vector<Arc<pack::Index>>, error::Discover> {
-    let pack_dir = pack_dir.as_ref();
-    let pack_dir_disp = pack_dir.display();
-    trace!("discovering packs at {}", pack_dir_disp);
-    match fs::read_dir(&pack_dir) {
-        Ok(iter) => {
-            let mut paths = Vec::new();
-            for entry in iter {
-                let entry = entry?;
-                let path = entry.path();
-                trace!("{}", path.display());
-                let meta = entry.metadata()?;
-                if meta.file_type().is_file() && path.extension().unwrap_or_default() == "idx" {
-                    let mtime = meta.modified()?;
-                    paths.push((path, mtime));
+fn load_header<F, E, O>(
+    ofs: u64,
+    info: &pack::Info,
+    ofs_in_pack: O,
+    pack_cache: F,
+) -> Result<Header, error::Lookup<E>>
+where
+    F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    O: Fn(&oid) -> Option<u64>,
+{
+    let data = pack_cache(info).map_err(error::Lookup::Lookup)?;
+    Ok(data.header(ofs, ofs_in_pack)?)
+}
+
+fn load_reader<F, E, O>(
+    ofs: u64,
+    info: &pack::Info,
+    ofs_in_pack: O,
+    pack_cache: F,
+    cache: &mut impl DecodeEntry,
+) -> Result<Reader, error::Lookup<E>>
+where
+    F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    O: Fn(&oid) -> Option<u64>,
+{
+    let data = pack_cache(info).map_err(error::Lookup::Lookup)?;
+    if let Some(reader) = data.inflate(ofs)? {
+        return Ok(reader);
+    }
+
+    let mut buf = Vec::new();
+    let kind = load_obj(ofs, info, ofs_in_pack, |_| Ok(data), &mut buf, cache)?.kind;
+    let header = Header {
+        kind,
+        size: buf.len() as u64,
+    };
+    Ok(Reader::new(header, io::Cursor::new(buf)))
+}
+
+/// The pack directories of `objects_dir` and all its [`alternates`], in lookup
+/// order.
+fn pack_dirs(objects_dir: &Path) -> Result<Vec<PathBuf>, error::Discover> {
+    let alternates = alternates::resolve(objects_dir)?;
+    Ok(Some(objects_dir.to_path_buf())
+        .into_iter()
+        .chain(alternates)
+        .map(|dir| dir.join("pack"))
+        .collect())
+}
+
+fn discover(objects_dir: &Path) -> Result<im::Vector<Arc<pack::Index>>, error::Discover> {
+    discover_in(&pack_dirs(objects_dir)?, |_| false, &im::Vector::new())
+}
+
+/// Like [`discover`], but only scan the given `pack_dirs`, and skip `.idx`
+/// files for which `exclude` returns `true`.
+///
+/// If an index is already contained in `reuse`, it is not read again from disk.
+/// Indices whose corresponding `.pack` file does not exist (eg. because it is
+/// being deleted) are skipped.
+fn discover_in<F>(
+    pack_dirs: &[PathBuf],
+    exclude: F,
+    reuse: &im::Vector<Arc<pack::Index>>,
+) -> Result<im::Vector<Arc<pack::Index>>, error::Discover>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut paths = Vec::new();
+    for pack_dir in pack_dirs {
+        let pack_dir_disp = pack_dir.display();
+        trace!("discovering packs at {}", pack_dir_disp);
+        match fs::read_dir(pack_dir) {
+            Ok(iter) => {
+                for entry in iter {
+                    let entry = entry?;
+                    let path = entry.path();
+                    trace!("{}", path.display());
+                    let meta = entry.metadata()?;
+                    if meta.file_type().is_file()
+                        && path.extension().unwrap_or_default() == "idx"
+                        && !exclude(&path)
+                        && path.with_extension("pack").is_file()
+                    {
+                        let mtime = meta.modified()?;
+                        paths.push((path, mtime));
+                    }
                 }
-            }
-            paths.sort_by(|(_, mtime_a), (_, mtime_b)| mtime_a.cmp(mtime_b));
-
-            let indices = paths
-                .into_iter()
-                .rev()
-                .map(|(path, _)| Ok(pack::Index::open(path).map(Arc::new)?))
-                .collect::<Result<_, error::Discover>>()?;
-
-            Ok(indices)
-        },
-        // It's not an error if the directory doesn't exist, the repository
-        // could contain only loose objects
-        Err(e) if e.kind() == io::ErrorKind::NotFound => {
-            trace!("not a directory: {}", pack_dir_disp);
-            Ok(im::Vector::new())
-        },
-        Err(e) => Err(e.into()),
+            },
+            // It's not an error if the directory doesn't exist, the repository
+            // could contain only loose objects
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                trace!("not a directory: {}", pack_dir_disp);
+            },
+            Err(e) => return Err(e.into()),
+        }
     }
+    paths.sort_by_key(|(_, mtime)| *mtime);
+
+    let reuse = reuse
+        .iter()
+        .map(|idx| (idx.info.data_path.as_path(), idx))
+        .collect::<BTreeMap<_, _>>();
+    paths
+        .into_iter()
+        .rev()
+        .map(|(path, _)| {
+            let data_path = path.with_extension("pack");
+            match reuse.get(data_path.as_path()) {
+                Some(idx) => Ok(Arc::clone(idx)),
+                None => Ok(pack::Index::open(path).map(Arc::new)?),
+            }
+        })
+        .collect()
 }
 
 impl<M> Index for Shared<M>
@@ -304,4 +773,92 @@ where
     {
         self.lookup(pack_cache, id, buf, cache)
     }
+
+    fn header<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+    ) -> Result<Option<Header>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.header(pack_cache, id)
+    }
+
+    fn reader<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Reader>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.reader(pack_cache, id, cache)
+    }
+
+    fn packs(&self) -> Result<Vec<Arc<pack::Index>>, error::Discover> {
+        Ok(self.indices.load().iter().cloned().collect())
+    }
+}
+
+impl<M> Index for MultiPack<M>
+where
+    M: Metrics,
+{
+    fn contains(&self, id: impl AsRef<oid>) -> bool {
+        self.contains(id)
+    }
+
+    fn lookup<'a, F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        buf: &'a mut Vec<u8>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Object<'a>>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.lookup(pack_cache, id, buf, cache)
+    }
+
+    fn header<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+    ) -> Result<Option<Header>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.header(pack_cache, id)
+    }
+
+    fn reader<F, E>(
+        &self,
+        pack_cache: F,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Option<Reader>, error::Lookup<E>>
+    where
+        F: FnOnce(&pack::Info) -> Result<Arc<pack::Data>, E>,
+    {
+        self.reader(pack_cache, id, cache)
+    }
+
+    fn packs(&self) -> Result<Vec<Arc<pack::Index>>, error::Discover> {
+        let state = self.state.load();
+        let pack_dir = self.objects_dir.join("pack");
+        let mut packs = match &state.midx {
+            None => Vec::new(),
+            Some(midx) => midx
+                .pack_names()
+                .iter()
+                .map(|name| Ok(pack::Index::open(pack_dir.join(name)).map(Arc::new)?))
+                .collect::<Result<_, error::Discover>>()?,
+        };
+        packs.extend(state.rest.iter().cloned());
+
+        Ok(packs)
+    }
 }
@@ -10,11 +10,18 @@ pub struct StatsView {
     pub hits: usize,
     /// Total number of times a lookup was unsuccessful.
     pub misses: usize,
+    /// Total number of successful lookups which were answered by a
+    /// multi-pack-index (see [`super::MultiPack`]). These are included in
+    /// `hits`.
+    pub midx_hits: usize,
     /// Total number of times an index was added explicitly via
     /// [`super::Shared::push`].
     pub pushes: usize,
     /// Total number of reloads via [`super::Shared::reload`].
     pub reloads: usize,
+    /// Total number of automatic reloads via [`super::Shared::sync`], eg.
+    /// triggered by a filesystem watcher.
+    pub auto_reloads: usize,
     /// Number of [`crate::odb::pack::Index`]es currently held.
     pub indices: usize,
 }
@@ -23,8 +30,10 @@ pub struct StatsView {
 pub struct Stats {
     hits: AtomicUsize,
     misses: AtomicUsize,
+    midx_hits: AtomicUsize,
     pushes: AtomicUsize,
     reloads: AtomicUsize,
+    auto_reloads: AtomicUsize,
 }
 
 pub trait Metrics {
@@ -32,8 +41,10 @@ pub trait Metrics {
 
     fn record_hit(&self);
     fn record_miss(&self);
+    fn record_midx_hit(&self);
     fn record_push(&self);
     fn record_reload(&self);
+    fn record_auto_reload(&self);
 
     fn snapshot(&self, indices: usize) -> Self::Snapshot;
 }
@@ -49,6 +60,10 @@ impl Metrics for Stats {
         self.misses.fetch_add(1, Ordering::Relaxed);
     }
 
+    fn record_midx_hit(&self) {
+        self.midx_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn record_push(&self) {
         self.pushes.fetch_add(1, Ordering::Relaxed);
     }
@@ -57,12 +72,18 @@ impl Metrics for Stats {
         self.reloads.fetch_add(1, Ordering::Relaxed);
     }
 
+    fn record_auto_reload(&self) {
+        self.auto_reloads.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn snapshot(&self, indices: usize) -> Self::Snapshot {
         StatsView {
             hits: self.hits.load(Ordering::Relaxed),
             misses: self.misses.load(Ordering::Relaxed),
+            midx_hits: self.midx_hits.load(Ordering::Relaxed),
             pushes: self.pushes.load(Ordering::Relaxed),
             reloads: self.reloads.load(Ordering::Relaxed),
+            auto_reloads: self.auto_reloads.load(Ordering::Relaxed),
             indices,
         }
     }
@@ -73,8 +94,10 @@ impl Metrics for () {
 
     fn record_hit(&self) {}
     fn record_miss(&self) {}
+    fn record_midx_hit(&self) {}
     fn record_push(&self) {}
     fn record_reload(&self) {}
+    fn record_auto_reload(&self) {}
 
     fn snapshot(&self, _: usize) -> Self::Snapshot {}
 }
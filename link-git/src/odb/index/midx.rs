@@ -0,0 +1,244 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Reader for git's [`multi-pack-index`] file.
+//!
+//! [`multi-pack-index`]: https://git-scm.com/docs/gitformat-pack#_multi_pack_index_midx_files_have_the_following_format
+
+use std::{
+    fs,
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use git_hash::oid;
+
+use crate::odb::chunk::{be_u32, be_u64, oid_at, Chunks, Fanout, HASH_LEN};
+
+pub const FILE_NAME: &str = "multi-pack-index";
+
+const SIGNATURE: &[u8] = b"MIDX";
+const HEADER_LEN: usize = 12;
+
+const CHUNK_PACK_NAMES: &[u8] = b"PNAM";
+const CHUNK_FANOUT: &[u8] = b"OIDF";
+const CHUNK_OIDS: &[u8] = b"OIDL";
+const CHUNK_OFFSETS: &[u8] = b"OOFF";
+const CHUNK_LARGE_OFFSETS: &[u8] = b"LOFF";
+
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Open {
+        #[error("{path:?}: invalid signature")]
+        Signature { path: PathBuf },
+
+        #[error("{path:?}: unsupported version {version}")]
+        Version { path: PathBuf, version: u8 },
+
+        #[error("{path:?}: unsupported hash version {version}")]
+        HashVersion { path: PathBuf, version: u8 },
+
+        #[error("{path:?}: incremental multi-pack-index chains are not supported")]
+        Incremental { path: PathBuf },
+
+        #[error("{path:?}: missing required chunk {chunk}")]
+        MissingChunk { path: PathBuf, chunk: String },
+
+        #[error("{path:?}: malformed: {reason}")]
+        Malformed { path: PathBuf, reason: &'static str },
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+}
+
+/// An entry in the multi-pack-index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Index into [`File::pack_names`] of the pack containing the object.
+    pub pack_id: u32,
+    /// Offset of the object within the pack.
+    pub ofs: u64,
+}
+
+/// A `multi-pack-index` file, read fully into memory.
+///
+/// Only version 1 files using SHA-1 are supported, which is what git writes as
+/// of this writing.
+pub struct File {
+    path: PathBuf,
+    data: Vec<u8>,
+    pack_names: Vec<String>,
+    num_objects: u32,
+    fanout: Fanout,
+    oids: Range<usize>,
+    offsets: Range<usize>,
+    large_offsets: Option<Range<usize>>,
+}
+
+impl File {
+    /// Open the `multi-pack-index` in `pack_dir`, if it exists.
+    pub fn open(pack_dir: impl AsRef<Path>) -> Result<Option<Self>, error::Open> {
+        let path = pack_dir.as_ref().join(FILE_NAME);
+        match fs::read(&path) {
+            Ok(data) => Self::from_bytes(path, data).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn from_bytes(path: PathBuf, data: Vec<u8>) -> Result<Self, error::Open> {
+        macro_rules! malformed {
+            ($reason:expr) => {
+                return Err(error::Open::Malformed {
+                    path,
+                    reason: $reason,
+                })
+            };
+        }
+
+        if data.len() < HEADER_LEN + HASH_LEN {
+            malformed!("file too short")
+        }
+        if &data[..4] != SIGNATURE {
+            return Err(error::Open::Signature { path });
+        }
+        match data[4] {
+            1 => {},
+            version => return Err(error::Open::Version { path, version }),
+        }
+        match data[5] {
+            1 => {},
+            version => return Err(error::Open::HashVersion { path, version }),
+        }
+        let num_chunks = data[6] as usize;
+        if data[7] != 0 {
+            return Err(error::Open::Incremental { path });
+        }
+        let num_packs = be_u32(&data[8..12]) as usize;
+
+        let chunks = match Chunks::read(&data, HEADER_LEN, num_chunks) {
+            Ok(chunks) => chunks,
+            Err(reason) => malformed!(reason),
+        };
+        let require = |id: &[u8]| {
+            chunks.get(id).ok_or_else(|| error::Open::MissingChunk {
+                path: path.clone(),
+                chunk: String::from_utf8_lossy(id).into_owned(),
+            })
+        };
+
+        let names = require(CHUNK_PACK_NAMES)?;
+        let fanout = require(CHUNK_FANOUT)?;
+        let oids = require(CHUNK_OIDS)?;
+        let offsets = require(CHUNK_OFFSETS)?;
+        let large_offsets = chunks.get(CHUNK_LARGE_OFFSETS);
+
+        let fanout = match Fanout::new(&data, fanout) {
+            Ok(fanout) => fanout,
+            Err(reason) => malformed!(reason),
+        };
+        let num_objects = fanout.total(&data);
+        if oids.len() != num_objects as usize * HASH_LEN {
+            malformed!("object id table does not match fanout")
+        }
+        if offsets.len() != num_objects as usize * 8 {
+            malformed!("object offset table does not match fanout")
+        }
+
+        let pack_names = data[names]
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect::<Vec<_>>();
+        if pack_names.len() != num_packs {
+            malformed!("number of pack names does not match header")
+        }
+
+        Ok(Self {
+            path,
+            data,
+            pack_names,
+            num_objects,
+            fanout,
+            oids,
+            offsets,
+            large_offsets,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of the pack index files covered by this multi-pack-index,
+    /// relative to the pack directory (eg. `pack-<sha>.idx`).
+    pub fn pack_names(&self) -> &[String] {
+        &self.pack_names
+    }
+
+    pub fn num_objects(&self) -> u32 {
+        self.num_objects
+    }
+
+    /// The checksum over the file contents, as stored in the trailer.
+    pub fn checksum(&self) -> &oid {
+        oid_at(&self.data[self.data.len() - HASH_LEN..])
+    }
+
+    pub fn contains(&self, id: impl AsRef<oid>) -> bool {
+        self.position(id.as_ref()).is_some()
+    }
+
+    pub fn lookup(&self, id: impl AsRef<oid>) -> Option<Entry> {
+        self.position(id.as_ref()).map(|pos| self.entry_at(pos))
+    }
+
+    /// Iterate over all object ids in the index, in sorted order.
+    pub fn iter(&self) -> impl Iterator<Item = (&oid, Entry)> + '_ {
+        (0..self.num_objects).map(move |pos| (self.oid_at(pos), self.entry_at(pos)))
+    }
+
+    fn position(&self, id: &oid) -> Option<u32> {
+        self.fanout.search(&self.data, id, |pos| self.oid_at(pos))
+    }
+
+    fn oid_at(&self, pos: u32) -> &oid {
+        let start = self.oids.start + pos as usize * HASH_LEN;
+        oid_at(&self.data[start..start + HASH_LEN])
+    }
+
+    fn entry_at(&self, pos: u32) -> Entry {
+        let start = self.offsets.start + pos as usize * 8;
+        let pack_id = be_u32(&self.data[start..start + 4]);
+        let ofs = be_u32(&self.data[start + 4..start + 8]);
+        let ofs = if ofs & LARGE_OFFSET_FLAG == 0 {
+            ofs as u64
+        } else {
+            // If the flag is set, but there is no `LOFF` chunk, the file is
+            // corrupt. We treat the offset as-is, which will fail to decode
+            // later on.
+            match &self.large_offsets {
+                None => ofs as u64,
+                Some(large) => {
+                    let start = large.start + (ofs & !LARGE_OFFSET_FLAG) as usize * 8;
+                    if start + 8 > large.end {
+                        ofs as u64
+                    } else {
+                        be_u64(&self.data[start..start + 8])
+                    }
+                },
+            }
+        };
+
+        Entry { pack_id, ofs }
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Keep a [`Shared`] index in sync with the filesystem.
+
+use std::{
+    path::Path,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use notify::{
+    event::{EventKind, ModifyKind},
+    Event,
+    PollWatcher,
+    RecursiveMode,
+};
+use tracing::{trace, warn};
+
+use super::{pack_dirs, Metrics, Shared};
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Watch {
+        #[error(transparent)]
+        Discover(#[from] crate::odb::index::error::Discover),
+
+        #[error(transparent)]
+        Notify(#[from] notify::Error),
+    }
+}
+
+/// How to detect changes to the packs directory.
+#[derive(Clone, Copy, Debug)]
+pub enum Mode {
+    /// Use the native notification mechanism of the platform (eg. `inotify` on
+    /// Linux).
+    Native,
+    /// Poll the filesystem in the given interval.
+    Poll(Duration),
+    /// Try [`Mode::Native`], and fall back to [`Mode::Poll`] with the given
+    /// interval if that is not available.
+    Auto(Duration),
+}
+
+/// Handle to a running watcher.
+///
+/// Watching stops when this is dropped. The watcher does not keep the
+/// [`Shared`] index alive: if all other references to it are dropped, events
+/// are ignored.
+pub struct Watcher {
+    _inner: Box<dyn notify::Watcher + Send>,
+}
+
+impl<M> Shared<M>
+where
+    M: Metrics + Send + Sync + 'static,
+{
+    /// Watch the packs directory (and the packs directories of any
+    /// alternates), and [`Shared::sync`] whenever a pack is added or removed.
+    ///
+    /// Note that the set of alternates is determined when this method is
+    /// called. Pack directories which do not exist at this point are not
+    /// watched.
+    pub fn watch(self: &Arc<Self>, mode: Mode) -> Result<Watcher, error::Watch> {
+        let dirs = pack_dirs(&self.objects_dir)?
+            .into_iter()
+            .filter(|dir| dir.is_dir())
+            .collect::<Vec<_>>();
+        let shared = Arc::downgrade(self);
+
+        let mut inner: Box<dyn notify::Watcher + Send> = match mode {
+            Mode::Native => Box::new(notify::recommended_watcher(handler(shared))?),
+            Mode::Poll(interval) => Box::new(poll_watcher(shared, interval)?),
+            Mode::Auto(interval) => match notify::recommended_watcher(handler(shared.clone())) {
+                Ok(native) => Box::new(native),
+                Err(e) => {
+                    warn!("native watcher unavailable, falling back to polling: {}", e);
+                    Box::new(poll_watcher(shared, interval)?)
+                },
+            },
+        };
+        for dir in dirs {
+            trace!("watching {}", dir.display());
+            inner.watch(&dir, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(Watcher { _inner: inner })
+    }
+}
+
+fn poll_watcher<M>(shared: Weak<Shared<M>>, interval: Duration) -> notify::Result<PollWatcher>
+where
+    M: Metrics + Send + Sync + 'static,
+{
+    PollWatcher::new(
+        handler(shared),
+        notify::Config::default().with_poll_interval(interval),
+    )
+}
+
+fn handler<M>(shared: Weak<Shared<M>>) -> impl Fn(notify::Result<Event>) + Send + 'static
+where
+    M: Metrics + Send + Sync + 'static,
+{
+    move |event| match event {
+        Err(e) => warn!("error watching packs directory: {}", e),
+        Ok(event) => {
+            if !is_relevant(&event) {
+                return;
+            }
+            if let Some(shared) = shared.upgrade() {
+                trace!("packs changed: {:?}", event.paths);
+                if let Err(e) = shared.sync() {
+                    warn!("failed to sync pack indices: {}", e);
+                }
+            }
+        },
+    }
+}
+
+/// We only care about packs and their indices appearing or disappearing.
+/// Temporary files written by `git-index-pack` et al. are ignored, as they are
+/// renamed into place eventually.
+fn is_relevant(event: &Event) -> bool {
+    let kind = matches!(
+        event.kind,
+        EventKind::Any
+            | EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_))
+            | EventKind::Modify(ModifyKind::Any)
+    );
+    kind && event.paths.iter().any(|path| is_pack_file(path))
+}
+
+fn is_pack_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("idx") | Some("pack")
+    )
+}
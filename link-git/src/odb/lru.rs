@@ -0,0 +1,110 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// A map which keeps track of the order in which its entries were last
+/// accessed, and of their total size.
+///
+/// This is the bookkeeping shared by [`super::window::Lru`] and
+/// [`super::decode::Sharded`]. It does not enforce any limits by itself:
+/// callers are expected to [`LruMap::pop_oldest`] until their limits allow a
+/// new entry to be inserted.
+pub(crate) struct LruMap<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Access tick -> key into `entries`, oldest first.
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    bytes: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    size: u64,
+    last_access: u64,
+}
+
+impl<K, V> Default for LruMap<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl<K, V> LruMap<K, V>
+where
+    K: Clone + Eq + Hash,
+{
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total size of the entries, as passed to [`LruMap::insert`].
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Get the value for `key`, and mark it as the most recently used entry.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.last_access);
+        self.order.insert(tick, key.clone());
+        entry.last_access = tick;
+
+        Some(&entry.value)
+    }
+
+    /// Insert `value` of the given `size` as the most recently used entry,
+    /// returning the previous value for `key`, if any.
+    pub fn insert(&mut self, key: K, value: V, size: u64) -> Option<V> {
+        let old = self.remove(&key);
+        let tick = self.next_tick();
+        self.bytes += size;
+        self.order.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                last_access: tick,
+            },
+        );
+
+        old
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.last_access);
+        self.bytes -= entry.size;
+        Some(entry.value)
+    }
+
+    /// Remove and return the least recently used entry.
+    pub fn pop_oldest(&mut self) -> Option<(K, V)> {
+        let oldest = self.order.keys().next().copied()?;
+        let key = self.order.remove(&oldest)?;
+        let entry = self.entries.remove(&key)?;
+        self.bytes -= entry.size;
+        Some((key, entry.value))
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
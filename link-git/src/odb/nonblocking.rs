@@ -0,0 +1,299 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! An async front-end to [`super::Odb`].
+//!
+//! Object lookups are performed on the [`blocking`] thread pool, with the
+//! number of concurrent lookups bounded by a semaphore. Each call gets a fresh
+//! decode cache, so callers don't need to manage one across `await` points.
+//!
+//! Large blobs can be streamed in chunks via [`Odb::find_chunked`].
+
+use std::{
+    io::{self, Read as _},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_lock::Semaphore;
+use blocking::Unblock;
+use futures_lite::{future, Stream};
+use git_hash::ObjectId;
+use git_object::Kind;
+use git_pack::cache::DecodeEntry;
+
+use super::{index, window, Error, Header, Reader};
+
+/// An object read from the [`Odb`], owning its data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Owned {
+    pub id: ObjectId,
+    pub kind: Kind,
+    pub data: Vec<u8>,
+}
+
+/// Async wrapper around a shared [`super::Odb`].
+///
+/// Cloning is cheap, clones share the odb as well as the concurrency limit.
+pub struct Odb<I, D, F> {
+    inner: Arc<super::Odb<I, D>>,
+    permits: Arc<Semaphore>,
+    cache: Arc<F>,
+}
+
+impl<I, D, F> Clone for Odb<I, D, F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            permits: Arc::clone(&self.permits),
+            cache: Arc::clone(&self.cache),
+        }
+    }
+}
+
+impl<I, D, F, C> Odb<I, D, F>
+where
+    I: index::Index + Send + Sync + 'static,
+    D: window::Cache + Send + Sync + 'static,
+    F: Fn() -> C + Send + Sync + 'static,
+    C: DecodeEntry + Send + 'static,
+{
+    /// Wrap `odb`, allowing at most `max_concurrency` lookups to run on the
+    /// blocking thread pool at any point in time.
+    ///
+    /// `cache` is called to create a decode cache for each lookup, or batch
+    /// of lookups.
+    ///
+    /// # Panics
+    ///
+    /// If `max_concurrency` is zero.
+    pub fn new(odb: Arc<super::Odb<I, D>>, max_concurrency: usize, cache: F) -> Self {
+        assert!(max_concurrency > 0, "max_concurrency must be non-zero");
+        Self {
+            inner: odb,
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            cache: Arc::new(cache),
+        }
+    }
+
+    /// The wrapped [`super::Odb`].
+    pub fn get_ref(&self) -> &super::Odb<I, D> {
+        &self.inner
+    }
+
+    pub async fn contains(&self, id: ObjectId) -> bool {
+        self.unblock(move |odb, _| odb.contains(id)).await
+    }
+
+    pub async fn find(&self, id: ObjectId) -> Result<Option<Owned>, Error> {
+        self.unblock(move |odb, cache| find(odb, id, &mut Vec::new(), cache))
+            .await
+    }
+
+    /// See [`super::Odb::header`].
+    pub async fn header(&self, id: ObjectId) -> Result<Option<Header>, Error> {
+        self.unblock(move |odb, _| odb.header(id)).await
+    }
+
+    /// Look up all of `ids` in a single blocking task, sharing one decode
+    /// cache.
+    ///
+    /// The result is in the same order as `ids`. Fails on the first error.
+    pub async fn find_many<T>(&self, ids: T) -> Result<Vec<Option<Owned>>, Error>
+    where
+        T: IntoIterator<Item = ObjectId> + Send + 'static,
+        T::IntoIter: Send,
+    {
+        self.unblock(move |odb, cache| {
+            let mut buf = Vec::new();
+            ids.into_iter()
+                .map(|id| find(odb, id, &mut buf, cache))
+                .collect()
+        })
+        .await
+    }
+
+    /// Look up the objects `ids` lazily, in order.
+    ///
+    /// Objects are looked up on the blocking thread pool, at most `buffer`
+    /// objects ahead of the consumer. Unlike [`Odb::find_many`], this bounds
+    /// the _number_ of objects held in memory at once. Each object is still
+    /// read fully into memory: use [`Odb::find_chunked`] for individual large
+    /// blobs.
+    ///
+    /// Every lookup acquires a concurrency slot separately, so an idle stream
+    /// does not prevent other lookups from making progress.
+    pub fn find_stream<T>(
+        &self,
+        ids: T,
+        buffer: usize,
+    ) -> impl Stream<Item = Result<(ObjectId, Option<Owned>), Error>> + Unpin + Send + 'static
+    where
+        T: IntoIterator<Item = ObjectId>,
+        T::IntoIter: Send + 'static,
+    {
+        Unblock::with_capacity(
+            buffer.max(1),
+            Objects {
+                odb: Arc::clone(&self.inner),
+                permits: Arc::clone(&self.permits),
+                cache: (self.cache)(),
+                ids: ids.into_iter(),
+                buf: Vec::new(),
+            },
+        )
+    }
+
+    /// Read the object `id` as a stream of chunks of at most `chunk_size`
+    /// bytes.
+    ///
+    /// Loose objects and non-delta packed objects are inflated incrementally,
+    /// at most one chunk ahead of the consumer, so large blobs are never held
+    /// in memory in their entirety. Deltified objects, however, can only be
+    /// resolved as a whole, see [`super::Odb::reader`].
+    ///
+    /// Like for [`Odb::find_stream`], every chunk acquires a concurrency slot
+    /// separately.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    pub async fn find_chunked(
+        &self,
+        id: ObjectId,
+        chunk_size: usize,
+    ) -> Result<Option<Chunked>, Error> {
+        assert!(chunk_size > 0, "chunk_size must be non-zero");
+        let reader = self
+            .unblock(move |odb, cache| odb.reader(id, cache))
+            .await?;
+        Ok(reader.map(|reader| Chunked {
+            id,
+            header: reader.header(),
+            chunks: Unblock::with_capacity(
+                1,
+                Chunks {
+                    permits: Arc::clone(&self.permits),
+                    reader: Some(reader),
+                    chunk_size,
+                },
+            ),
+        }))
+    }
+
+    async fn unblock<G, T>(&self, f: G) -> T
+    where
+        G: FnOnce(&super::Odb<I, D>, &mut C) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self.permits.acquire_arc().await;
+        let odb = Arc::clone(&self.inner);
+        let mut cache = (self.cache)();
+        blocking::unblock(move || {
+            // Hold the permit until the lookup is done, even if the caller
+            // stops waiting for it
+            let _permit = permit;
+            f(&odb, &mut cache)
+        })
+        .await
+    }
+}
+
+struct Objects<I, D, C, T> {
+    odb: Arc<super::Odb<I, D>>,
+    permits: Arc<Semaphore>,
+    cache: C,
+    ids: T,
+    buf: Vec<u8>,
+}
+
+impl<I, D, C, T> Iterator for Objects<I, D, C, T>
+where
+    I: index::Index,
+    D: window::Cache,
+    C: DecodeEntry,
+    T: Iterator<Item = ObjectId>,
+{
+    type Item = Result<(ObjectId, Option<Owned>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        // We're on the blocking thread pool already
+        let _permit = future::block_on(self.permits.acquire());
+        Some(find(&self.odb, id, &mut self.buf, &mut self.cache).map(|obj| (id, obj)))
+    }
+}
+
+/// A stream of the chunks of an object's data, see [`Odb::find_chunked`].
+pub struct Chunked {
+    pub id: ObjectId,
+    pub header: Header,
+    chunks: Unblock<Chunks>,
+}
+
+impl Stream for Chunked {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.chunks).poll_next(cx)
+    }
+}
+
+struct Chunks {
+    permits: Arc<Semaphore>,
+    /// `None` after the end of the data, or an error
+    reader: Option<Reader>,
+    chunk_size: usize,
+}
+
+impl Iterator for Chunks {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reader = self.reader.as_mut()?;
+        // We're on the blocking thread pool already
+        let _permit = future::block_on(self.permits.acquire());
+        let mut chunk = vec![0; self.chunk_size];
+        let mut len = 0;
+        while len < chunk.len() {
+            match reader.read(&mut chunk[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.reader = None;
+                    return Some(Err(e));
+                },
+            }
+        }
+        if len < chunk.len() {
+            self.reader = None;
+        }
+        if len == 0 {
+            return None;
+        }
+        chunk.truncate(len);
+        Some(Ok(chunk))
+    }
+}
+
+fn find<I, D, C>(
+    odb: &super::Odb<I, D>,
+    id: ObjectId,
+    buf: &mut Vec<u8>,
+    cache: &mut C,
+) -> Result<Option<Owned>, Error>
+where
+    I: index::Index,
+    D: window::Cache,
+    C: DecodeEntry,
+{
+    Ok(odb.find(id, buf, cache)?.map(|obj| Owned {
+        id,
+        kind: obj.kind,
+        data: obj.data.to_vec(),
+    }))
+}
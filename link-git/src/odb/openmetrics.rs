@@ -0,0 +1,465 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Export [`index::Metrics`], [`window::Metrics`] and [`decode::Metrics`] in
+//! the [OpenMetrics] text format.
+//!
+//! A [`Registry`] hands out metrics implementations labelled with a
+//! repository name, which can be plugged into [`index::Shared::with_metrics`]
+//! and friends. [`Registry::encode`] renders the current values of all
+//! registered metrics, eg. in response to a scrape request.
+//!
+//! The gauges of a [`window::Cache`] are only observed by [`window::Metrics`]
+//! when the cache's `stats()` are taken. [`Registry::observe_window`] reads
+//! them from the cache itself whenever the registry is rendered instead.
+//!
+//! [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+        Weak,
+    },
+};
+
+use parking_lot::RwLock;
+
+use super::{
+    decode::{self, Metrics as _},
+    index::{self, Metrics as _},
+    window::{self, Metrics as _},
+};
+
+const PREFIX: &str = "link_git_odb";
+
+/// A metric family: name, type, help text, and how to get its value from a
+/// snapshot of type `S`.
+type Family<S, V> = (&'static str, &'static str, &'static str, fn(&S) -> V);
+
+/// Per-repository [`index::Metrics`], registered with a [`Registry`].
+///
+/// The snapshot is the same as for [`index::Stats`].
+#[derive(Clone)]
+pub struct IndexMetrics {
+    inner: Arc<IndexInner>,
+}
+
+#[derive(Default)]
+struct IndexInner {
+    stats: index::Stats,
+    indices: AtomicUsize,
+}
+
+impl index::Metrics for IndexMetrics {
+    type Snapshot = index::StatsView;
+
+    fn record_hit(&self) {
+        self.inner.stats.record_hit()
+    }
+
+    fn record_miss(&self) {
+        self.inner.stats.record_miss()
+    }
+
+    fn record_midx_hit(&self) {
+        self.inner.stats.record_midx_hit()
+    }
+
+    fn record_push(&self) {
+        self.inner.stats.record_push()
+    }
+
+    fn record_reload(&self) {
+        self.inner.stats.record_reload()
+    }
+
+    fn record_auto_reload(&self) {
+        self.inner.stats.record_auto_reload()
+    }
+
+    fn snapshot(&self, indices: usize) -> Self::Snapshot {
+        self.inner.indices.store(indices, Ordering::Relaxed);
+        self.inner.stats.snapshot(indices)
+    }
+}
+
+/// Per-repository [`window::Metrics`], registered with a [`Registry`].
+///
+/// The snapshot is the same as for [`window::Stats`].
+#[derive(Clone)]
+pub struct WindowMetrics {
+    inner: Arc<WindowInner>,
+}
+
+#[derive(Default)]
+struct WindowInner {
+    stats: window::Stats,
+    open_files: AtomicUsize,
+}
+
+impl window::Metrics for WindowMetrics {
+    type Snapshot = window::StatsView;
+
+    fn record_hit(&self) {
+        self.inner.stats.record_hit()
+    }
+
+    fn record_miss(&self) {
+        self.inner.stats.record_miss()
+    }
+
+    fn record_load(&self) {
+        self.inner.stats.record_load()
+    }
+
+    fn record_eviction(&self) {
+        self.inner.stats.record_eviction()
+    }
+
+    fn snapshot(&self, open_files: usize) -> Self::Snapshot {
+        self.inner.open_files.store(open_files, Ordering::Relaxed);
+        self.inner.stats.snapshot(open_files)
+    }
+}
+
+/// Per-repository [`decode::Metrics`], registered with a [`Registry`].
+///
+/// The snapshot is the same as for [`decode::Stats`].
+#[derive(Clone)]
+pub struct DecodeMetrics {
+    inner: Arc<DecodeInner>,
+}
+
+#[derive(Default)]
+struct DecodeInner {
+    stats: decode::Stats,
+    entries: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl decode::Metrics for DecodeMetrics {
+    type Snapshot = decode::StatsView;
+
+    fn record_hit(&self) {
+        self.inner.stats.record_hit()
+    }
+
+    fn record_miss(&self) {
+        self.inner.stats.record_miss()
+    }
+
+    fn record_eviction(&self) {
+        self.inner.stats.record_eviction()
+    }
+
+    fn snapshot(&self, entries: usize, bytes: u64) -> Self::Snapshot {
+        self.inner.entries.store(entries, Ordering::Relaxed);
+        self.inner.bytes.store(bytes, Ordering::Relaxed);
+        self.inner.stats.snapshot(entries, bytes)
+    }
+}
+
+/// The current contents of a [`window::Cache`], see
+/// [`Registry::observe_window`].
+pub trait WindowGauges: Send + Sync {
+    /// Number of pack files held by the cache.
+    fn open_files(&self) -> usize;
+    /// Total size in bytes of the pack files held by the cache.
+    fn bytes(&self) -> u64;
+}
+
+impl<M, const B: usize, const S: usize> WindowGauges for window::Fixed<M, B, S>
+where
+    M: window::Metrics + Send + Sync,
+{
+    fn open_files(&self) -> usize {
+        self.open_files()
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes()
+    }
+}
+
+impl<M> WindowGauges for window::Lru<M>
+where
+    M: window::Metrics + Send + Sync,
+{
+    fn open_files(&self) -> usize {
+        self.open_files()
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes()
+    }
+}
+
+#[derive(Default)]
+struct Repo {
+    index: Option<IndexMetrics>,
+    window: Option<WindowMetrics>,
+    window_gauges: Option<Weak<dyn WindowGauges>>,
+    decode: Option<DecodeMetrics>,
+}
+
+/// A registry of [`IndexMetrics`], [`WindowMetrics`] and [`DecodeMetrics`],
+/// labelled by repository.
+///
+/// The registry is cheap to clone, clones share the same underlying state.
+///
+/// Note that the gauges (number of indices, number of open files, decode cache
+/// size) are only updated when the respective `stats()` method of the index or
+/// cache is called, as the metrics traits only observe them at that point.
+/// Window caches registered via [`Registry::observe_window`] are the
+/// exception.
+#[derive(Clone, Default)]
+pub struct Registry {
+    repos: Arc<RwLock<BTreeMap<String, Repo>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the [`IndexMetrics`] for `repo`, creating them if they don't exist.
+    pub fn index(&self, repo: impl Into<String>) -> IndexMetrics {
+        self.repos
+            .write()
+            .entry(repo.into())
+            .or_default()
+            .index
+            .get_or_insert_with(|| IndexMetrics {
+                inner: Arc::new(IndexInner::default()),
+            })
+            .clone()
+    }
+
+    /// Get the [`WindowMetrics`] for `repo`, creating them if they don't
+    /// exist.
+    ///
+    /// If a [`window::Cache`] is shared between repositories, `repo` should be
+    /// a name identifying the cache instead.
+    pub fn window(&self, repo: impl Into<String>) -> WindowMetrics {
+        self.repos
+            .write()
+            .entry(repo.into())
+            .or_default()
+            .window
+            .get_or_insert_with(|| WindowMetrics {
+                inner: Arc::new(WindowInner::default()),
+            })
+            .clone()
+    }
+
+    /// Read the number of open files and the size of `cache` whenever the
+    /// registry is rendered, and export them as the window gauges of `repo`.
+    ///
+    /// The registry does not keep `cache` alive: once it is dropped, the
+    /// gauges fall back to the values last observed by the [`WindowMetrics`]
+    /// of `repo`, if any.
+    pub fn observe_window<C>(&self, repo: impl Into<String>, cache: &Arc<C>)
+    where
+        C: WindowGauges + 'static,
+    {
+        let cache: Weak<C> = Arc::downgrade(cache);
+        self.repos
+            .write()
+            .entry(repo.into())
+            .or_default()
+            .window_gauges = Some(cache as Weak<dyn WindowGauges>);
+    }
+
+    /// Get the [`DecodeMetrics`] for `repo`, creating them if they don't
+    /// exist.
+    pub fn decode(&self, repo: impl Into<String>) -> DecodeMetrics {
+        self.repos
+            .write()
+            .entry(repo.into())
+            .or_default()
+            .decode
+            .get_or_insert_with(|| DecodeMetrics {
+                inner: Arc::new(DecodeInner::default()),
+            })
+            .clone()
+    }
+
+    /// Stop exporting the metrics of `repo`.
+    ///
+    /// Handles to the metrics which are still in use continue to work, but
+    /// their values are no longer rendered.
+    pub fn remove(&self, repo: &str) {
+        self.repos.write().remove(repo);
+    }
+
+    /// Render all registered metrics in the OpenMetrics text format.
+    pub fn encode<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        let repos = self.repos.read();
+
+        let index = repos
+            .iter()
+            .filter_map(|(repo, m)| {
+                m.index.as_ref().map(|m| {
+                    let indices = m.inner.indices.load(Ordering::Relaxed);
+                    (repo.as_str(), m.inner.stats.snapshot(indices))
+                })
+            })
+            .collect::<Vec<_>>();
+        #[rustfmt::skip]
+        let index_families: &[Family<index::StatsView, usize>] = &[
+            ("index_hits", "counter", "Number of successful object lookups.", |s| s.hits),
+            ("index_misses", "counter", "Number of unsuccessful object lookups.", |s| s.misses),
+            ("index_midx_hits", "counter", "Number of lookups answered by a multi-pack-index.", |s| s.midx_hits),
+            ("index_pushes", "counter", "Number of pack indices added explicitly.", |s| s.pushes),
+            ("index_reloads", "counter", "Number of reloads of the packs directory.", |s| s.reloads),
+            ("index_auto_reloads", "counter", "Number of automatic reloads of the packs directory.", |s| s.auto_reloads),
+            ("index_indices", "gauge", "Number of pack indices held.", |s| s.indices),
+        ];
+        for (name, typ, help, get) in index_families {
+            family(
+                out,
+                name,
+                typ,
+                help,
+                index.iter().map(|(repo, s)| (*repo, get(s))),
+            )?;
+        }
+
+        let window_gauges = repos
+            .iter()
+            .filter_map(|(repo, m)| {
+                let cache = m.window_gauges.as_ref()?.upgrade()?;
+                Some((repo.as_str(), (cache.open_files(), cache.bytes())))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let window = repos
+            .iter()
+            .filter_map(|(repo, m)| {
+                m.window.as_ref().map(|m| {
+                    let open_files = match window_gauges.get(repo.as_str()) {
+                        Some((open_files, _)) => *open_files,
+                        None => m.inner.open_files.load(Ordering::Relaxed),
+                    };
+                    (repo.as_str(), m.inner.stats.snapshot(open_files))
+                })
+            })
+            .collect::<Vec<_>>();
+        #[rustfmt::skip]
+        let window_families: &[Family<window::StatsView, usize>] = &[
+            ("window_hits", "counter", "Number of times pack data was found in the cache.", |s| s.cache_hits),
+            ("window_misses", "counter", "Number of times pack data was not found in the cache.", |s| s.cache_misses),
+            ("window_loads", "counter", "Number of attempts to load pack data from disk.", |s| s.file_loads),
+            ("window_evictions", "counter", "Number of pack files evicted from the cache.", |s| s.evictions),
+        ];
+        for (name, typ, help, get) in window_families {
+            family(
+                out,
+                name,
+                typ,
+                help,
+                window.iter().map(|(repo, s)| (*repo, get(s))),
+            )?;
+        }
+        // Observed caches may not have window metrics of their own
+        let open_files = window
+            .iter()
+            .filter(|(repo, _)| !window_gauges.contains_key(repo))
+            .map(|(repo, s)| (*repo, s.open_files))
+            .chain(
+                window_gauges
+                    .iter()
+                    .map(|(repo, (open_files, _))| (*repo, *open_files)),
+            )
+            .collect::<BTreeMap<_, _>>();
+        family(
+            out,
+            "window_open_files",
+            "gauge",
+            "Number of pack files held by the cache.",
+            open_files.into_iter(),
+        )?;
+        family(
+            out,
+            "window_bytes",
+            "gauge",
+            "Total size in bytes of the pack files held by the cache.",
+            window_gauges
+                .iter()
+                .map(|(repo, (_, bytes))| (*repo, *bytes)),
+        )?;
+
+        let decode = repos
+            .iter()
+            .filter_map(|(repo, m)| {
+                m.decode.as_ref().map(|m| {
+                    let entries = m.inner.entries.load(Ordering::Relaxed);
+                    let bytes = m.inner.bytes.load(Ordering::Relaxed);
+                    (repo.as_str(), m.inner.stats.snapshot(entries, bytes))
+                })
+            })
+            .collect::<Vec<_>>();
+        #[rustfmt::skip]
+        let decode_families: &[Family<decode::StatsView, u64>] = &[
+            ("decode_hits", "counter", "Number of times a delta base was found in the cache.", |s| s.cache_hits as u64),
+            ("decode_misses", "counter", "Number of times a delta base was not found in the cache.", |s| s.cache_misses as u64),
+            ("decode_evictions", "counter", "Number of entries evicted from the cache.", |s| s.evictions as u64),
+            ("decode_entries", "gauge", "Number of entries held by the cache.", |s| s.entries as u64),
+            ("decode_bytes", "gauge", "Total size in bytes of the entries held by the cache.", |s| s.bytes),
+        ];
+        for (name, typ, help, get) in decode_families {
+            family(
+                out,
+                name,
+                typ,
+                help,
+                decode.iter().map(|(repo, s)| (*repo, get(s))),
+            )?;
+        }
+
+        writeln!(out, "# EOF")
+    }
+
+    /// Like [`Registry::encode`], but return the result as a [`String`].
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.encode(&mut out)
+            .expect("writing to a String never fails");
+        out
+    }
+}
+
+fn family<'a, W, I, V>(out: &mut W, name: &str, typ: &str, help: &str, samples: I) -> fmt::Result
+where
+    W: fmt::Write,
+    I: Iterator<Item = (&'a str, V)>,
+    V: fmt::Display,
+{
+    writeln!(out, "# TYPE {}_{} {}", PREFIX, name, typ)?;
+    writeln!(out, "# HELP {}_{} {}", PREFIX, name, help)?;
+    let suffix = if typ == "counter" { "_total" } else { "" };
+    for (repo, value) in samples {
+        write!(out, "{}_{}{}{{repo=\"", PREFIX, name, suffix)?;
+        escape_label(out, repo)?;
+        writeln!(out, "\"}} {}", value)?;
+    }
+
+    Ok(())
+}
+
+fn escape_label<W: fmt::Write>(out: &mut W, value: &str) -> fmt::Result {
+    for c in value.chars() {
+        match c {
+            '\\' => out.write_str("\\\\")?,
+            '"' => out.write_str("\\\"")?,
+            '\n' => out.write_str("\\n")?,
+            c => out.write_char(c)?,
+        }
+    }
+
+    Ok(())
+}
@@ -4,15 +4,37 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
+    io,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
+use git_features::zlib;
 use git_hash::{oid, ObjectId};
-use git_pack::{data, index};
+use git_pack::{
+    data::{self, entry},
+    index,
+};
 use rustc_hash::FxHasher;
 use tracing::warn;
 
+use super::{Header, Reader};
+
+/// Size of the pack data header: signature, version and number of objects.
+const PACK_HEADER_LEN: u64 = 12;
+
+/// The maximum number of compressed bytes read to obtain a delta header.
+const DELTA_HEADER_INPUT: u64 = 4096;
+
+/// The maximum length of a delta chain followed by [`Data::header`].
+///
+/// This is the maximum `--depth` accepted by `git-pack-objects`. Longer chains
+/// are either corrupt or contain a cycle of ref-deltas.
+pub const MAX_DELTA_DEPTH: usize = 4095;
+
 pub mod error {
     use super::*;
     use thiserror::Error;
@@ -30,6 +52,21 @@ pub mod error {
         pub path: PathBuf,
         pub source: index::init::Error,
     }
+
+    #[derive(Debug, Error)]
+    pub enum Header {
+        #[error("delta base {base} of entry at offset {ofs} not found in pack")]
+        BaseNotFound { ofs: u64, base: ObjectId },
+
+        #[error("malformed entry at offset {ofs}: {reason}")]
+        Malformed { ofs: u64, reason: &'static str },
+
+        #[error("delta chain of entry at offset {ofs} exceeds {max} entries")]
+        ChainTooLong { ofs: u64, max: usize },
+
+        #[error(transparent)]
+        Inflate(#[from] zlib::inflate::Error),
+    }
 }
 
 pub struct Data {
@@ -50,6 +87,231 @@ impl Data {
     pub fn file(&self) -> &data::File {
         &self.file
     }
+
+    /// Size in bytes of the pack data file.
+    pub fn size(&self) -> u64 {
+        self.file.data_len() as u64
+    }
+
+    /// Read the kind and size of the object at `ofs`, without decoding it.
+    ///
+    /// For deltified entries, the size is read from the delta header, and the
+    /// kind from the (non-delta) entry at the end of the delta chain. Neither
+    /// requires applying any deltas. `ofs_in_pack` resolves the base of
+    /// ref-deltas to an offset in the same pack.
+    ///
+    /// Delta chains longer than [`MAX_DELTA_DEPTH`] are rejected.
+    pub fn header<F>(&self, ofs: u64, ofs_in_pack: F) -> Result<Header, error::Header>
+    where
+        F: Fn(&oid) -> Option<u64>,
+    {
+        let entry = self.entry(ofs)?;
+        let size = if entry.header.is_delta() {
+            self.delta_result_size(ofs, &entry)?
+        } else {
+            entry.decompressed_size
+        };
+
+        let mut base = entry;
+        let mut base_ofs = ofs;
+        let mut depth = 0;
+        let kind = loop {
+            if depth > MAX_DELTA_DEPTH {
+                return Err(error::Header::ChainTooLong {
+                    ofs,
+                    max: MAX_DELTA_DEPTH,
+                });
+            }
+            depth += 1;
+            match base.header {
+                entry::Header::OfsDelta { base_distance } => {
+                    base_ofs = entry::Header::verified_base_pack_offset(base_ofs, base_distance)
+                        .ok_or(error::Header::Malformed {
+                            ofs: base_ofs,
+                            reason: "delta base offset out of bounds",
+                        })?;
+                },
+                entry::Header::RefDelta { base_id } => {
+                    base_ofs = ofs_in_pack(&base_id).ok_or(error::Header::BaseNotFound {
+                        ofs: base_ofs,
+                        base: base_id,
+                    })?;
+                },
+                header => {
+                    break header
+                        .as_kind()
+                        .expect("non-delta entries have an object kind")
+                },
+            }
+            base = self.entry(base_ofs)?;
+        };
+
+        Ok(Header { kind, size })
+    }
+
+    /// Parse the header of the entry at `ofs`.
+    ///
+    /// Unlike [`data::File::entry`], this does not panic if `ofs` is out of
+    /// bounds, or if the header is malformed.
+    pub fn entry(&self, ofs: u64) -> Result<data::Entry, error::Header> {
+        let malformed = |reason| error::Header::Malformed { ofs, reason };
+
+        let mut input = Some(ofs)
+            .filter(|ofs| (PACK_HEADER_LEN..self.entries_end()).contains(ofs))
+            .and_then(|ofs| self.file.entry_slice(ofs..self.entries_end()))
+            .ok_or_else(|| malformed("offset out of bounds"))?
+            .iter()
+            .copied();
+        let mut next = || {
+            input
+                .next()
+                .ok_or_else(|| malformed("truncated entry header"))
+        };
+
+        let mut c = next()?;
+        let mut consumed = 1;
+        let type_id = (c >> 4) & 0b111;
+        let mut size = (c & 0b1111) as u64;
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            if shift > 60 {
+                return Err(malformed("entry size overflows"));
+            }
+            c = next()?;
+            consumed += 1;
+            size |= ((c & 0x7f) as u64) << shift;
+            shift += 7;
+        }
+
+        let header = match type_id {
+            1 => entry::Header::Commit,
+            2 => entry::Header::Tree,
+            3 => entry::Header::Blob,
+            4 => entry::Header::Tag,
+            6 => {
+                // Big-endian base-128, with an offset of one added to each
+                // continuation byte
+                let mut c = next()?;
+                consumed += 1;
+                let mut base_distance = (c & 0x7f) as u64;
+                while c & 0x80 != 0 {
+                    c = next()?;
+                    consumed += 1;
+                    base_distance = base_distance
+                        .checked_add(1)
+                        .and_then(|d| d.checked_mul(128))
+                        .map(|d| d | (c & 0x7f) as u64)
+                        .ok_or_else(|| malformed("delta base offset overflows"))?;
+                }
+                entry::Header::OfsDelta { base_distance }
+            },
+            7 => {
+                let mut base_id = [0u8; 20];
+                for b in base_id.iter_mut() {
+                    *b = next()?;
+                }
+                consumed += base_id.len() as u64;
+                entry::Header::RefDelta {
+                    base_id: ObjectId::new_sha1(base_id),
+                }
+            },
+            _ => return Err(malformed("invalid entry type")),
+        };
+
+        Ok(data::Entry {
+            header,
+            decompressed_size: size,
+            data_offset: ofs + consumed,
+        })
+    }
+
+    /// Inflate the non-delta entry at `ofs` incrementally.
+    ///
+    /// Returns `None` if the entry is a delta, which can only be resolved as a
+    /// whole.
+    pub fn inflate(self: &Arc<Self>, ofs: u64) -> Result<Option<Reader>, error::Header> {
+        let entry = self.entry(ofs)?;
+        let kind = match entry.header.as_kind() {
+            None => return Ok(None),
+            Some(kind) => kind,
+        };
+        let header = Header {
+            kind,
+            size: entry.decompressed_size,
+        };
+
+        Ok(Some(Reader::new(
+            header,
+            Inflate {
+                data: Arc::clone(self),
+                pos: entry.data_offset,
+                state: Box::new(zlib::Decompress::new(true)),
+            },
+        )))
+    }
+
+    /// Offset of the end of the last entry, ie. the start of the trailing
+    /// checksum.
+    fn entries_end(&self) -> u64 {
+        (self.file.data_len() as u64).saturating_sub(20)
+    }
+
+    /// Inflate just enough of the delta at `ofs` to read the size of the
+    /// object it produces.
+    fn delta_result_size(&self, ofs: u64, entry: &data::Entry) -> Result<u64, error::Header> {
+        let malformed = |reason| error::Header::Malformed { ofs, reason };
+
+        let end = self.entries_end();
+        let input = self
+            .file
+            .entry_slice(entry.data_offset..end.min(entry.data_offset + DELTA_HEADER_INPUT))
+            .ok_or_else(|| malformed("entry out of bounds"))?;
+        // Two varints of at most 10 bytes each
+        let mut out = [0u8; 20];
+        let (_, _, produced) = zlib::Inflate::default().once(input, &mut out)?;
+
+        let mut header = &out[..produced];
+        let _base_size =
+            delta_varint(&mut header).ok_or_else(|| malformed("truncated delta header"))?;
+        delta_varint(&mut header).ok_or_else(|| malformed("truncated delta header"))
+    }
+}
+
+/// The inflated data of a single pack entry, see [`Data::inflate`].
+struct Inflate {
+    data: Arc<Data>,
+    pos: u64,
+    state: Box<zlib::Decompress>,
+}
+
+impl io::Read for Inflate {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let end = self.data.entries_end();
+        let input = self
+            .data
+            .file
+            .entry_slice(self.pos.min(end)..end)
+            .unwrap_or_default();
+        let mut rest = input;
+        let n = zlib::stream::inflate::read(&mut rest, &mut self.state, buf)?;
+        self.pos += (input.len() - rest.len()) as u64;
+        Ok(n)
+    }
+}
+
+/// Decode a size from a delta header: little-endian base-128, where the most
+/// significant bit of each byte indicates continuation.
+fn delta_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, byte) in input.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *input = &input[i + 1..];
+            return Some(value);
+        }
+    }
+
+    None
 }
 
 impl AsRef<data::File> for Data {
@@ -58,13 +320,44 @@ impl AsRef<data::File> for Data {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Info {
     pub(super) hash: u64,
     pub data_path: PathBuf,
 }
 
 impl Info {
+    /// The [`Info`] for the pack data file corresponding to the pack index
+    /// file at `path`.
+    pub fn for_index(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let data_path = path.with_extension("pack");
+        let hash = {
+            let file_name = path
+                .file_name()
+                .expect("pack index path must have a file name")
+                .to_string_lossy();
+            // XXX: inexplicably, gitoxide omits the "pack-" prefix
+            let sha_hex = file_name.strip_prefix("pack-").unwrap_or(&file_name);
+            match sha_hex.as_bytes().get(..40).and_then(crate::hex::object_id) {
+                None => {
+                    warn!(
+                        "unconventional pack name {:?}, falling back to fxhash",
+                        path
+                    );
+                    hash(path)
+                },
+                Some(oid) => {
+                    let mut buf = [0u8; 8];
+                    buf.copy_from_slice(&oid.sha1()[..8]);
+                    u64::from_be_bytes(buf)
+                },
+            }
+        };
+
+        Self { hash, data_path }
+    }
+
     pub fn data(&self) -> Result<Data, error::Data> {
         let file = data::File::at(&self.data_path).map_err(|source| error::Data {
             path: self.data_path.clone(),
@@ -90,30 +383,7 @@ impl Index {
             path: path.to_path_buf(),
             source,
         })?;
-        let data_path = path.with_extension("pack");
-        let hash = {
-            let file_name = path
-                .file_name()
-                .expect("must have a file name, we opened it")
-                .to_string_lossy();
-            // XXX: inexplicably, gitoxide omits the "pack-" prefix
-            let sha_hex = file_name.strip_prefix("pack-").unwrap_or(&file_name);
-            match ObjectId::from_hex(&sha_hex.as_bytes()[..40]) {
-                Err(e) => {
-                    warn!(
-                        "unconventional pack name {:?}, falling back to fxhash: {}",
-                        path, e
-                    );
-                    hash(path)
-                },
-                Ok(oid) => {
-                    let mut buf = [0u8; 8];
-                    buf.copy_from_slice(&oid.sha1()[..8]);
-                    u64::from_be_bytes(buf)
-                },
-            }
-        };
-        let info = Info { hash, data_path };
+        let info = Info::for_index(path);
 
         Ok(Self { file, info })
     }
@@ -127,6 +397,21 @@ impl Index {
             .lookup(id)
             .map(|idx| self.file.pack_offset_at_index(idx))
     }
+
+    /// Path to the index file.
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    pub fn num_objects(&self) -> u32 {
+        self.file.num_objects()
+    }
+
+    /// Iterate over all object ids in the index, along with their offset in
+    /// the pack.
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, u64)> + '_ {
+        self.file.iter().map(|entry| (entry.oid, entry.pack_offset))
+    }
 }
 
 fn hash(p: &Path) -> u64 {
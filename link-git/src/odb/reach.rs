@@ -0,0 +1,528 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Reachability queries over the commit history: ancestry, "contains" and
+//! merge-base computations, and generation numbers.
+//!
+//! Queries consult a [`commit_graph::Graph`] if one is supplied, and fall back
+//! to decoding commit objects from the [`Odb`] for commits which are not in the
+//! graph (eg. because they were created after the graph was last written), or
+//! whose entry in the graph is corrupt.
+
+use std::collections::{BinaryHeap, HashMap};
+
+use git_hash::{oid, ObjectId};
+use git_object::Kind;
+use git_pack::cache::DecodeEntry;
+use tracing::warn;
+
+use super::{
+    commit_graph::{self, GENERATION_INFINITY},
+    index,
+    window,
+    Odb,
+};
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Reach {
+        #[error("commit {0} not found")]
+        NotFound(ObjectId),
+
+        #[error("object {id} is a {kind}, not a commit")]
+        NotACommit { id: ObjectId, kind: Kind },
+
+        #[error("malformed commit {id}: {reason}")]
+        Malformed { id: ObjectId, reason: &'static str },
+
+        #[error(transparent)]
+        Find(#[from] crate::odb::Error),
+    }
+}
+
+/// A commit as far as reachability is concerned.
+#[derive(Clone, Debug)]
+struct Node {
+    parents: Vec<ObjectId>,
+    /// The generation as recorded in the commit-graph, or
+    /// [`GENERATION_INFINITY`] if unknown.
+    generation: u32,
+    commit_time: u64,
+}
+
+/// Handle for reachability queries, obtained via [`Odb::reach`].
+pub struct Reach<'a, I, D, C> {
+    odb: &'a Odb<I, D>,
+    graph: Option<&'a commit_graph::Graph>,
+    cache: C,
+    buf: Vec<u8>,
+}
+
+impl<I, D> Odb<I, D>
+where
+    I: index::Index,
+    D: window::Cache,
+{
+    /// Prepare reachability queries, using `graph` to speed them up if given.
+    ///
+    /// `cache` is used when decoding commits which are not in the graph.
+    pub fn reach<'a, C>(
+        &'a self,
+        graph: Option<&'a commit_graph::Graph>,
+        cache: C,
+    ) -> Reach<'a, I, D, C>
+    where
+        C: DecodeEntry,
+    {
+        Reach {
+            odb: self,
+            graph,
+            cache,
+            buf: Vec::new(),
+        }
+    }
+}
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 1 << 1;
+const STALE: u8 = 1 << 2;
+
+impl<'a, I, D, C> Reach<'a, I, D, C>
+where
+    I: index::Index,
+    D: window::Cache,
+    C: DecodeEntry,
+{
+    /// The generation number of `commit`: one for root commits, and one more
+    /// than the maximum generation of the parents otherwise.
+    ///
+    /// This is cheap for commits in the commit-graph, but requires walking the
+    /// history down to the graph (or the root commits) for others.
+    pub fn generation(&mut self, commit: impl AsRef<oid>) -> Result<u32, error::Reach> {
+        let commit = commit.as_ref().to_owned();
+        let mut memo = HashMap::new();
+        let mut stack = vec![(commit, self.node(&commit)?)];
+        while let Some((id, node)) = stack.last() {
+            if node.generation != GENERATION_INFINITY {
+                memo.insert(*id, node.generation);
+                stack.pop();
+                continue;
+            }
+
+            let mut max = 0;
+            let mut pending = None;
+            for parent in &node.parents {
+                match memo.get(parent) {
+                    Some(gen) => max = max.max(*gen),
+                    None => {
+                        pending = Some(*parent);
+                        break;
+                    },
+                }
+            }
+            match pending {
+                Some(parent) => {
+                    let node = self.node(&parent)?;
+                    stack.push((parent, node));
+                },
+                None => {
+                    memo.insert(*id, max.saturating_add(1));
+                    stack.pop();
+                },
+            }
+        }
+
+        Ok(memo[&commit])
+    }
+
+    /// `true` if `ancestor` is reachable from `descendant` (or they are the
+    /// same commit).
+    pub fn is_ancestor(
+        &mut self,
+        ancestor: impl AsRef<oid>,
+        descendant: impl AsRef<oid>,
+    ) -> Result<bool, error::Reach> {
+        let mut memo = HashMap::new();
+        let target = self.target(ancestor.as_ref())?;
+        self.contains_one(&target, descendant.as_ref(), &mut memo)
+    }
+
+    /// For each of `tips`, determine whether `commit` is reachable from it,
+    /// eg. to find out which branches contain `commit`.
+    ///
+    /// The result is in the same order as `tips`. The traversal state is
+    /// shared between tips, so this is considerably faster than calling
+    /// [`Reach::is_ancestor`] for each tip when their histories overlap.
+    pub fn contains<T>(
+        &mut self,
+        commit: impl AsRef<oid>,
+        tips: impl IntoIterator<Item = T>,
+    ) -> Result<Vec<bool>, error::Reach>
+    where
+        T: AsRef<oid>,
+    {
+        let mut memo = HashMap::new();
+        let target = self.target(commit.as_ref())?;
+        tips.into_iter()
+            .map(|tip| self.contains_one(&target, tip.as_ref(), &mut memo))
+            .collect()
+    }
+
+    /// The best common ancestor of `a` and `b`, if any.
+    ///
+    /// If there are multiple best common ancestors (criss-cross merges), an
+    /// arbitrary one of them is returned. Use [`Reach::merge_bases`] to obtain
+    /// all of them.
+    pub fn merge_base(
+        &mut self,
+        a: impl AsRef<oid>,
+        b: impl AsRef<oid>,
+    ) -> Result<Option<ObjectId>, error::Reach> {
+        self.merge_bases(a, b).map(|bases| bases.into_iter().next())
+    }
+
+    /// All best common ancestors of `a` and `b`, ie. the common ancestors
+    /// which are not reachable from any other common ancestor.
+    pub fn merge_bases(
+        &mut self,
+        a: impl AsRef<oid>,
+        b: impl AsRef<oid>,
+    ) -> Result<Vec<ObjectId>, error::Reach> {
+        let (a, b) = (a.as_ref().to_owned(), b.as_ref().to_owned());
+        if a == b {
+            self.node(&a)?;
+            return Ok(vec![a]);
+        }
+
+        let candidates = self.paint_down_to_common(a, b)?;
+        self.remove_redundant(candidates)
+    }
+
+    /// Walk the histories of `a` and `b` in generation (then commit date)
+    /// order, and collect the commits reachable from both which are not
+    /// reachable from an already found common commit.
+    fn paint_down_to_common(
+        &mut self,
+        a: ObjectId,
+        b: ObjectId,
+    ) -> Result<Vec<ObjectId>, error::Reach> {
+        let mut queue = Queue::default();
+        let mut results = Vec::new();
+
+        queue.paint(a, PARENT1);
+        queue.push(a, &self.node(&a)?);
+        queue.paint(b, PARENT2);
+        queue.push(b, &self.node(&b)?);
+
+        while queue.has_non_stale() {
+            let id = queue.pop().expect("queue is not empty");
+            let mut flag = queue.flags(&id) & (PARENT1 | PARENT2 | STALE);
+            if flag == PARENT1 | PARENT2 {
+                if !results.contains(&id) {
+                    results.push(id);
+                }
+                flag |= STALE;
+            }
+
+            for parent in self.node(&id)?.parents {
+                if queue.flags(&parent) & flag == flag {
+                    continue;
+                }
+                queue.paint(parent, flag);
+                let node = self.node(&parent)?;
+                queue.push(parent, &node);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Remove candidates which are ancestors of other candidates.
+    fn remove_redundant(
+        &mut self,
+        candidates: Vec<ObjectId>,
+    ) -> Result<Vec<ObjectId>, error::Reach> {
+        if candidates.len() < 2 {
+            return Ok(candidates);
+        }
+
+        let mut redundant = vec![false; candidates.len()];
+        for (i, candidate) in candidates.iter().enumerate() {
+            let mut memo = HashMap::new();
+            let target = self.target(candidate)?;
+            for (j, other) in candidates.iter().enumerate() {
+                if i != j && !redundant[j] && self.contains_one(&target, other, &mut memo)? {
+                    redundant[i] = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(candidates
+            .into_iter()
+            .zip(redundant)
+            .filter(|(_, redundant)| !redundant)
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    fn target(&mut self, id: &oid) -> Result<Target, error::Reach> {
+        let node = self.node(id)?;
+        Ok(Target {
+            id: id.to_owned(),
+            in_graph: self.graph.map(|g| g.contains(id)).unwrap_or(false),
+            generation: node.generation,
+        })
+    }
+
+    /// Depth-first search for `target` from `tip`, memoising the result for
+    /// every commit visited.
+    fn contains_one(
+        &mut self,
+        target: &Target,
+        tip: &oid,
+        memo: &mut HashMap<ObjectId, bool>,
+    ) -> Result<bool, error::Reach> {
+        struct Frame {
+            id: ObjectId,
+            parents: Vec<ObjectId>,
+            next: usize,
+        }
+
+        if let Some(known) = self.known(target, tip, memo) {
+            return Ok(known);
+        }
+        let mut stack = vec![Frame {
+            id: tip.to_owned(),
+            parents: self.node(tip)?.parents,
+            next: 0,
+        }];
+        while let Some(top) = stack.last_mut() {
+            if top.next == top.parents.len() {
+                memo.insert(top.id, false);
+                stack.pop();
+                continue;
+            }
+            let parent = top.parents[top.next];
+            top.next += 1;
+
+            match self.known(target, &parent, memo) {
+                Some(true) => {
+                    // Every commit on the stack is on the path to `target`
+                    for frame in stack.drain(..) {
+                        memo.insert(frame.id, true);
+                    }
+                    return Ok(true);
+                },
+                Some(false) => {},
+                None => {
+                    let parents = self.node(&parent)?.parents;
+                    stack.push(Frame {
+                        id: parent,
+                        parents,
+                        next: 0,
+                    });
+                },
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Determine whether `id` reaches `target` without walking the history,
+    /// if possible.
+    fn known(&self, target: &Target, id: &oid, memo: &mut HashMap<ObjectId, bool>) -> Option<bool> {
+        if id == target.id {
+            return Some(true);
+        }
+        if let Some(known) = memo.get(id) {
+            return Some(*known);
+        }
+
+        let graph = self.graph?;
+        let pos = graph.lookup(id)?;
+        let known = if !target.in_graph {
+            // The graph is closed under reachability, so none of the commits
+            // in it can reach a commit outside of it.
+            Some(false)
+        } else {
+            let generation = known_generation(graph.commit_at(pos).ok()?.generation);
+            // A commit can only reach commits of strictly lower generation
+            if generation != GENERATION_INFINITY
+                && target.generation != GENERATION_INFINITY
+                && generation <= target.generation
+            {
+                Some(false)
+            } else {
+                None
+            }
+        };
+        if let Some(known) = known {
+            memo.insert(id.to_owned(), known);
+        }
+        known
+    }
+
+    fn node(&mut self, id: &oid) -> Result<Node, error::Reach> {
+        if let Some(graph) = self.graph {
+            if let Some(pos) = graph.lookup(id) {
+                match graph.commit_at(pos) {
+                    Ok(commit) => {
+                        return Ok(Node {
+                            parents: commit
+                                .parents
+                                .iter()
+                                .filter_map(|p| graph.id_at(*p))
+                                .map(ToOwned::to_owned)
+                                .collect(),
+                            generation: known_generation(commit.generation),
+                            commit_time: commit.commit_time,
+                        })
+                    },
+                    Err(e) => warn!("{}, falling back to the object database", e),
+                }
+            }
+        }
+
+        let obj = self
+            .odb
+            .find(id, &mut self.buf, &mut self.cache)?
+            .ok_or_else(|| error::Reach::NotFound(id.to_owned()))?;
+        if obj.kind != Kind::Commit {
+            return Err(error::Reach::NotACommit {
+                id: id.to_owned(),
+                kind: obj.kind,
+            });
+        }
+        parse_commit(obj.data).map_err(|reason| error::Reach::Malformed {
+            id: id.to_owned(),
+            reason,
+        })
+    }
+}
+
+struct Target {
+    id: ObjectId,
+    in_graph: bool,
+    generation: u32,
+}
+
+/// Priority queue of [`Reach::paint_down_to_common`], which keeps track of
+/// the number of queued entries which are not [`STALE`].
+///
+/// Like in git, an entry counts as stale if its commit is currently flagged as
+/// such, regardless of when it was queued.
+#[derive(Default)]
+struct Queue {
+    heap: BinaryHeap<Queued>,
+    flags: HashMap<ObjectId, u8>,
+    /// Number of entries in `heap` per commit.
+    queued: HashMap<ObjectId, usize>,
+    non_stale: usize,
+}
+
+impl Queue {
+    fn flags(&self, id: &oid) -> u8 {
+        self.flags.get(id).copied().unwrap_or(0)
+    }
+
+    fn is_stale(&self, id: &oid) -> bool {
+        self.flags(id) & STALE != 0
+    }
+
+    fn has_non_stale(&self) -> bool {
+        self.non_stale > 0
+    }
+
+    /// Add `flag` to the flags of `id`.
+    fn paint(&mut self, id: ObjectId, flag: u8) {
+        let was_stale = self.is_stale(&id);
+        *self.flags.entry(id).or_insert(0) |= flag;
+        if !was_stale && self.is_stale(&id) {
+            self.non_stale -= self.queued.get(&id).copied().unwrap_or(0);
+        }
+    }
+
+    fn push(&mut self, id: ObjectId, node: &Node) {
+        *self.queued.entry(id).or_insert(0) += 1;
+        if !self.is_stale(&id) {
+            self.non_stale += 1;
+        }
+        self.heap.push(Queued::new(id, node));
+    }
+
+    fn pop(&mut self) -> Option<ObjectId> {
+        let Queued { id, .. } = self.heap.pop()?;
+        if let Some(n) = self.queued.get_mut(&id) {
+            *n -= 1;
+        }
+        if !self.is_stale(&id) {
+            self.non_stale -= 1;
+        }
+        Some(id)
+    }
+}
+
+/// Entry of the merge-base priority queue: highest generation first, then
+/// most recent commit time.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Queued {
+    generation: u32,
+    commit_time: u64,
+    id: ObjectId,
+}
+
+impl Queued {
+    fn new(id: ObjectId, node: &Node) -> Self {
+        Self {
+            generation: node.generation,
+            commit_time: node.commit_time,
+            id,
+        }
+    }
+}
+
+/// Commit-graphs written by old versions of git record zero generations.
+fn known_generation(generation: u32) -> u32 {
+    if generation == 0 {
+        GENERATION_INFINITY
+    } else {
+        generation
+    }
+}
+
+fn parse_commit(data: &[u8]) -> Result<Node, &'static str> {
+    let mut parents = Vec::new();
+    let mut commit_time = None;
+    for line in data.split(|b| *b == b'\n') {
+        if line.is_empty() {
+            break;
+        }
+        if let Some(hex) = line.strip_prefix(b"parent ") {
+            parents.push(crate::hex::object_id(hex).ok_or("invalid parent id")?);
+        } else if let Some(ident) = line.strip_prefix(b"committer ") {
+            let gt = ident
+                .iter()
+                .rposition(|b| *b == b'>')
+                .ok_or("invalid committer")?;
+            let timestamp = ident[gt + 1..]
+                .split(|b| *b == b' ')
+                .find(|s| !s.is_empty())
+                .ok_or("missing commit time")?;
+            commit_time = std::str::from_utf8(timestamp)
+                .ok()
+                .and_then(|t| t.parse().ok());
+        }
+    }
+
+    Ok(Node {
+        parents,
+        generation: GENERATION_INFINITY,
+        commit_time: commit_time.ok_or("invalid commit time")?,
+    })
+}
@@ -10,6 +10,9 @@ use parking_lot::Mutex;
 
 use super::pack;
 
+mod lru;
+pub use lru::{Limits, Lru};
+
 mod metrics;
 pub use metrics::{Metrics, Stats, StatsView};
 
@@ -22,6 +25,21 @@ pub trait Cache {
     fn get(&self, info: &pack::Info) -> Result<Arc<pack::Data>, pack::error::Data>;
 }
 
+impl<C> Cache for Arc<C>
+where
+    C: Cache + ?Sized,
+{
+    type Stats = C::Stats;
+
+    fn stats(&self) -> Self::Stats {
+        (**self).stats()
+    }
+
+    fn get(&self, info: &pack::Info) -> Result<Arc<pack::Data>, pack::error::Data> {
+        (**self).get(info)
+    }
+}
+
 impl<M, const B: usize, const S: usize> Cache for Fixed<M, B, S>
 where
     M: Metrics,
@@ -111,12 +129,30 @@ where
     }
 
     pub fn stats(&self) -> M::Snapshot {
-        let open_files = self
-            .entries
+        self.stats.snapshot(self.open_files())
+    }
+
+    /// Number of pack files currently held by the cache.
+    pub fn open_files(&self) -> usize {
+        self.entries
             .iter()
             .map(|bucket| bucket.load().iter().flatten().count())
-            .sum();
-        self.stats.snapshot(open_files)
+            .sum()
+    }
+
+    /// Total size in bytes of the pack files currently held by the cache.
+    pub fn bytes(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .load()
+                    .iter()
+                    .flatten()
+                    .map(|e| e.size())
+                    .sum::<u64>()
+            })
+            .sum()
     }
 
     pub fn get(&self, info: &pack::Info) -> Result<Arc<pack::Data>, pack::error::Data> {
@@ -167,6 +203,9 @@ where
                 },
             }
         }
+        if bucket[evict].is_some() {
+            self.stats.record_eviction();
+        }
         let mut entries = Guard::into_inner(bucket);
         {
             // This costs `SLOTS` refcount increments if the slot is currently
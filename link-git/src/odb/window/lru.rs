@@ -0,0 +1,302 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tracing::trace;
+
+use super::{pack, Cache, Metrics, Stats};
+use crate::odb::lru::LruMap;
+
+/// Resource limits of an [`Lru`] cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum total size in bytes of the pack files held by the cache.
+    pub bytes: u64,
+    /// Maximum number of pack files held by the cache.
+    pub files: usize,
+}
+
+impl Default for Limits {
+    /// 8GiB, 1024 open files
+    fn default() -> Self {
+        Self {
+            bytes: 8 * 1024 * 1024 * 1024,
+            files: 1024,
+        }
+    }
+}
+
+/// A [`Cache`] bounded by the total size of the pack files it holds, as well
+/// as their number, both configured at runtime.
+///
+/// Unlike [`super::Fixed`], entries are identified by both the pack hash and
+/// the path of the pack file. This makes [`Lru`] suitable to be shared
+/// between repositories, for example by wrapping it in an [`Arc`]. The least
+/// recently used entries are evicted when either limit would be exceeded by
+/// loading a new pack file. A single pack file which is larger than the size
+/// limit is still admitted, but evicts all other entries.
+///
+/// Note that evicting an entry only releases the cache's reference to it:
+/// callers which obtained the data from [`Cache::get`] before keep the file
+/// open until they drop their reference. The limits thus bound what the cache
+/// holds on to, not what the process has open at any point in time.
+///
+/// All accesses are serialised through a [`Mutex`], which is, however, not
+/// held while a pack file is loaded from disk.
+pub struct Lru<M> {
+    limits: Limits,
+    state: Mutex<State>,
+    stats: M,
+}
+
+#[derive(Default)]
+struct State {
+    entries: LruMap<pack::Info, Arc<pack::Data>>,
+}
+
+impl State {
+    fn get(&mut self, info: &pack::Info) -> Option<Arc<pack::Data>> {
+        self.entries.get(info).map(Arc::clone)
+    }
+
+    fn insert<M: Metrics>(
+        &mut self,
+        limits: &Limits,
+        info: pack::Info,
+        data: Arc<pack::Data>,
+        stats: &M,
+    ) {
+        let size = data.size();
+        while !self.entries.is_empty()
+            && (self.entries.len() + 1 > limits.files || self.entries.bytes() + size > limits.bytes)
+        {
+            if let Some((info, _)) = self.entries.pop_oldest() {
+                trace!("evicting {}", info.data_path.display());
+                stats.record_eviction();
+            }
+        }
+        self.entries.insert(info, data, size);
+    }
+}
+
+impl Lru<()> {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            state: Mutex::new(State::default()),
+            stats: (),
+        }
+    }
+}
+
+impl Default for Lru<()> {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl<M> Lru<M>
+where
+    M: Metrics,
+{
+    pub fn with_stats(self) -> Lru<Stats> {
+        self.with_metrics(Stats::default())
+    }
+
+    pub fn with_metrics<N: Metrics>(self, m: N) -> Lru<N> {
+        Lru {
+            limits: self.limits,
+            state: self.state,
+            stats: m,
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Total size in bytes of the pack files currently held by the cache.
+    pub fn bytes(&self) -> u64 {
+        self.state.lock().entries.bytes()
+    }
+
+    /// Number of pack files currently held by the cache.
+    pub fn open_files(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    pub fn stats(&self) -> M::Snapshot {
+        self.stats.snapshot(self.open_files())
+    }
+
+    /// Get the data for `info`, loading it from disk on a cache miss.
+    ///
+    /// Unlike [`super::Fixed`], a miss is never followed by a hit if another
+    /// thread loads the same pack concurrently: `cache_hits + cache_misses`
+    /// is the number of accesses.
+    pub fn get(&self, info: &pack::Info) -> Result<Arc<pack::Data>, pack::error::Data> {
+        if let Some(data) = self.state.lock().get(info) {
+            self.stats.record_hit();
+            data.hit();
+            return Ok(data);
+        }
+        self.stats.record_miss();
+
+        // Load without holding the lock. Another thread may race us, in which
+        // case we discard our result in favour of theirs.
+        self.stats.record_load();
+        let data = Arc::new(info.data()?);
+
+        Ok(self.insert_loaded(info, data))
+    }
+
+    /// Insert `data` after a cache miss, unless another thread was faster.
+    ///
+    /// The miss was already recorded, so neither case counts as a hit.
+    fn insert_loaded(&self, info: &pack::Info, data: Arc<pack::Data>) -> Arc<pack::Data> {
+        let mut state = self.state.lock();
+        if let Some(theirs) = state.get(info) {
+            theirs.hit();
+            return theirs;
+        }
+        state.insert(&self.limits, info.clone(), Arc::clone(&data), &self.stats);
+        drop(state);
+
+        data.hit();
+        data
+    }
+}
+
+impl<M> Cache for Lru<M>
+where
+    M: Metrics,
+{
+    type Stats = M::Snapshot;
+
+    fn stats(&self) -> Self::Stats {
+        self.stats()
+    }
+
+    fn get(&self, info: &pack::Info) -> Result<Arc<pack::Data>, pack::error::Data> {
+        self.get(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Write an empty pack of `size` bytes, and return its [`pack::Info`].
+    ///
+    /// The data following the pack header is never looked at, so it can be
+    /// padded to any size.
+    fn pack(dir: &Path, n: u8, size: usize) -> pack::Info {
+        let mut data = vec![0; size];
+        data[..12].copy_from_slice(b"PACK\0\0\0\x02\0\0\0\0");
+        let hex = format!("{:02x}", n).repeat(20);
+        fs::write(dir.join(format!("pack-{}.pack", hex)), data).unwrap();
+        pack::Info::for_index(dir.join(format!("pack-{}.idx", hex)))
+    }
+
+    fn lru(limits: Limits) -> Lru<Stats> {
+        Lru::new(limits).with_stats()
+    }
+
+    fn held(lru: &Lru<Stats>, info: &pack::Info) -> bool {
+        lru.state.lock().entries.get(info).is_some()
+    }
+
+    #[test]
+    fn byte_limit() {
+        let tmp = TempDir::new().unwrap();
+        let packs = (0..4).map(|n| pack(tmp.path(), n, 100)).collect::<Vec<_>>();
+        let lru = lru(Limits {
+            bytes: 250,
+            files: 1024,
+        });
+
+        for info in &packs {
+            lru.get(info).unwrap();
+            assert!(lru.bytes() <= 250);
+        }
+        assert_eq!(lru.bytes(), 200);
+        let stats = lru.stats();
+        assert_eq!(stats.open_files, 2);
+        assert_eq!(stats.evictions, 2);
+
+        // A single pack exceeding the limit is admitted, but evicts all others
+        let large = pack(tmp.path(), 4, 300);
+        lru.get(&large).unwrap();
+        assert_eq!(lru.bytes(), 300);
+        assert_eq!(lru.stats().open_files, 1);
+    }
+
+    #[test]
+    fn file_limit() {
+        let tmp = TempDir::new().unwrap();
+        let packs = (0..5).map(|n| pack(tmp.path(), n, 32)).collect::<Vec<_>>();
+        let lru = lru(Limits {
+            bytes: u64::MAX,
+            files: 3,
+        });
+
+        for info in &packs {
+            lru.get(info).unwrap();
+            assert!(lru.stats().open_files <= 3);
+        }
+        let stats = lru.stats();
+        assert_eq!(stats.open_files, 3);
+        assert_eq!(stats.evictions, 2);
+        assert_eq!(stats.file_loads, 5);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let tmp = TempDir::new().unwrap();
+        let packs = (0..4).map(|n| pack(tmp.path(), n, 32)).collect::<Vec<_>>();
+        let lru = lru(Limits {
+            bytes: u64::MAX,
+            files: 3,
+        });
+
+        for info in &packs[..3] {
+            lru.get(info).unwrap();
+        }
+        // Make 0 more recently used than 1
+        lru.get(&packs[0]).unwrap();
+        lru.get(&packs[3]).unwrap();
+
+        assert!(!held(&lru, &packs[1]));
+        for info in [&packs[0], &packs[2], &packs[3]] {
+            assert!(held(&lru, info));
+        }
+        let stats = lru.stats();
+        assert_eq!(stats.cache_hits, 1);
+        assert_eq!(stats.cache_misses, 4);
+    }
+
+    #[test]
+    fn lost_race() {
+        let tmp = TempDir::new().unwrap();
+        let info = pack(tmp.path(), 0, 32);
+        let lru = lru(Limits::default());
+
+        let ours = lru.get(&info).unwrap();
+        // Another thread missed concurrently, and loaded the same pack
+        let theirs = lru.insert_loaded(&info, Arc::new(info.data().unwrap()));
+        assert!(Arc::ptr_eq(&ours, &theirs));
+
+        let stats = lru.stats();
+        assert_eq!(stats.cache_hits, 0);
+        assert_eq!(stats.cache_misses, 1);
+        assert_eq!(stats.open_files, 1);
+    }
+}
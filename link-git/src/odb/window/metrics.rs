@@ -21,6 +21,9 @@ pub struct StatsView {
     pub file_loads: usize,
     /// Total number of pack files the cache holds on to.
     pub open_files: usize,
+    /// Total number of pack files which were evicted from the cache to make
+    /// room for others.
+    pub evictions: usize,
 }
 
 #[derive(Default)]
@@ -28,6 +31,7 @@ pub struct Stats {
     hits: AtomicUsize,
     miss: AtomicUsize,
     load: AtomicUsize,
+    evict: AtomicUsize,
 }
 
 pub trait Metrics {
@@ -36,6 +40,7 @@ pub trait Metrics {
     fn record_hit(&self);
     fn record_miss(&self);
     fn record_load(&self);
+    fn record_eviction(&self);
 
     fn snapshot(&self, open_files: usize) -> Self::Snapshot;
 }
@@ -58,12 +63,18 @@ impl Metrics for Stats {
         self.load.fetch_add(1, Ordering::Relaxed);
     }
 
+    fn record_eviction(&self) {
+        trace!("pack eviction");
+        self.evict.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn snapshot(&self, open_files: usize) -> Self::Snapshot {
         StatsView {
             cache_hits: self.hits.load(Ordering::Relaxed),
             cache_misses: self.miss.load(Ordering::Relaxed),
             file_loads: self.load.load(Ordering::Relaxed),
             open_files,
+            evictions: self.evict.load(Ordering::Relaxed),
         }
     }
 }
@@ -74,6 +85,7 @@ impl Metrics for () {
     fn record_hit(&self) {}
     fn record_miss(&self) {}
     fn record_load(&self) {}
+    fn record_eviction(&self) {}
 
     fn snapshot(&self, _: usize) -> Self::Snapshot {}
 }
@@ -3,5 +3,11 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use std::time::Duration;
+
 pub mod db;
+pub mod reftable;
 pub use git_ref::*;
+
+/// How long to retry acquiring ref (or reftable stack) locks before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_millis(500);
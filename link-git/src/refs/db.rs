@@ -9,11 +9,16 @@ use std::{
     io,
     path::{Path, PathBuf},
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::SystemTime,
 };
 
 use git_ref::{
-    file::{self, iter::LooseThenPacked, Transaction, WriteReflog},
+    file::{
+        self,
+        iter::{loose_then_packed, LooseThenPacked},
+        Transaction,
+        WriteReflog,
+    },
     packed,
     FullName,
     PartialNameRef,
@@ -22,7 +27,27 @@ use git_ref::{
 };
 use parking_lot::RwLock;
 
+mod update;
+pub use update::{Edit, Expected, New, Outcome};
+
+pub mod namespace;
+pub use namespace::NamespacedSnapshot;
+
+mod pack;
+pub use pack::Packing;
+
+mod glob;
+pub use glob::{Match, Matching};
+
+pub mod reflog;
+pub use reflog::Reflog;
+
+#[cfg(feature = "notify")]
+pub mod watch;
+
 pub mod error {
+    pub use super::{pack::error::PackRefs, reflog::error::Expire, update::error::Update};
+
     use super::*;
     use thiserror::Error;
 
@@ -119,6 +144,12 @@ impl Refdb {
         }
     }
 
+    /// Forget the cached `packed-refs`, so that the next [`Refdb::snapshot`]
+    /// reads them from disk again.
+    fn invalidate_packed(&self) {
+        *self.packed.write() = None;
+    }
+
     fn reload<F>(&self, modified_while_blocked: F) -> Result<Snapshot, error::Snapshot>
     where
         F: FnOnce(&Packed) -> bool,
@@ -226,6 +257,24 @@ impl Snapshot {
     }
 }
 
+/// The git dir of the repository `store` belongs to.
+fn git_dir(store: &file::Store) -> &Path {
+    &store.base
+}
+
+/// `true` if an error yielded by [`Snapshot::iter`] is due to the loose refs
+/// directory of the prefix not existing.
+///
+/// This happens if the refs under the prefix were packed (or never existed)
+/// and the directory _containing_ the prefix does not exist either. The error
+/// is yielded before any packed refs, which are still iterated over.
+fn is_missing_dir(e: &loose_then_packed::Error) -> bool {
+    matches!(
+        e,
+        loose_then_packed::Error::Traversal(e) if e.kind() == io::ErrorKind::NotFound
+    )
+}
+
 struct Packed {
     buf: Arc<packed::Buffer>,
     path: PathBuf,
@@ -238,7 +287,7 @@ impl Packed {
 
         let _lock = Marker::acquire_to_hold_resource(
             &path,
-            acquire::Fail::AfterDurationWithBackoff(Duration::from_millis(500)),
+            acquire::Fail::AfterDurationWithBackoff(super::LOCK_TIMEOUT),
             None,
         )?;
         match path.metadata() {
@@ -262,7 +311,7 @@ impl Packed {
 
             Ok(meta) => {
                 let mtime = meta.modified()?;
-                Ok(self.mtime == mtime)
+                Ok(self.mtime != mtime)
             },
         }
     }
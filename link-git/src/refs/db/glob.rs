@@ -0,0 +1,100 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Iterating over refs matching a refspec pattern.
+
+use std::{io, path::PathBuf};
+
+use bstr::{BStr, BString, ByteSlice as _};
+use git_ref::{
+    file::iter::{loose_then_packed, LooseThenPacked},
+    Reference,
+};
+use git_ref_format::{
+    refspec::{self, PatternStr},
+    RefStr,
+};
+
+use super::Snapshot;
+
+/// A reference matched by [`Snapshot::iter_matching`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub reference: Reference,
+    /// The part of the name matched by the `*` glob, if the pattern has one.
+    pub capture: Option<BString>,
+}
+
+impl Snapshot {
+    /// Iterate over the refs whose names match `pattern`, in the same order
+    /// as [`Snapshot::iter`].
+    ///
+    /// Like in a refspec, a `*` in the pattern matches any sequence of
+    /// characters, including `/`. Eg. `refs/remotes/*/heads/main` matches
+    /// `refs/remotes/a/heads/main` as well as `refs/remotes/a/b/heads/main`.
+    ///
+    /// Only the refs under the longest literal prefix of `pattern` (up to the
+    /// first component containing the glob) are visited.
+    pub fn iter_matching<'a>(&'a self, pattern: &'a PatternStr) -> io::Result<Matching<'a>> {
+        let prefix = literal_prefix(pattern);
+        let inner = if prefix.as_os_str().is_empty() {
+            self.iter(None::<PathBuf>)?
+        } else {
+            self.iter(Some(prefix))?
+        };
+
+        Ok(Matching { inner, pattern })
+    }
+}
+
+/// The directory of refs which can possibly match `pattern`.
+///
+/// Consists of the leading components without a glob, except for the last
+/// component, which is always matched against ref names.
+fn literal_prefix(pattern: &PatternStr) -> PathBuf {
+    let mut components = pattern.components().collect::<Vec<_>>();
+    components.pop();
+    components
+        .into_iter()
+        .take_while(|c| matches!(c, refspec::Component::Normal(_)))
+        .map(|c| c.as_str().to_owned())
+        .collect()
+}
+
+/// Iterator created by [`Snapshot::iter_matching`].
+pub struct Matching<'a> {
+    inner: LooseThenPacked<'a, 'a>,
+    pattern: &'a PatternStr,
+}
+
+impl Matching<'_> {
+    /// Match `name`, returning the glob capture if any.
+    fn matches(&self, name: &BStr) -> Option<Option<BString>> {
+        let name = RefStr::try_from_str(name.to_str().ok()?).ok()?;
+        let capture = self.pattern.matches(name)?;
+        if self.pattern.contains('*') {
+            Some(Some(capture.as_str().into()))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+impl Iterator for Matching<'_> {
+    type Item = Result<Match, loose_then_packed::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let reference = match self.inner.next()? {
+                Err(e) if super::is_missing_dir(&e) => continue,
+                Err(e) => return Some(Err(e)),
+                Ok(r) => r,
+            };
+            if let Some(capture) = self.matches(reference.name.as_bstr()) {
+                return Some(Ok(Match { reference, capture }));
+            }
+        }
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Views of a [`Snapshot`] scoped to a (possibly nested) git namespace.
+
+use std::{
+    convert::TryFrom,
+    io,
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice as _;
+use git_ref::{
+    file::{self, iter::LooseThenPacked},
+    FullName,
+    Reference,
+    Target,
+};
+use git_ref_format::{name, Component, Qualified, RefStr, RefString};
+
+use super::{update, Edit, Expected, New, Outcome, Snapshot};
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Name {
+        #[error("{0:?} is not a qualified ref name")]
+        Unqualified(FullName),
+
+        #[error("{name:?} is not in namespace {namespace}")]
+        Foreign {
+            name: FullName,
+            namespace: RefString,
+        },
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Find {
+        #[error(transparent)]
+        Name(#[from] Name),
+
+        #[error(transparent)]
+        Find(#[from] file::find::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Follow {
+        #[error("reference {0:?} not found")]
+        NotFound(FullName),
+
+        #[error(transparent)]
+        Name(#[from] Name),
+
+        #[error(transparent)]
+        Find(#[from] file::find::Error),
+
+        #[error(transparent)]
+        Follow(#[from] crate::refdb::error::Follow),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Update {
+        #[error(transparent)]
+        Name(#[from] Name),
+
+        #[error(transparent)]
+        Update(#[from] update::error::Update),
+    }
+}
+
+impl Snapshot {
+    /// A view of the refs under `refs/namespaces/<namespace>/`.
+    ///
+    /// The view operates on ref names relative to the namespace, ie. what
+    /// `git` would see with `GIT_NAMESPACE=<namespace>` in effect.
+    pub fn namespaced<'a>(&self, namespace: impl Into<Component<'a>>) -> NamespacedSnapshot {
+        NamespacedSnapshot {
+            snapshot: self.clone(),
+            namespaces: vec![owned(namespace.into())],
+        }
+    }
+}
+
+/// A [`Snapshot`] scoped to a git namespace, obtained via
+/// [`Snapshot::namespaced`].
+///
+/// Names passed in are [`Qualified`] names relative to the namespace, and
+/// names of references returned have the namespace stripped. This also
+/// applies to the targets of symbolic refs, unless they point outside of the
+/// namespace.
+#[derive(Clone)]
+pub struct NamespacedSnapshot {
+    snapshot: Snapshot,
+    /// Outermost first.
+    namespaces: Vec<Component<'static>>,
+}
+
+impl NamespacedSnapshot {
+    /// A view of the nested namespace `namespace` within this one.
+    pub fn namespaced<'a>(&self, namespace: impl Into<Component<'a>>) -> Self {
+        let mut namespaces = self.namespaces.clone();
+        namespaces.push(owned(namespace.into()));
+        Self {
+            snapshot: self.snapshot.clone(),
+            namespaces,
+        }
+    }
+
+    /// The namespaces of this view, outermost first.
+    pub fn namespaces(&self) -> &[Component<'static>] {
+        &self.namespaces
+    }
+
+    /// The prefix of all refs in this view, eg.
+    /// `refs/namespaces/a/refs/namespaces/b`.
+    pub fn prefix(&self) -> RefString {
+        let mut prefix = name::REFS.to_ref_string();
+        for (i, ns) in self.namespaces.iter().enumerate() {
+            if i > 0 {
+                prefix.push(name::REFS);
+            }
+            prefix.push(name::NAMESPACES);
+            prefix.push(ns);
+        }
+        prefix
+    }
+
+    /// The underlying, un-namespaced [`Snapshot`].
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+
+    pub fn find(&self, name: &Qualified) -> Result<Option<Reference>, error::Find> {
+        let full = full_name(&self.namespace(name));
+        match self.snapshot.find(full.to_partial())? {
+            None => Ok(None),
+            Some(r) => Ok(Some(self.strip_ref(r)?)),
+        }
+    }
+
+    /// Iterate over the refs in the namespace, optionally restricted to those
+    /// under `prefix` (relative to the namespace, eg. `refs/heads`).
+    pub fn iter(&self, prefix: Option<impl AsRef<Path>>) -> io::Result<Iter<'_>> {
+        let mut full = PathBuf::from(self.prefix().as_str());
+        match prefix {
+            None => full.push(name::str::REFS),
+            Some(p) => full.push(p),
+        }
+        Ok(Iter {
+            inner: self.snapshot.iter(Some(full))?,
+            view: self,
+        })
+    }
+
+    /// Follow a symbolic reference until a direct reference is found.
+    ///
+    /// `symref` is looked up by name within the namespace, and followed as
+    /// per [`Snapshot::follow`]. It is an error if the result lies outside of
+    /// the namespace.
+    pub fn follow(&self, symref: &Reference) -> Result<Reference, error::Follow> {
+        let name = full_name(&self.namespace(&qualified(&symref.name)?));
+        let full = self
+            .snapshot
+            .find(name.to_partial())?
+            .ok_or_else(|| error::Follow::NotFound(symref.name.clone()))?;
+        let target = self.snapshot.follow(&full)?;
+        Ok(self.strip_ref(target)?)
+    }
+
+    /// Atomically apply a batch of `edits` to refs in the namespace, as per
+    /// [`super::Refdb::update`].
+    ///
+    /// The names of the edits and any symbolic targets are interpreted
+    /// relative to the namespace, and so are the returned [`Outcome`]s.
+    pub fn update<I>(
+        &self,
+        edits: I,
+        committer: &git_actor::Signature,
+    ) -> Result<Vec<Outcome>, error::Update>
+    where
+        I: IntoIterator<Item = Edit>,
+    {
+        let edits = edits
+            .into_iter()
+            .map(|edit| self.namespace_edit(edit))
+            .collect::<Result<Vec<_>, _>>()?;
+        update::apply(&self.snapshot.store, edits, committer)?
+            .into_iter()
+            .map(|outcome| self.strip_outcome(outcome))
+            .collect::<Result<_, _>>()
+            .map_err(error::Update::from)
+    }
+
+    fn namespace<'a>(&self, name: &Qualified<'a>) -> Qualified<'static> {
+        self.namespaces
+            .iter()
+            .rev()
+            .fold(name.to_owned(), |name, ns| {
+                name.add_namespace(ns.clone()).into_owned().into_qualified()
+            })
+    }
+
+    fn strip(&self, name: &FullName) -> Result<Qualified<'static>, error::Name> {
+        let foreign = || error::Name::Foreign {
+            name: name.clone(),
+            namespace: self.prefix(),
+        };
+        let mut stripped = qualified(name)?;
+        for ns in &self.namespaces {
+            let namespaced = stripped.namespaced().ok_or_else(foreign)?;
+            if namespaced.namespace().as_str() != ns.as_str() {
+                return Err(foreign());
+            }
+            stripped = namespaced.strip_namespace();
+        }
+        Ok(stripped)
+    }
+
+    fn strip_ref(&self, mut r: Reference) -> Result<Reference, error::Name> {
+        r.name = full_name(&self.strip(&r.name)?);
+        r.target = self.strip_target(r.target);
+        Ok(r)
+    }
+
+    /// Symbolic targets outside of the namespace are left as they are.
+    fn strip_target(&self, target: Target) -> Target {
+        match target {
+            Target::Symbolic(name) => match self.strip(&name) {
+                Ok(stripped) => Target::Symbolic(full_name(&stripped)),
+                Err(_) => Target::Symbolic(name),
+            },
+            peeled => peeled,
+        }
+    }
+
+    fn namespace_target(&self, target: Target) -> Result<Target, error::Name> {
+        match target {
+            Target::Symbolic(name) => Ok(Target::Symbolic(full_name(
+                &self.namespace(&qualified(&name)?),
+            ))),
+            peeled => Ok(peeled),
+        }
+    }
+
+    fn namespace_edit(&self, edit: Edit) -> Result<Edit, error::Name> {
+        let expected = match edit.expected {
+            Expected::Is(target) => Expected::Is(self.namespace_target(target)?),
+            Expected::AbsentOr(target) => Expected::AbsentOr(self.namespace_target(target)?),
+            other => other,
+        };
+        let new = match edit.new {
+            New::Symbolic(name) => New::Symbolic(full_name(&self.namespace(&qualified(&name)?))),
+            other => other,
+        };
+        Ok(Edit {
+            name: full_name(&self.namespace(&qualified(&edit.name)?)),
+            expected,
+            new,
+            reflog_message: edit.reflog_message,
+        })
+    }
+
+    fn strip_outcome(&self, outcome: Outcome) -> Result<Outcome, error::Name> {
+        Ok(match outcome {
+            Outcome::Created { name, new } => Outcome::Created {
+                name: full_name(&self.strip(&name)?),
+                new: self.strip_target(new),
+            },
+            Outcome::Updated {
+                name,
+                previous,
+                new,
+            } => Outcome::Updated {
+                name: full_name(&self.strip(&name)?),
+                previous: self.strip_target(previous),
+                new: self.strip_target(new),
+            },
+            Outcome::Deleted { name, previous } => Outcome::Deleted {
+                name: full_name(&self.strip(&name)?),
+                previous: previous.map(|target| self.strip_target(target)),
+            },
+        })
+    }
+}
+
+/// Iterator over the refs of a [`NamespacedSnapshot`], see
+/// [`NamespacedSnapshot::iter`].
+pub struct Iter<'a> {
+    inner: LooseThenPacked<'a, 'a>,
+    view: &'a NamespacedSnapshot,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<Reference, file::iter::loose_then_packed::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Err(e) if super::is_missing_dir(&e) => continue,
+                Err(e) => return Some(Err(e)),
+                Ok(r) => {
+                    // The path prefix may also match eg. `refs/namespaces/a/refsx`
+                    if let Ok(r) = self.view.strip_ref(r) {
+                        return Some(Ok(r));
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn owned(c: Component) -> Component<'static> {
+    Component::from_refstring(c.to_ref_string()).expect("a component is a valid component")
+}
+
+fn qualified(name: &FullName) -> Result<Qualified<'static>, error::Name> {
+    name.as_bstr()
+        .to_str()
+        .ok()
+        .and_then(|s| RefStr::try_from_str(s).ok())
+        .and_then(|s| s.qualified())
+        .map(|q| q.into_owned())
+        .ok_or_else(|| error::Name::Unqualified(name.clone()))
+}
+
+fn full_name(name: &Qualified) -> FullName {
+    FullName::try_from(name.as_str()).expect("qualified ref names are valid full names")
+}
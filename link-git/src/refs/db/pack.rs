@@ -0,0 +1,329 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Folding loose refs into `packed-refs`, like [`git-pack-refs`].
+//!
+//! [`git-pack-refs`]: https://git-scm.com/docs/git-pack-refs
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use git_hash::{oid, ObjectId};
+use git_lock::{acquire::Fail, Marker};
+use git_pack::cache::DecodeEntry;
+use git_ref_format::RefStr;
+use tracing::warn;
+
+use super::{git_dir, Packed, Refdb};
+use crate::{
+    hex,
+    odb::{self, index, window, Odb},
+    refs::LOCK_TIMEOUT,
+    repository,
+};
+
+const HEADER: &[u8] = b"# pack-refs with: peeled fully-peeled sorted \n";
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum PackRefs {
+        #[error("failed to lock packed-refs")]
+        Lock(#[from] git_lock::acquire::Error),
+
+        #[error("malformed packed-refs at line {0}")]
+        Malformed(usize),
+
+        #[error("failed to peel {id}")]
+        Peel {
+            id: ObjectId,
+            #[source]
+            source: odb::Error,
+        },
+
+        #[error("failed to reload packed-refs")]
+        Reload(#[from] crate::refdb::error::Snapshot),
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+}
+
+/// The result of [`Refdb::pack_refs`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Packing {
+    /// Number of loose refs written to `packed-refs`.
+    pub packed: usize,
+    /// Number of loose ref files removed. This is less than
+    /// [`Packing::packed`] only if removing a file failed.
+    pub pruned: usize,
+}
+
+struct Entry {
+    target: ObjectId,
+    peeled: Option<ObjectId>,
+}
+
+impl Refdb {
+    /// Fold the loose refs under `prefix` (relative to the git dir, eg.
+    /// `refs/namespaces/xyz`), or all loose refs if `None`, into
+    /// `packed-refs`.
+    ///
+    /// Annotated tags are peeled using `odb`, so readers don't need to access
+    /// the object database to do so. Symbolic refs are never packed.
+    ///
+    /// `packed-refs` is locked for the duration of the operation. Each loose
+    /// ref is locked and re-read before it is packed, and stays locked until
+    /// it has been removed after the new `packed-refs` have been committed, so
+    /// concurrent updates or deletions of packed refs are not lost. A ref
+    /// which is already locked is left alone, as it is about to be updated
+    /// anyway.
+    ///
+    /// The in-memory `packed-refs` are reloaded afterwards.
+    pub fn pack_refs<I, D>(
+        &self,
+        prefix: Option<impl AsRef<Path>>,
+        odb: &Odb<I, D>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<Packing, error::PackRefs>
+    where
+        I: index::Index,
+        D: window::Cache,
+    {
+        let path = self.store.packed_refs_path();
+        let git_dir = git_dir(&self.store);
+        let mut lock = git_lock::File::acquire_to_update_resource(
+            &path,
+            Fail::AfterDurationWithBackoff(LOCK_TIMEOUT),
+            None,
+        )?;
+
+        let (mut refs, fully_peeled) = read_packed(&path)?;
+        let mut buf = Vec::new();
+        let mut peel = |id: &ObjectId| -> Result<Option<ObjectId>, error::PackRefs> {
+            peel_tag(odb, id, &mut buf, cache).map_err(|source| error::PackRefs::Peel {
+                id: id.to_owned(),
+                source,
+            })
+        };
+        if !fully_peeled {
+            for entry in refs.values_mut() {
+                entry.peeled = peel(&entry.target)?;
+            }
+        }
+
+        let start = match &prefix {
+            None => git_dir.join("refs"),
+            Some(p) => git_dir.join(p),
+        };
+        let mut locked = Vec::new();
+        for name in loose_refs(git_dir, &start)? {
+            let path = git_dir.join(&name);
+            let lock = match Marker::acquire_to_hold_resource(&path, Fail::Immediately, None) {
+                Ok(lock) => lock,
+                Err(_) => continue,
+            };
+            // The ref may have been modified or deleted since we scanned it
+            if let Some((_, target)) = read_loose(git_dir, &path)? {
+                refs.insert(
+                    name.clone(),
+                    Entry {
+                        target,
+                        peeled: peel(&target)?,
+                    },
+                );
+                locked.push((name, lock));
+            }
+        }
+
+        lock.write_all(HEADER)?;
+        for (name, entry) in &refs {
+            writeln!(lock, "{} {}", entry.target, name)?;
+            if let Some(peeled) = entry.peeled {
+                writeln!(lock, "^{}", peeled)?;
+            }
+        }
+        lock.commit().map_err(|e| e.error)?;
+
+        let packed = locked.len();
+        let pruned = locked
+            .into_iter()
+            .map(|locked| prune(git_dir, locked))
+            .filter(|removed| *removed)
+            .count();
+
+        *self.packed.write() = Packed::open(path)?;
+
+        Ok(Packing { packed, pruned })
+    }
+}
+
+/// Read the current `packed-refs`, and whether they are "fully-peeled".
+fn read_packed(path: &Path) -> Result<(BTreeMap<String, Entry>, bool), error::PackRefs> {
+    let data = match fs::read(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((BTreeMap::new(), true)),
+        Err(e) => return Err(e.into()),
+        Ok(data) => data,
+    };
+
+    let mut refs: BTreeMap<String, Entry> = BTreeMap::new();
+    let mut fully_peeled = false;
+    let mut last: Option<String> = None;
+    for (i, line) in data.split(|b| *b == b'\n').enumerate() {
+        let malformed = || error::PackRefs::Malformed(i + 1);
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(traits) = line.strip_prefix(b"# pack-refs with:") {
+            fully_peeled = traits.split(|b| *b == b' ').any(|t| t == b"fully-peeled");
+        } else if let Some(hex) = line.strip_prefix(b"^") {
+            let entry = last
+                .take()
+                .and_then(|name| refs.get_mut(&name))
+                .ok_or_else(malformed)?;
+            entry.peeled = Some(hex::object_id(hex).ok_or_else(malformed)?);
+        } else {
+            let sp = line.iter().position(|b| *b == b' ').ok_or_else(malformed)?;
+            let target = hex::object_id(&line[..sp]).ok_or_else(malformed)?;
+            let name = std::str::from_utf8(&line[sp + 1..]).map_err(|_| malformed())?;
+            refs.insert(
+                name.to_owned(),
+                Entry {
+                    target,
+                    peeled: None,
+                },
+            );
+            last = Some(name.to_owned());
+        }
+    }
+
+    Ok((refs, fully_peeled))
+}
+
+/// Collect the names of the direct loose refs under `dir`.
+fn loose_refs(git_dir: &Path, dir: &Path) -> io::Result<Vec<String>> {
+    let mut refs = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+            Ok(entries) => entries,
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            if let Some((name, _)) = read_loose(git_dir, &path)? {
+                refs.push(name);
+            }
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Read a loose ref file, returning `None` if it is not a valid, direct ref.
+fn read_loose(git_dir: &Path, path: &Path) -> io::Result<Option<(String, ObjectId)>> {
+    let name = match ref_name(git_dir, path) {
+        None => return Ok(None),
+        Some(name) => name,
+    };
+    let data = match fs::read(path) {
+        // Deleted concurrently
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+        Ok(data) => data,
+    };
+    let hex = data.strip_suffix(b"\n").unwrap_or(&data);
+    if hex.starts_with(b"ref:") {
+        return Ok(None);
+    }
+    match hex::object_id(hex) {
+        Some(target) => Ok(Some((name, target))),
+        None => {
+            warn!("ignoring malformed loose ref {}", name);
+            Ok(None)
+        },
+    }
+}
+
+/// The ref name of the loose ref file at `path`, if it is a valid one.
+///
+/// Lock files are not valid ref names.
+fn ref_name(git_dir: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(git_dir).ok()?;
+    let name = rel
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?
+        .join("/");
+    RefStr::try_from_str(&name).ok()?;
+    Some(name)
+}
+
+/// Remove the loose ref `name`, which must be locked by the caller. The lock
+/// is released before removing empty parent directories.
+///
+/// Returns `true` if the ref file was removed.
+fn prune(git_dir: &Path, (name, lock): (String, Marker)) -> bool {
+    let path = git_dir.join(&name);
+    let removed = fs::remove_file(&path);
+    drop(lock);
+    if let Err(e) = removed {
+        warn!("failed to prune loose ref {}: {}", name, e);
+        return false;
+    }
+    remove_empty_parents(git_dir, &path);
+
+    true
+}
+
+/// Remove empty directories left behind by pruning, stopping at the ref
+/// category (eg. `refs/heads`).
+fn remove_empty_parents(git_dir: &Path, path: &Path) {
+    let keep = git_dir.join("refs");
+    let mut dir = path.parent().map(PathBuf::from);
+    while let Some(d) = dir {
+        if d.parent() == Some(keep.as_path()) || !d.starts_with(&keep) {
+            break;
+        }
+        // Fails if the directory is not empty
+        if fs::remove_dir(&d).is_err() {
+            break;
+        }
+        dir = d.parent().map(PathBuf::from);
+    }
+}
+
+/// If `id` is an annotated tag, the id of the (non-tag) object it points to.
+fn peel_tag<I, D>(
+    odb: &Odb<I, D>,
+    id: &oid,
+    buf: &mut Vec<u8>,
+    cache: &mut impl DecodeEntry,
+) -> Result<Option<ObjectId>, odb::Error>
+where
+    I: index::Index,
+    D: window::Cache,
+{
+    match repository::peel(odb, id, None, buf, cache) {
+        Ok(peeled) if peeled.as_ref() == id => Ok(None),
+        Ok(peeled) => Ok(Some(peeled)),
+        Err(repository::error::Peel::Find(e)) => Err(e),
+        // git doesn't record a peeled value for missing or broken objects
+        // either
+        Err(_) => Ok(None),
+    }
+}
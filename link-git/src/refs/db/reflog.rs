@@ -0,0 +1,355 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Reading and expiring the reflogs of loose refs.
+
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bstr::{BString, ByteSlice as _};
+use git_actor::{Sign, Signature, Time};
+use git_hash::ObjectId;
+use git_lock::acquire::Fail;
+use git_ref::FullName;
+use tracing::warn;
+
+use super::{git_dir, Refdb, Snapshot};
+use crate::{hex, refs::LOCK_TIMEOUT};
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    #[error("malformed reflog entry: {0}")]
+    pub struct Parse(pub &'static str);
+
+    #[derive(Debug, Error)]
+    pub enum Expire {
+        #[error("failed to lock ref or reflog")]
+        Lock(#[from] git_lock::acquire::Error),
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+}
+
+/// An entry of a ref's reflog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub previous: ObjectId,
+    pub new: ObjectId,
+    pub committer: Signature,
+    pub message: BString,
+}
+
+impl Entry {
+    fn parse(line: &[u8]) -> Result<Self, error::Parse> {
+        let (ids, message) = match line.find_byte(b'\t') {
+            None => (line, &b""[..]),
+            Some(tab) => (&line[..tab], &line[tab + 1..]),
+        };
+        let mut parts = ids.splitn(3, |b| *b == b' ');
+        let previous = parts
+            .next()
+            .and_then(hex::object_id)
+            .ok_or(error::Parse("invalid previous id"))?;
+        let new = parts
+            .next()
+            .and_then(hex::object_id)
+            .ok_or(error::Parse("invalid new id"))?;
+        let committer = parts
+            .next()
+            .ok_or(error::Parse("missing committer"))
+            .and_then(parse_signature)?;
+
+        Ok(Self {
+            previous,
+            new,
+            committer,
+            message: message.into(),
+        })
+    }
+
+    /// The time of the entry.
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.committer.time.time as u64)
+    }
+}
+
+/// `Name <email> 1234567890 +0100`
+fn parse_signature(ident: &[u8]) -> Result<Signature, error::Parse> {
+    let lt = ident
+        .find_byte(b'<')
+        .ok_or(error::Parse("invalid committer"))?;
+    let gt = ident
+        .rfind_byte(b'>')
+        .ok_or(error::Parse("invalid committer"))?;
+    if gt < lt {
+        return Err(error::Parse("invalid committer"));
+    }
+    let mut time = ident[gt + 1..].fields();
+    let secs = time
+        .next()
+        .and_then(|t| t.to_str().ok())
+        .and_then(|t| t.parse().ok())
+        .ok_or(error::Parse("invalid time"))?;
+    let tz = time.next().ok_or(error::Parse("missing timezone"))?;
+    let (sign, hhmm) = match tz.split_first() {
+        Some((b'-', hhmm)) => (Sign::Minus, hhmm),
+        Some((b'+', hhmm)) => (Sign::Plus, hhmm),
+        _ => return Err(error::Parse("invalid timezone")),
+    };
+    let hhmm: i32 = hhmm
+        .to_str()
+        .ok()
+        .filter(|s| s.len() == 4)
+        .and_then(|s| s.parse().ok())
+        .ok_or(error::Parse("invalid timezone"))?;
+    let offset = hhmm / 100 * 3600 + hhmm % 100 * 60;
+    let offset = match sign {
+        Sign::Minus => -offset,
+        Sign::Plus => offset,
+    };
+
+    Ok(Signature {
+        name: ident[..lt].trim_end().into(),
+        email: ident[lt + 1..gt].into(),
+        time: Time {
+            time: secs,
+            offset,
+            sign,
+        },
+    })
+}
+
+/// The reflog of a ref, as read by [`Snapshot::reflog`].
+#[derive(Clone, Debug)]
+pub struct Reflog {
+    name: FullName,
+    data: Vec<u8>,
+}
+
+impl Reflog {
+    pub fn name(&self) -> &FullName {
+        &self.name
+    }
+
+    /// Iterate over the entries, oldest first.
+    ///
+    /// Use [`Iterator::rev`] to iterate most recent first.
+    pub fn iter(&self) -> Entries<'_> {
+        Entries {
+            lines: self
+                .data
+                .lines()
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .into_iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Reflog {
+    type Item = Result<Entry, error::Parse>;
+    type IntoIter = Entries<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the entries of a [`Reflog`].
+pub struct Entries<'a> {
+    lines: std::vec::IntoIter<&'a [u8]>,
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<Entry, error::Parse>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines.next().map(Entry::parse)
+    }
+}
+
+impl DoubleEndedIterator for Entries<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.lines.next_back().map(Entry::parse)
+    }
+}
+
+impl Snapshot {
+    /// Read the reflog of the ref `name`.
+    ///
+    /// Returns `None` if the ref has no reflog. Note that the reflog is not
+    /// part of the snapshot, but read from disk at the time of calling this
+    /// method.
+    pub fn reflog(&self, name: &FullName) -> io::Result<Option<Reflog>> {
+        let path = log_path(git_dir(&self.store), name);
+        match fs::read(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+            Ok(data) => Ok(Some(Reflog {
+                name: name.clone(),
+                data,
+            })),
+        }
+    }
+}
+
+/// Which reflog entries to remove in [`Refdb::expire_reflog`].
+///
+/// An entry is expired if it matches any of the criteria.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Expire {
+    /// Expire entries recorded before this time.
+    pub older_than: Option<SystemTime>,
+    /// Expire all but this many of the most recent entries.
+    pub keep_latest: Option<usize>,
+}
+
+impl Refdb {
+    /// Remove the entries matching `policy` from the reflog of `name`.
+    ///
+    /// The ref is locked while the reflog is rewritten, so no entries are
+    /// appended concurrently. Returns the number of entries removed.
+    ///
+    /// Malformed entries are removed, too, as `git reflog expire` does. They
+    /// are counted as removed, but not towards [`Expire::keep_latest`].
+    pub fn expire_reflog(&self, name: &FullName, policy: Expire) -> Result<usize, error::Expire> {
+        use git_lock::{File, Marker};
+
+        let git_dir = git_dir(&self.store);
+        let path = log_path(git_dir, name);
+        if !path.is_file() {
+            return Ok(0);
+        }
+
+        let _ref = Marker::acquire_to_hold_resource(
+            git_dir.join(name_path(name)),
+            Fail::AfterDurationWithBackoff(LOCK_TIMEOUT),
+            None,
+        )?;
+        let mut log = File::acquire_to_update_resource(
+            &path,
+            Fail::AfterDurationWithBackoff(LOCK_TIMEOUT),
+            None,
+        )?;
+        let data = match fs::read(&path) {
+            // Deleted while we were acquiring the locks
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+            Ok(data) => data,
+        };
+        let mut removed = 0;
+        let entries = data
+            .lines_with_terminator()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(
+                |line| match Entry::parse(line.trim_end_with(|c| c == '\n')) {
+                    Ok(entry) => Some((line, entry)),
+                    Err(e) => {
+                        warn!("{}: dropping {:?}: {}", path.display(), line.as_bstr(), e);
+                        removed += 1;
+                        None
+                    },
+                },
+            )
+            .collect::<Vec<_>>();
+        let keep_from = policy
+            .keep_latest
+            .map(|keep| entries.len().saturating_sub(keep))
+            .unwrap_or(0);
+
+        for (i, (line, entry)) in entries.iter().enumerate() {
+            let too_old = policy
+                .older_than
+                .map(|cutoff| entry.time() < cutoff)
+                .unwrap_or(false);
+            if i < keep_from || too_old {
+                removed += 1;
+            } else {
+                log.write_all(line)?;
+            }
+        }
+        // Dropping the lock without committing leaves the reflog untouched
+        if removed > 0 {
+            log.commit().map_err(|e| e.error)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// [`Refdb::expire_reflog`] for all refs which have a reflog.
+    ///
+    /// Failing to expire the reflog of a ref (eg. because the ref is locked)
+    /// is logged, and does not prevent the remaining reflogs from being
+    /// expired. Returns the total number of entries removed.
+    pub fn expire_reflogs(&self, policy: Expire) -> Result<usize, error::Expire> {
+        let logs = git_dir(&self.store).join("logs");
+        let mut removed = 0;
+        for name in log_names(&logs)? {
+            match self.expire_reflog(&name, policy) {
+                Ok(n) => removed += n,
+                Err(e) => warn!("failed to expire reflog of {}: {}", name.as_bstr(), e),
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// The names of all refs with a reflog under `logs`.
+fn log_names(logs: &Path) -> io::Result<Vec<FullName>> {
+    use std::convert::TryFrom;
+
+    let mut names = Vec::new();
+    let mut stack = vec![logs.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+            Ok(entries) => entries,
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let name = path
+                .strip_prefix(logs)
+                .ok()
+                .and_then(|rel| {
+                    rel.components()
+                        .map(|c| c.as_os_str().to_str())
+                        .collect::<Option<Vec<_>>>()
+                })
+                .map(|cs| cs.join("/"));
+            // Skips lock files, among others
+            if let Some(name) = name.and_then(|name| FullName::try_from(name.as_str()).ok()) {
+                names.push(name);
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+fn log_path(git_dir: &Path, name: &FullName) -> PathBuf {
+    git_dir.join("logs").join(name_path(name))
+}
+
+/// The relative path of the loose ref `name`.
+fn name_path(name: &FullName) -> PathBuf {
+    name.as_bstr()
+        .split_str("/")
+        .map(|c| c.to_str_lossy().into_owned())
+        .collect()
+}
@@ -0,0 +1,240 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use bstr::BString;
+use git_hash::ObjectId;
+use git_ref::{
+    file::{
+        self,
+        transaction::{commit, prepare},
+    },
+    transaction::{Change, LogChange, PreviousValue, RefEdit, RefLog},
+    FullName,
+    Target,
+};
+
+use super::Refdb;
+use crate::refs::LOCK_TIMEOUT;
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Update {
+        /// An [`super::Edit`] deletes a ref which is
+        /// [`super::Expected::Absent`]. No ref was modified.
+        #[error("cannot delete {0:?}, which is expected to be absent")]
+        DeleteAbsent(FullName),
+
+        /// Locking failed, or a ref did not have the [`super::Expected`]
+        /// value. No ref was modified.
+        #[error("failed to prepare ref transaction")]
+        Prepare(#[from] prepare::Error),
+
+        #[error("failed to commit ref transaction")]
+        Commit(#[from] commit::Error),
+    }
+}
+
+/// The value a ref is expected to have before an [`Edit`] is applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// Apply the edit regardless of the current value.
+    Any,
+    /// The ref must not exist.
+    ///
+    /// This is not valid for [`New::Delete`].
+    Absent,
+    /// The ref must exist, with any value.
+    Present,
+    /// The ref must exist and point to the given target.
+    Is(Target),
+    /// If the ref exists, it must point to the given target.
+    AbsentOr(Target),
+}
+
+impl From<Expected> for PreviousValue {
+    fn from(e: Expected) -> Self {
+        match e {
+            Expected::Any => Self::Any,
+            Expected::Absent => Self::MustNotExist,
+            Expected::Present => Self::MustExist,
+            Expected::Is(target) => Self::MustExistAndMatch(target),
+            Expected::AbsentOr(target) => Self::ExistingMustMatch(target),
+        }
+    }
+}
+
+/// The value a ref should have after an [`Edit`] is applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum New {
+    /// Create or update a direct ref.
+    Direct(ObjectId),
+    /// Create or update a symbolic ref.
+    Symbolic(FullName),
+    /// Delete the ref, including its reflog.
+    Delete,
+}
+
+/// A single ref edit, as part of a batch passed to [`Refdb::update`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub name: FullName,
+    pub expected: Expected,
+    pub new: New,
+    /// The message recorded in the reflog. Ignored for deletions.
+    pub reflog_message: BString,
+}
+
+impl Edit {
+    fn into_ref_edit(self) -> Result<RefEdit, error::Update> {
+        // git-ref panics on this combination
+        if self.new == New::Delete && self.expected == Expected::Absent {
+            return Err(error::Update::DeleteAbsent(self.name));
+        }
+        let expected = self.expected.into();
+        let change = match self.new {
+            New::Delete => Change::Delete {
+                expected,
+                log: RefLog::AndReference,
+            },
+            New::Direct(oid) => Change::Update {
+                log: log_change(self.reflog_message),
+                expected,
+                new: Target::Peeled(oid),
+            },
+            New::Symbolic(name) => Change::Update {
+                log: log_change(self.reflog_message),
+                expected,
+                new: Target::Symbolic(name),
+            },
+        };
+
+        Ok(RefEdit {
+            change,
+            name: self.name,
+            deref: false,
+        })
+    }
+}
+
+/// The effect an [`Edit`] had.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Created {
+        name: FullName,
+        new: Target,
+    },
+    Updated {
+        name: FullName,
+        previous: Target,
+        new: Target,
+    },
+    Deleted {
+        name: FullName,
+        /// `None` if the ref did not exist.
+        previous: Option<Target>,
+    },
+}
+
+impl Outcome {
+    fn from_ref_edit(edit: RefEdit) -> Self {
+        // When a transaction is prepared, the expected value of every ref
+        // which exists is replaced with `MustExistAndMatch` its current value.
+        // Any other variant thus means that the ref did not exist, even if it
+        // carries a target.
+        let previous = |expected: PreviousValue| match expected {
+            PreviousValue::MustExistAndMatch(target) => Some(target),
+            _ => None,
+        };
+        match edit.change {
+            Change::Delete { expected, .. } => Self::Deleted {
+                name: edit.name,
+                previous: previous(expected),
+            },
+            Change::Update { expected, new, .. } => match previous(expected) {
+                None => Self::Created {
+                    name: edit.name,
+                    new,
+                },
+                Some(previous) => Self::Updated {
+                    name: edit.name,
+                    previous,
+                    new,
+                },
+            },
+        }
+    }
+
+    pub fn name(&self) -> &FullName {
+        match self {
+            Self::Created { name, .. }
+            | Self::Updated { name, .. }
+            | Self::Deleted { name, .. } => name,
+        }
+    }
+}
+
+impl Refdb {
+    /// Atomically apply a batch of `edits`.
+    ///
+    /// All refs are locked (retrying for a short while if they are locked by
+    /// another process), and their [`Expected`] values are checked while the
+    /// locks are held. Only if all checks pass are the changes committed,
+    /// otherwise no ref is modified.
+    ///
+    /// The outcomes are returned in the same order as `edits`, with the
+    /// previous values as observed under the lock.
+    ///
+    /// The cached `packed-refs` are invalidated, so that the next
+    /// [`Refdb::snapshot`] observes the changes.
+    pub fn update<I>(
+        &self,
+        edits: I,
+        committer: &git_actor::Signature,
+    ) -> Result<Vec<Outcome>, error::Update>
+    where
+        I: IntoIterator<Item = Edit>,
+    {
+        let res = apply(&self.store, edits, committer);
+        // Deletions may have rewritten packed-refs, even if the commit failed
+        // half-way.
+        self.invalidate_packed();
+        res
+    }
+}
+
+/// Apply `edits` to `store` in a single transaction.
+pub(super) fn apply<I>(
+    store: &file::Store,
+    edits: I,
+    committer: &git_actor::Signature,
+) -> Result<Vec<Outcome>, error::Update>
+where
+    I: IntoIterator<Item = Edit>,
+{
+    let edits = edits
+        .into_iter()
+        .map(Edit::into_ref_edit)
+        .collect::<Result<Vec<_>, _>>()?;
+    let edits = store
+        .transaction()
+        .prepare(
+            edits,
+            git_lock::acquire::Fail::AfterDurationWithBackoff(LOCK_TIMEOUT),
+        )?
+        .commit(committer)?;
+
+    Ok(edits.into_iter().map(Outcome::from_ref_edit).collect())
+}
+
+fn log_change(message: BString) -> LogChange {
+    LogChange {
+        mode: RefLog::AndReference,
+        force_create_reflog: false,
+        message,
+    }
+}
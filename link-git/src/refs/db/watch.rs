@@ -0,0 +1,362 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Subscribe to changes of refs in a [`Refdb`].
+//!
+//! A [`Watcher`] observes the refs of a repository using a single native
+//! filesystem watcher (if available) and a single background thread. Any
+//! number of [`Subscription`]s can be obtained from it, each of which only
+//! rescans the refs when a change under its prefix is detected.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{mpsc, Arc},
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+
+use futures_lite::Stream;
+use git_ref::{FullName, Target};
+use notify::{RecursiveMode, Watcher as _};
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use tracing::{trace, warn};
+
+use super::Refdb;
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Watch {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Subscribe {
+        #[error(transparent)]
+        Scan(#[from] Scan),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Scan {
+        #[error(transparent)]
+        Snapshot(#[from] crate::refdb::error::Snapshot),
+
+        #[error(transparent)]
+        Iter(#[from] git_ref::file::iter::loose_then_packed::Error),
+
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+    }
+}
+
+/// A change to a ref, as observed by a [`Subscription`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Created {
+        name: FullName,
+        new: Target,
+    },
+    Updated {
+        name: FullName,
+        old: Target,
+        new: Target,
+    },
+    Deleted {
+        name: FullName,
+        old: Target,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &FullName {
+        match self {
+            Self::Created { name, .. }
+            | Self::Updated { name, .. }
+            | Self::Deleted { name, .. } => name,
+        }
+    }
+}
+
+/// Watches the refs of a [`Refdb`], obtained from [`Refdb::watch`].
+///
+/// Cloning is cheap, clones share the same filesystem watcher and background
+/// thread. Watching stops when the last clone and the last [`Subscription`]
+/// obtained from it are dropped.
+#[derive(Clone)]
+pub struct Watcher {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    refdb: Refdb,
+    subscriptions: Arc<Mutex<Vec<Subscriber>>>,
+    /// Only ever dropped, the [`Mutex`] makes [`Watcher`] `Sync`.
+    _handles: Mutex<Handles>,
+}
+
+struct Handles {
+    _native: Option<notify::RecommendedWatcher>,
+    /// Dropping this terminates the background thread.
+    _wake: mpsc::Sender<Wake>,
+}
+
+/// The state of a [`Subscription`], as maintained by the background thread.
+struct Subscriber {
+    prefix: PathBuf,
+    seen: BTreeMap<FullName, Target>,
+    events: async_channel::Sender<Event>,
+}
+
+/// What may have changed.
+enum Wake {
+    /// A loose ref or directory, relative to the git dir.
+    Path(PathBuf),
+    /// `packed-refs`, or anything else we can't attribute to a path.
+    All,
+}
+
+impl Refdb {
+    /// Start watching the refs of this [`Refdb`].
+    ///
+    /// Changes to both loose refs and `packed-refs` are detected using the
+    /// platform's native filesystem notifications, if available. Regardless,
+    /// the refs are also compared against the previously seen state every
+    /// `interval`, so changes missed by the notification mechanism are
+    /// eventually reported.
+    pub fn watch(&self, interval: Duration) -> Result<Watcher, error::Watch> {
+        let git_dir = fs::canonicalize(super::git_dir(&self.store))?;
+        let (wake_tx, wake_rx) = mpsc::channel();
+        let native = native_watcher(wake_tx.clone(), &git_dir);
+        let subscriptions = Arc::new(Mutex::new(Vec::new()));
+
+        let refdb = self.clone();
+        let subs = Arc::clone(&subscriptions);
+        thread::Builder::new()
+            .name("refdb-watch".into())
+            .spawn(move || run(refdb, subs, wake_rx, interval))?;
+
+        Ok(Watcher {
+            inner: Arc::new(Inner {
+                refdb: self.clone(),
+                subscriptions,
+                _handles: Mutex::new(Handles {
+                    _native: native,
+                    _wake: wake_tx,
+                }),
+            }),
+        })
+    }
+}
+
+impl Watcher {
+    /// Subscribe to changes of the refs under `prefix`, such as
+    /// `refs/namespaces/<ns>/`.
+    ///
+    /// Events are computed by diffing successive [`Snapshot`]s, so a ref which
+    /// changes multiple times in quick succession may be reported only once,
+    /// with the latest value. Changes which occurred before this method
+    /// returned are not reported.
+    ///
+    /// [`Snapshot`]: super::Snapshot
+    pub fn subscribe(&self, prefix: impl Into<PathBuf>) -> Result<Subscription, error::Subscribe> {
+        let prefix = prefix.into();
+        let seen = scan(&self.inner.refdb, &prefix)?;
+        let (tx, rx) = async_channel::unbounded();
+        self.inner.subscriptions.lock().push(Subscriber {
+            prefix,
+            seen,
+            events: tx,
+        });
+
+        Ok(Subscription {
+            events: rx,
+            _watcher: self.clone(),
+        })
+    }
+}
+
+/// A [`Stream`] of [`Event`]s, obtained from [`Watcher::subscribe`].
+///
+/// Events for this subscription stop when it is dropped.
+#[pin_project]
+pub struct Subscription {
+    #[pin]
+    events: async_channel::Receiver<Event>,
+    _watcher: Watcher,
+}
+
+impl Subscription {
+    /// Wait for the next event, blocking the current thread.
+    ///
+    /// Returns `None` if the subscription was terminated.
+    pub fn recv_blocking(&self) -> Option<Event> {
+        futures_lite::future::block_on(self.events.recv()).ok()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().events.poll_next(cx)
+    }
+}
+
+/// The background thread of a [`Watcher`].
+fn run(
+    refdb: Refdb,
+    subscriptions: Arc<Mutex<Vec<Subscriber>>>,
+    wake: mpsc::Receiver<Wake>,
+    interval: Duration,
+) {
+    loop {
+        let mut changed = Vec::new();
+        let mut all = false;
+        match wake.recv_timeout(interval) {
+            Ok(Wake::Path(path)) => changed.push(path),
+            Ok(Wake::All) | Err(mpsc::RecvTimeoutError::Timeout) => all = true,
+            // The watcher is gone
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        // Coalesce bursts of notifications
+        while let Ok(more) = wake.try_recv() {
+            match more {
+                Wake::Path(path) => changed.push(path),
+                Wake::All => all = true,
+            }
+        }
+
+        let mut subscriptions = subscriptions.lock();
+        subscriptions.retain(|sub| !sub.events.is_closed());
+        for sub in subscriptions.iter_mut() {
+            let affected = all
+                || changed
+                    .iter()
+                    .any(|path| path.starts_with(&sub.prefix) || sub.prefix.starts_with(path));
+            if !affected {
+                continue;
+            }
+            let current = match scan(&refdb, &sub.prefix) {
+                Ok(current) => current,
+                Err(e) => {
+                    warn!("failed to scan refs: {}", e);
+                    continue;
+                },
+            };
+            for event in diff(&sub.seen, &current) {
+                trace!("ref changed: {:?}", event);
+                // Only fails if the subscription was dropped
+                sub.events.try_send(event).ok();
+            }
+            sub.seen = current;
+        }
+    }
+}
+
+/// Create a native watcher which signals `wake` when refs may have changed, if
+/// the platform supports it.
+fn native_watcher(wake: mpsc::Sender<Wake>, git_dir: &Path) -> Option<notify::RecommendedWatcher> {
+    let base = git_dir.to_path_buf();
+    let handler = move |event: notify::Result<notify::Event>| match event {
+        Err(e) => warn!("error watching refs: {}", e),
+        Ok(event) => {
+            for path in &event.paths {
+                if let Some(w) = relevant(&base, path) {
+                    // The receiver going away means the watcher is being
+                    // dropped
+                    wake.send(w).ok();
+                }
+            }
+        },
+    };
+    match notify::recommended_watcher(handler) {
+        Ok(mut watcher) => match watch(&mut watcher, git_dir) {
+            Ok(()) => Some(watcher),
+            Err(e) => {
+                warn!("failed to watch refs, falling back to polling: {}", e);
+                None
+            },
+        },
+        Err(e) => {
+            warn!("native watcher unavailable, falling back to polling: {}", e);
+            None
+        },
+    }
+}
+
+fn watch(watcher: &mut notify::RecommendedWatcher, git_dir: &Path) -> notify::Result<()> {
+    watcher.watch(&git_dir.join("refs"), RecursiveMode::Recursive)?;
+    // `packed-refs` is replaced by renaming the lock file, so watch the
+    // directory containing it. Events for other files in there are filtered
+    // out by `relevant`.
+    watcher.watch(git_dir, RecursiveMode::NonRecursive)
+}
+
+/// Determine what an event for `path` affects, if anything.
+///
+/// Lock files are renamed into place eventually, which is what we want to
+/// observe.
+fn relevant(git_dir: &Path, path: &Path) -> Option<Wake> {
+    if path.extension().map(|ext| ext == "lock").unwrap_or(false) {
+        return None;
+    }
+    let rel = path.strip_prefix(git_dir).ok()?;
+    if rel == Path::new("packed-refs") {
+        Some(Wake::All)
+    } else if rel.starts_with("refs") {
+        Some(Wake::Path(rel.to_path_buf()))
+    } else {
+        None
+    }
+}
+
+fn scan(refdb: &Refdb, prefix: &Path) -> Result<BTreeMap<FullName, Target>, error::Scan> {
+    let snapshot = refdb.snapshot()?;
+    let mut refs = BTreeMap::new();
+    for r in snapshot.iter(Some(prefix))? {
+        let r = match r {
+            Err(e) if super::is_missing_dir(&e) => continue,
+            r => r?,
+        };
+        refs.insert(r.name, r.target);
+    }
+
+    Ok(refs)
+}
+
+fn diff(old: &BTreeMap<FullName, Target>, new: &BTreeMap<FullName, Target>) -> Vec<Event> {
+    let mut events = Vec::new();
+    for (name, target) in new {
+        match old.get(name) {
+            None => events.push(Event::Created {
+                name: name.clone(),
+                new: target.clone(),
+            }),
+            Some(prev) if prev != target => events.push(Event::Updated {
+                name: name.clone(),
+                old: prev.clone(),
+                new: target.clone(),
+            }),
+            Some(_) => {},
+        }
+    }
+    for (name, target) in old {
+        if !new.contains_key(name) {
+            events.push(Event::Deleted {
+                name: name.clone(),
+                old: target.clone(),
+            })
+        }
+    }
+
+    events
+}
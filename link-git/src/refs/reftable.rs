@@ -0,0 +1,782 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! A refdb backed by git's [reftable] format.
+//!
+//! The refs of a repository are stored in a stack of immutable tables under
+//! `$GIT_DIR/reftable`, listed oldest first in `tables.list`. A ref's value is
+//! the one recorded in the newest table mentioning it. Updates append a new
+//! table to the stack, and become visible atomically when `tables.list` is
+//! replaced.
+//!
+//! Like git, updates keep the stack compact by merging tables such that
+//! their sizes form a geometric sequence, see [`Refdb::update`].
+//!
+//! [reftable]: https://git-scm.com/docs/reftable
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+    fs,
+    io::{self, Write as _},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bstr::{BString, ByteSlice as _};
+use git_hash::ObjectId;
+use git_ref::{FullName, Reference, Target};
+use parking_lot::RwLock;
+use tracing::warn;
+
+use super::LOCK_TIMEOUT;
+use crate::refdb::{Edit, Expected, New, Outcome};
+
+mod compact;
+
+mod record;
+use record::{LogRecord, LogValue, RefRecord, RefValue};
+
+mod table;
+use table::Table;
+
+mod writer;
+
+/// How often to retry reading the stack if a table was removed concurrently.
+const MAX_RELOADS: usize = 3;
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Read {
+        #[error("malformed reftable {table}: {reason}")]
+        Malformed { table: String, reason: &'static str },
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Find {
+        #[error("malformed reftable {table}: {reason}")]
+        Malformed { table: String, reason: &'static str },
+
+        #[error("invalid ref name {0:?}")]
+        InvalidName(BString),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Follow {
+        #[error("cyclic symref: {0:?}")]
+        Cycle(FullName),
+
+        #[error("reference {0:?} not found")]
+        NotFound(FullName),
+
+        #[error("max symref depth {0} exceeded")]
+        DepthLimitExceeded(usize),
+
+        #[error(transparent)]
+        Find(#[from] Find),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Update {
+        #[error("failed to lock the reftable stack")]
+        Lock(#[from] git_lock::acquire::Error),
+
+        /// A ref did not have the [`Expected`] value. No ref was modified.
+        #[error("expected {name:?} to be {expected:?}, but found {actual:?}")]
+        Rejected {
+            name: FullName,
+            expected: Expected,
+            actual: Option<Target>,
+        },
+
+        #[error("{0:?} is edited more than once")]
+        Duplicate(FullName),
+
+        #[error(transparent)]
+        Read(#[from] Read),
+
+        #[error(transparent)]
+        Find(#[from] Find),
+
+        #[error(transparent)]
+        Io(#[from] io::Error),
+    }
+}
+
+/// Threadsafe refdb reading and writing the reftable stack of a repository.
+///
+/// Like [`crate::refdb::Refdb`], this caches the parsed stack, which is shared
+/// between [`Snapshot`]s. [`Refdb::snapshot`] reloads it only if `tables.list`
+/// has changed since it was last read, reusing tables which are still part of
+/// the stack.
+#[derive(Clone)]
+pub struct Refdb {
+    dir: PathBuf,
+    stack: Arc<RwLock<Stack>>,
+}
+
+#[derive(Clone, Default)]
+struct Stack {
+    /// Contents of `tables.list` this stack was loaded from.
+    list: Vec<u8>,
+    /// Oldest first.
+    tables: Arc<Vec<Arc<Table>>>,
+}
+
+impl Stack {
+    fn load(dir: &Path, prev: &Stack) -> Result<Self, error::Read> {
+        let mut reloads = 0;
+        loop {
+            let list = fs::read(dir.join("tables.list"))?;
+            if list == prev.list {
+                return Ok(prev.clone());
+            }
+
+            let mut open = prev
+                .tables
+                .iter()
+                .map(|t| (t.name.as_str(), t))
+                .collect::<HashMap<_, _>>();
+            let tables = list
+                .lines()
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    let name = name.to_str().map_err(|_| error::Read::Malformed {
+                        table: name.to_str_lossy().into_owned(),
+                        reason: "invalid table name",
+                    })?;
+                    match open.remove(name) {
+                        Some(table) => Ok(Arc::clone(table)),
+                        None => Table::open(dir, name).map(Arc::new),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>();
+            match tables {
+                // A concurrent compaction removed a table after we read the
+                // list, try again
+                Err(error::Read::Io(e))
+                    if e.kind() == io::ErrorKind::NotFound && reloads < MAX_RELOADS =>
+                {
+                    reloads += 1;
+                },
+                Err(e) => return Err(e),
+                Ok(tables) => {
+                    return Ok(Self {
+                        list,
+                        tables: Arc::new(tables),
+                    })
+                },
+            }
+        }
+    }
+
+    fn next_update_index(&self) -> u64 {
+        self.tables
+            .last()
+            .map(|t| t.max_update_index + 1)
+            .unwrap_or(1)
+    }
+}
+
+impl Refdb {
+    /// Open the reftable stack of the repository at `git_dir`.
+    ///
+    /// Fails if `$GIT_DIR/reftable/tables.list` does not exist, ie. the
+    /// repository does not use the reftable format.
+    pub fn open(git_dir: impl AsRef<Path>) -> Result<Self, error::Read> {
+        let dir = git_dir.as_ref().join("reftable");
+        let stack = Stack::load(&dir, &Stack::default())?;
+        Ok(Self {
+            dir,
+            stack: Arc::new(RwLock::new(stack)),
+        })
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot, error::Read> {
+        let prev = self.stack.read().clone();
+        let stack = Stack::load(&self.dir, &prev)?;
+        if stack.list != prev.list {
+            *self.stack.write() = stack.clone();
+        }
+        Ok(Snapshot {
+            tables: stack.tables,
+        })
+    }
+
+    /// Atomically apply a batch of `edits`.
+    ///
+    /// The stack is locked (retrying for a short while if it is locked by
+    /// another process), and the [`Expected`] values are checked against the
+    /// stack as of acquiring the lock. Only if all checks pass is a new table
+    /// appended to the stack, otherwise no ref is modified.
+    ///
+    /// Reflog entries are recorded for direct refs. Deleting a ref also
+    /// deletes its reflog.
+    ///
+    /// After appending the new table, the stack is compacted if necessary
+    /// (see [`compact`]). Failing to compact is not an error, the next update
+    /// will try again.
+    ///
+    /// The outcomes are returned in the same order as `edits`.
+    pub fn update<I>(
+        &self,
+        edits: I,
+        committer: &git_actor::Signature,
+    ) -> Result<Vec<Outcome>, error::Update>
+    where
+        I: IntoIterator<Item = Edit>,
+    {
+        use git_lock::acquire::Fail;
+
+        let lock = git_lock::File::acquire_to_update_resource(
+            self.dir.join("tables.list"),
+            Fail::AfterDurationWithBackoff(LOCK_TIMEOUT),
+            None,
+        )?;
+        let stack = Stack::load(&self.dir, &self.stack.read().clone())?;
+        let snapshot = Snapshot {
+            tables: Arc::clone(&stack.tables),
+        };
+        let update_index = stack.next_update_index();
+
+        let mut seen = BTreeSet::new();
+        let mut refs = Vec::new();
+        let mut logs = Vec::new();
+        let mut outcomes = Vec::new();
+        for edit in edits {
+            if !seen.insert(edit.name.clone()) {
+                return Err(error::Update::Duplicate(edit.name));
+            }
+            let name = edit.name.as_bstr().to_vec();
+            let actual = snapshot.find(&name)?.map(|r| r.target);
+            check(&edit, actual.as_ref())?;
+
+            let value = match &edit.new {
+                New::Delete => {
+                    let reflog = snapshot.reflog(&edit.name)?;
+                    for entry in reflog.iter().flat_map(Reflog::iter) {
+                        logs.push(LogRecord {
+                            name: name.clone(),
+                            update_index: entry.update_index,
+                            value: LogValue::Deletion,
+                        });
+                    }
+                    RefValue::Deletion
+                },
+                New::Direct(new) => {
+                    let old = match &actual {
+                        Some(Target::Peeled(old)) => *old,
+                        _ => null_oid(),
+                    };
+                    logs.push(LogRecord {
+                        name: name.clone(),
+                        update_index,
+                        value: log_update(old, *new, committer, &edit.reflog_message),
+                    });
+                    RefValue::Direct(*new)
+                },
+                New::Symbolic(target) => RefValue::Symbolic(target.as_bstr().to_vec()),
+            };
+            refs.push(RefRecord {
+                name,
+                update_index,
+                value,
+            });
+            outcomes.push(outcome(edit, actual));
+        }
+        if refs.is_empty() {
+            return Ok(outcomes);
+        }
+
+        refs.sort_by(|a, b| a.name.cmp(&b.name));
+        logs.sort_by_key(LogRecord::key);
+        let mut tables = stack.tables.to_vec();
+        tables.push(self.write_table(update_index, update_index, &refs, &logs)?);
+        let mut written = vec![tables[tables.len() - 1].name.clone()];
+        let mut obsolete = Vec::new();
+        match self.compact(&tables) {
+            Err(e) => warn!("failed to compact reftable stack: {}", e),
+            Ok(None) => {},
+            Ok(Some(Compaction { segment, table })) => {
+                written.push(table.name.clone());
+                obsolete = tables
+                    .splice(segment, Some(table))
+                    .map(|t| t.name.clone())
+                    .collect();
+            },
+        }
+
+        if let Err(e) = commit(lock, &tables) {
+            for name in written {
+                fs::remove_file(self.dir.join(name)).ok();
+            }
+            return Err(e);
+        }
+        // Readers which loaded the previous list retry if a table is gone
+        for name in obsolete {
+            fs::remove_file(self.dir.join(name)).ok();
+        }
+
+        Ok(outcomes)
+    }
+
+    /// If the sizes of `tables` do not form a geometric sequence, merge the
+    /// offending range into a new table.
+    ///
+    /// Must be called while holding the `tables.list` lock.
+    fn compact(&self, tables: &[Arc<Table>]) -> Result<Option<Compaction>, error::Update> {
+        let sizes = tables.iter().map(|t| t.size()).collect::<Vec<_>>();
+        let segment = match compact::segment(&sizes) {
+            None => return Ok(None),
+            Some(segment) => segment,
+        };
+        let merge = &tables[segment.clone()];
+        let (refs, logs) = compact::merge(merge, segment.start == 0)?;
+        let table = self.write_table(
+            merge[0].min_update_index,
+            merge[merge.len() - 1].max_update_index,
+            &refs,
+            &logs,
+        )?;
+
+        Ok(Some(Compaction { segment, table }))
+    }
+
+    /// Write a new table file, which is not yet part of the stack.
+    fn write_table(
+        &self,
+        min_update_index: u64,
+        max_update_index: u64,
+        refs: &[RefRecord],
+        logs: &[LogRecord],
+    ) -> Result<Arc<Table>, error::Update> {
+        let name = table_name(min_update_index, max_update_index);
+        let path = self.dir.join(&name);
+        let data = writer::write(min_update_index, max_update_index, refs, logs);
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        file.write_all(&data)?;
+        // The table must be durable before `tables.list` refers to it
+        file.sync_all()?;
+
+        match Table::from_bytes(name.clone(), data) {
+            Ok(table) => Ok(Arc::new(table)),
+            Err(reason) => {
+                fs::remove_file(path).ok();
+                Err(error::Read::Malformed {
+                    table: name,
+                    reason,
+                }
+                .into())
+            },
+        }
+    }
+}
+
+/// The result of [`Refdb::compact`].
+struct Compaction {
+    /// The range of tables to be replaced by `table`.
+    segment: Range<usize>,
+    /// The merged table, which is not yet part of the stack.
+    table: Arc<Table>,
+}
+
+/// Replace `tables.list` with the names of `tables`.
+fn commit(mut lock: git_lock::File, tables: &[Arc<Table>]) -> Result<(), error::Update> {
+    for table in tables {
+        lock.write_all(table.name.as_bytes())?;
+        lock.write_all(b"\n")?;
+    }
+    lock.with_mut(|file| file.sync_all())?;
+    lock.commit().map_err(|e| e.error)?;
+
+    Ok(())
+}
+
+fn check(edit: &Edit, actual: Option<&Target>) -> Result<(), error::Update> {
+    let ok = match (&edit.expected, actual) {
+        (Expected::Any, _) => true,
+        (Expected::Absent, actual) => actual.is_none(),
+        (Expected::Present, actual) => actual.is_some(),
+        (Expected::Is(expected), Some(actual)) => expected == actual,
+        (Expected::Is(_), None) => false,
+        (Expected::AbsentOr(expected), Some(actual)) => expected == actual,
+        (Expected::AbsentOr(_), None) => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(error::Update::Rejected {
+            name: edit.name.clone(),
+            expected: edit.expected.clone(),
+            actual: actual.cloned(),
+        })
+    }
+}
+
+fn outcome(edit: Edit, previous: Option<Target>) -> Outcome {
+    let new = match edit.new {
+        New::Delete => {
+            return Outcome::Deleted {
+                name: edit.name,
+                previous,
+            }
+        },
+        New::Direct(id) => Target::Peeled(id),
+        New::Symbolic(name) => Target::Symbolic(name),
+    };
+    match previous {
+        None => Outcome::Created {
+            name: edit.name,
+            new,
+        },
+        Some(previous) => Outcome::Updated {
+            name: edit.name,
+            previous,
+            new,
+        },
+    }
+}
+
+fn log_update(
+    old: ObjectId,
+    new: ObjectId,
+    committer: &git_actor::Signature,
+    message: &BString,
+) -> LogValue {
+    let minutes = (committer.time.offset / 60) as i16;
+    LogValue::Update {
+        old,
+        new,
+        name: committer.name.to_vec(),
+        email: committer.email.to_vec(),
+        time: committer.time.time as u64,
+        tz_offset: match committer.time.sign {
+            git_actor::Sign::Plus => minutes.abs(),
+            git_actor::Sign::Minus => -minutes.abs(),
+        },
+        message: message.to_vec(),
+    }
+}
+
+/// Table names follow git's convention of the update index range, followed by
+/// a random suffix.
+fn table_name(min_update_index: u64, max_update_index: u64) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let suffix = nanos ^ std::process::id().rotate_left(16);
+    format!(
+        "0x{:012x}-0x{:012x}-{:08x}.ref",
+        min_update_index, max_update_index, suffix
+    )
+}
+
+fn null_oid() -> ObjectId {
+    ObjectId::from([0u8; record::HASH_LEN])
+}
+
+/// A consistent view of the reftable stack.
+#[derive(Clone)]
+pub struct Snapshot {
+    tables: Arc<Vec<Arc<Table>>>,
+}
+
+impl Snapshot {
+    pub fn find(&self, name: impl AsRef<[u8]>) -> Result<Option<Reference>, error::Find> {
+        let name = name.as_ref();
+        for table in self.tables.iter().rev() {
+            let rec = table
+                .find(name)
+                .map_err(|reason| malformed(table, reason))?;
+            if let Some(rec) = rec {
+                return to_reference(rec);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterate over the refs whose name starts with `prefix`, in name order.
+    pub fn iter(&self, prefix: Option<impl AsRef<Path>>) -> Result<Iter<'_>, error::Find> {
+        let prefix = match &prefix {
+            None => Vec::new(),
+            Some(p) => {
+                let p = p.as_ref();
+                <[u8]>::from_path(p)
+                    .ok_or_else(|| error::Find::InvalidName(p.to_string_lossy().as_ref().into()))?
+                    .to_vec()
+            },
+        };
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| {
+                table
+                    .refs_from(&prefix)
+                    .map(|refs| (table.as_ref(), Some(refs)))
+                    .map_err(|reason| malformed(table, reason))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Iter {
+            heads: vec![None; tables.len()],
+            tables,
+            prefix,
+        })
+    }
+
+    /// Follow a symbolic reference until a direct reference is found.
+    ///
+    /// See [`crate::refdb::Snapshot::follow`].
+    pub fn follow(&self, symref: &Reference) -> Result<Reference, error::Follow> {
+        const MAX_DEPTH: usize = 5;
+
+        let mut seen = BTreeSet::new();
+        seen.insert(symref.name.clone());
+        let mut next = symref.clone();
+        loop {
+            match next.target {
+                Target::Peeled(_) => return Ok(next),
+                Target::Symbolic(sym) => {
+                    if seen.len() > MAX_DEPTH {
+                        return Err(error::Follow::DepthLimitExceeded(MAX_DEPTH));
+                    }
+                    if !seen.insert(sym.clone()) {
+                        return Err(error::Follow::Cycle(sym));
+                    }
+                    next = self
+                        .find(sym.as_bstr())?
+                        .ok_or(error::Follow::NotFound(sym))?;
+                },
+            }
+        }
+    }
+
+    /// Read the reflog of the ref `name`.
+    ///
+    /// Returns `None` if the ref has no reflog entries.
+    pub fn reflog(&self, name: &FullName) -> Result<Option<Reflog>, error::Find> {
+        let key = name.as_bstr().as_bytes();
+        // Newer tables override entries of older ones with the same update index
+        let mut entries = BTreeMap::new();
+        for table in self.tables.iter() {
+            let logs = table
+                .logs_from(key)
+                .map_err(|reason| malformed(table, reason))?;
+            for rec in logs {
+                let rec = rec.map_err(|reason| malformed(table, reason))?;
+                if rec.name != key {
+                    break;
+                }
+                entries.insert(rec.update_index, rec.value);
+            }
+        }
+
+        let entries = entries
+            .into_iter()
+            .filter_map(|(update_index, value)| LogEntry::from_value(update_index, value))
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Reflog {
+            name: name.clone(),
+            entries,
+        }))
+    }
+}
+
+/// The reflog of a ref, as read by [`Snapshot::reflog`].
+#[derive(Clone, Debug)]
+pub struct Reflog {
+    name: FullName,
+    /// Oldest first.
+    entries: Vec<LogEntry>,
+}
+
+impl Reflog {
+    pub fn name(&self) -> &FullName {
+        &self.name
+    }
+
+    /// Iterate over the entries, oldest first.
+    ///
+    /// Use [`Iterator::rev`] to iterate most recent first.
+    pub fn iter(&self) -> Entries<'_> {
+        Entries {
+            entries: self.entries.iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Reflog {
+    type Item = &'a LogEntry;
+    type IntoIter = Entries<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the entries of a [`Reflog`].
+pub struct Entries<'a> {
+    entries: std::slice::Iter<'a, LogEntry>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = &'a LogEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+}
+
+impl DoubleEndedIterator for Entries<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back()
+    }
+}
+
+/// An entry of a ref's reflog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    pub update_index: u64,
+    pub previous: ObjectId,
+    pub new: ObjectId,
+    pub committer: git_actor::Signature,
+    pub message: BString,
+}
+
+impl LogEntry {
+    fn from_value(update_index: u64, value: LogValue) -> Option<Self> {
+        match value {
+            LogValue::Deletion => None,
+            LogValue::Update {
+                old,
+                new,
+                name,
+                email,
+                time,
+                tz_offset,
+                message,
+            } => Some(Self {
+                update_index,
+                previous: old,
+                new,
+                committer: git_actor::Signature {
+                    name: name.into(),
+                    email: email.into(),
+                    time: git_actor::Time {
+                        time: time as u32,
+                        offset: tz_offset as i32 * 60,
+                        sign: if tz_offset < 0 {
+                            git_actor::Sign::Minus
+                        } else {
+                            git_actor::Sign::Plus
+                        },
+                    },
+                },
+                message: message.into(),
+            }),
+        }
+    }
+}
+
+/// Iterator over the refs of a [`Snapshot`], see [`Snapshot::iter`].
+pub struct Iter<'a> {
+    /// Oldest first, like the stack. `None` once a table is exhausted.
+    tables: Vec<(&'a Table, Option<table::Refs<'a>>)>,
+    /// The next record of each table.
+    heads: Vec<Option<RefRecord>>,
+    prefix: Vec<u8>,
+}
+
+impl Iter<'_> {
+    fn next_ref(&mut self) -> Result<Option<Reference>, error::Find> {
+        let prefix = &self.prefix;
+        loop {
+            for ((table, refs), head) in self.tables.iter_mut().zip(&mut self.heads) {
+                if let (None, Some(iter)) = (&head, refs.as_mut()) {
+                    *head = iter
+                        .next()
+                        .transpose()
+                        .map_err(|reason| malformed(table, reason))?
+                        .filter(|rec| rec.name.starts_with(prefix));
+                    if head.is_none() {
+                        *refs = None;
+                    }
+                }
+            }
+
+            let min = match self.heads.iter().flatten().map(|rec| &rec.name).min() {
+                None => return Ok(None),
+                Some(min) => min.clone(),
+            };
+            // The newest table wins, all others are shadowed
+            let mut winner = None;
+            for head in self.heads.iter_mut() {
+                if head.as_ref().map(|rec| rec.name == min).unwrap_or(false) {
+                    winner = head.take();
+                }
+            }
+
+            match winner {
+                Some(RefRecord {
+                    value: RefValue::Deletion,
+                    ..
+                })
+                | None => continue,
+                Some(rec) => return to_reference(rec),
+            }
+        }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<Reference, error::Find>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_ref().transpose()
+    }
+}
+
+fn malformed(table: &Table, reason: &'static str) -> error::Find {
+    error::Find::Malformed {
+        table: table.name.clone(),
+        reason,
+    }
+}
+
+fn to_reference(rec: RefRecord) -> Result<Option<Reference>, error::Find> {
+    let (target, peeled) = match rec.value {
+        RefValue::Deletion => return Ok(None),
+        RefValue::Direct(id) => (Target::Peeled(id), None),
+        RefValue::Peeled { target, peeled } => (Target::Peeled(target), Some(peeled)),
+        RefValue::Symbolic(sym) => (Target::Symbolic(full_name(sym)?), None),
+    };
+    Ok(Some(Reference {
+        name: full_name(rec.name)?,
+        target,
+        peeled,
+    }))
+}
+
+fn full_name(name: Vec<u8>) -> Result<FullName, error::Find> {
+    let name = BString::from(name);
+    FullName::try_from(name.clone()).map_err(|_| error::Find::InvalidName(name))
+}
@@ -0,0 +1,95 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Geometric compaction of the reftable stack, as done by git.
+//!
+//! After each update, the stack is compacted such that the size of each table
+//! is at least [`FACTOR`] times the size of the next newer table. This keeps
+//! the number of tables logarithmic in the number of updates, while newly
+//! written tables are usually merged only with a few small tables.
+
+use std::{collections::BTreeMap, ops::Range, sync::Arc};
+
+use super::{
+    error,
+    malformed,
+    record::{LogRecord, LogValue, RefRecord, RefValue},
+    table::Table,
+};
+
+/// The geometric factor between the sizes of adjacent tables.
+const FACTOR: u64 = 2;
+
+/// Determine the range of tables to compact, given their sizes (oldest first).
+///
+/// Returns `None` if the sizes already form a geometric sequence. Otherwise,
+/// the range contains at least two tables.
+pub(super) fn segment(sizes: &[u64]) -> Option<Range<usize>> {
+    // Find the newest table which is not at most half the size of its
+    // predecessor. Newer tables are valid members of the sequence, and due to
+    // its properties, their sum can't exceed the size of that table.
+    let mut i = sizes.len().checked_sub(1)?;
+    let (end, mut bytes) = loop {
+        if i == 0 {
+            return None;
+        }
+        if sizes[i - 1] < sizes[i].saturating_mul(FACTOR) {
+            break (i + 1, sizes[i]);
+        }
+        i -= 1;
+    };
+
+    // Tables are merged backwards, so compare each predecessor against the
+    // accumulated size. Keep going even after the first match, as older tables
+    // may violate the sequence as well.
+    let mut start = end - 1;
+    while i > 0 {
+        let curr = bytes;
+        bytes += sizes[i - 1];
+        if sizes[i - 1] < curr.saturating_mul(FACTOR) {
+            start = i - 1;
+        }
+        i -= 1;
+    }
+
+    Some(start..end)
+}
+
+/// Merge the records of `tables` (oldest first), such that the newest record
+/// for each key wins.
+///
+/// If `base` is true, the oldest table is the bottom of the stack, so deletion
+/// records no longer shadow anything and are dropped.
+pub(super) fn merge(
+    tables: &[Arc<Table>],
+    base: bool,
+) -> Result<(Vec<RefRecord>, Vec<LogRecord>), error::Find> {
+    let mut refs = BTreeMap::new();
+    let mut logs = BTreeMap::new();
+    for table in tables.iter().rev() {
+        let table_refs = table
+            .refs_from(&[])
+            .map_err(|reason| malformed(table, reason))?;
+        for rec in table_refs {
+            let rec = rec.map_err(|reason| malformed(table, reason))?;
+            refs.entry(rec.name.clone()).or_insert(rec);
+        }
+        for rec in table.logs() {
+            let rec = rec.map_err(|reason| malformed(table, reason))?;
+            logs.entry(rec.key()).or_insert(rec);
+        }
+    }
+
+    let refs = refs
+        .into_values()
+        .filter(|rec| !(base && rec.value == RefValue::Deletion))
+        .collect();
+    let logs = logs
+        .into_values()
+        .filter(|rec| !(base && rec.value == LogValue::Deletion))
+        .collect();
+
+    Ok((refs, logs))
+}
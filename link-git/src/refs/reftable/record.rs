@@ -0,0 +1,275 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Encoding and decoding of reftable records.
+
+use git_hash::{oid, ObjectId};
+
+pub(super) const HASH_LEN: usize = 20;
+
+pub(super) const REF_DELETION: u8 = 0x0;
+pub(super) const REF_VAL1: u8 = 0x1;
+pub(super) const REF_VAL2: u8 = 0x2;
+pub(super) const REF_SYMREF: u8 = 0x3;
+
+pub(super) const LOG_DELETION: u8 = 0x0;
+pub(super) const LOG_UPDATE: u8 = 0x1;
+
+/// Describes why a table is malformed.
+pub(super) type Error = &'static str;
+pub(super) type Result<T> = std::result::Result<T, Error>;
+
+/// The value of a ref record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum RefValue {
+    Deletion,
+    Direct(ObjectId),
+    Peeled { target: ObjectId, peeled: ObjectId },
+    Symbolic(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct RefRecord {
+    pub name: Vec<u8>,
+    pub update_index: u64,
+    pub value: RefValue,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum LogValue {
+    Deletion,
+    Update {
+        old: ObjectId,
+        new: ObjectId,
+        name: Vec<u8>,
+        email: Vec<u8>,
+        time: u64,
+        tz_offset: i16,
+        message: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct LogRecord {
+    pub name: Vec<u8>,
+    pub update_index: u64,
+    pub value: LogValue,
+}
+
+impl LogRecord {
+    /// Log keys are the ref name, a NUL byte, and the reversed update index,
+    /// so that the most recent entry sorts first.
+    pub fn key(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(self.name.len() + 9);
+        key.extend_from_slice(&self.name);
+        key.push(0);
+        key.extend_from_slice(&(u64::MAX - self.update_index).to_be_bytes());
+        key
+    }
+
+    pub fn from_key(key: &[u8], value: LogValue) -> Result<Self> {
+        if key.len() < 9 || key[key.len() - 9] != 0 {
+            return Err("invalid log key");
+        }
+        let (name, rest) = key.split_at(key.len() - 9);
+        let mut reversed = [0u8; 8];
+        reversed.copy_from_slice(&rest[1..]);
+
+        Ok(Self {
+            name: name.to_vec(),
+            update_index: u64::MAX - u64::from_be_bytes(reversed),
+            value,
+        })
+    }
+}
+
+/// Cursor over the bytes of a block.
+pub(super) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or("length overflow")?;
+        let bytes = self.buf.get(self.pos..end).ok_or("truncated record")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn varint(&mut self) -> Result<u64> {
+        let mut byte = self.u8()?;
+        let mut val = (byte & 0x7f) as u64;
+        while byte & 0x80 != 0 {
+            byte = self.u8()?;
+            val = val
+                .checked_add(1)
+                .and_then(|val| val.checked_mul(128))
+                .ok_or("varint overflow")?
+                | (byte & 0x7f) as u64;
+        }
+        Ok(val)
+    }
+
+    pub fn len_prefixed(&mut self) -> Result<&'a [u8]> {
+        let len = self.varint()? as usize;
+        self.bytes(len)
+    }
+
+    pub fn oid(&mut self) -> Result<ObjectId> {
+        oid::try_from(self.bytes(HASH_LEN)?)
+            .map(ToOwned::to_owned)
+            .map_err(|_| "invalid object id")
+    }
+
+    /// Decode a prefix-compressed key, relative to `last_key`, and return the
+    /// value type.
+    pub fn key(&mut self, last_key: &mut Vec<u8>) -> Result<u8> {
+        let prefix_len = self.varint()? as usize;
+        let suffix_and_type = self.varint()?;
+        let suffix_len = (suffix_and_type >> 3) as usize;
+        if prefix_len > last_key.len() {
+            return Err("invalid key prefix length");
+        }
+        last_key.truncate(prefix_len);
+        last_key.extend_from_slice(self.bytes(suffix_len)?);
+
+        Ok((suffix_and_type & 0x7) as u8)
+    }
+
+    pub fn ref_value(&mut self, value_type: u8) -> Result<(u64, RefValue)> {
+        let update_index_delta = self.varint()?;
+        let value = match value_type {
+            REF_DELETION => RefValue::Deletion,
+            REF_VAL1 => RefValue::Direct(self.oid()?),
+            REF_VAL2 => RefValue::Peeled {
+                target: self.oid()?,
+                peeled: self.oid()?,
+            },
+            REF_SYMREF => RefValue::Symbolic(self.len_prefixed()?.to_vec()),
+            _ => return Err("invalid ref value type"),
+        };
+        Ok((update_index_delta, value))
+    }
+
+    pub fn log_value(&mut self, value_type: u8) -> Result<LogValue> {
+        match value_type {
+            LOG_DELETION => Ok(LogValue::Deletion),
+            LOG_UPDATE => {
+                let old = self.oid()?;
+                let new = self.oid()?;
+                let name = self.len_prefixed()?.to_vec();
+                let email = self.len_prefixed()?.to_vec();
+                let time = self.varint()?;
+                let tz = self.bytes(2)?;
+                let tz_offset = i16::from_be_bytes([tz[0], tz[1]]);
+                let message = self.len_prefixed()?.to_vec();
+                Ok(LogValue::Update {
+                    old,
+                    new,
+                    name,
+                    email,
+                    time,
+                    tz_offset,
+                    message,
+                })
+            },
+            _ => Err("invalid log value type"),
+        }
+    }
+}
+
+pub(super) fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    let mut buf = [0u8; 10];
+    let mut i = buf.len() - 1;
+    buf[i] = (value & 0x7f) as u8;
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        i -= 1;
+        buf[i] = 0x80 | (value & 0x7f) as u8;
+        value >>= 7;
+    }
+    out.extend_from_slice(&buf[i..]);
+}
+
+fn put_len_prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Encode `key` relative to `last_key` (pass an empty `last_key` for restart
+/// points).
+pub(super) fn put_key(out: &mut Vec<u8>, last_key: &[u8], key: &[u8], value_type: u8) {
+    let prefix_len = last_key.iter().zip(key).take_while(|(a, b)| a == b).count();
+    let suffix = &key[prefix_len..];
+    put_varint(out, prefix_len as u64);
+    put_varint(out, ((suffix.len() as u64) << 3) | value_type as u64);
+    out.extend_from_slice(suffix);
+}
+
+pub(super) fn ref_value_type(value: &RefValue) -> u8 {
+    match value {
+        RefValue::Deletion => REF_DELETION,
+        RefValue::Direct(_) => REF_VAL1,
+        RefValue::Peeled { .. } => REF_VAL2,
+        RefValue::Symbolic(_) => REF_SYMREF,
+    }
+}
+
+pub(super) fn put_ref_value(out: &mut Vec<u8>, update_index_delta: u64, value: &RefValue) {
+    put_varint(out, update_index_delta);
+    match value {
+        RefValue::Deletion => {},
+        RefValue::Direct(id) => out.extend_from_slice(id.as_bytes()),
+        RefValue::Peeled { target, peeled } => {
+            out.extend_from_slice(target.as_bytes());
+            out.extend_from_slice(peeled.as_bytes());
+        },
+        RefValue::Symbolic(target) => put_len_prefixed(out, target),
+    }
+}
+
+pub(super) fn log_value_type(value: &LogValue) -> u8 {
+    match value {
+        LogValue::Deletion => LOG_DELETION,
+        LogValue::Update { .. } => LOG_UPDATE,
+    }
+}
+
+pub(super) fn put_log_value(out: &mut Vec<u8>, value: &LogValue) {
+    match value {
+        LogValue::Deletion => {},
+        LogValue::Update {
+            old,
+            new,
+            name,
+            email,
+            time,
+            tz_offset,
+            message,
+        } => {
+            out.extend_from_slice(old.as_bytes());
+            out.extend_from_slice(new.as_bytes());
+            put_len_prefixed(out, name);
+            put_len_prefixed(out, email);
+            put_varint(out, *time);
+            out.extend_from_slice(&tz_offset.to_be_bytes());
+            put_len_prefixed(out, message);
+        },
+    }
+}
@@ -0,0 +1,547 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Reading a single reftable file.
+
+use std::{borrow::Cow, convert::TryInto, fs, ops::Range, path::Path};
+
+use git_features::zlib;
+
+use super::{
+    error,
+    record::{self, LogRecord, Reader, RefRecord},
+};
+
+pub(super) const MAGIC: &[u8] = b"REFT";
+pub(super) const HEADER_LEN_V1: usize = 24;
+const HEADER_LEN_V2: usize = 28;
+/// The footer repeats the header, followed by five section offsets and a
+/// CRC-32.
+const FOOTER_EXTRA_LEN: usize = 5 * 8 + 4;
+
+pub(super) const BLOCK_REF: u8 = b'r';
+pub(super) const BLOCK_LOG: u8 = b'g';
+pub(super) const BLOCK_INDEX: u8 = b'i';
+
+/// The maximum number of index levels followed when seeking.
+///
+/// Every level of a well-formed index divides the number of blocks by the
+/// number of records per index block, so this is never reached in practice. A
+/// malformed index, however, may be cyclic.
+const MAX_INDEX_DEPTH: usize = 32;
+
+pub(super) struct Table {
+    pub name: String,
+    data: Vec<u8>,
+    header_len: usize,
+    block_size: usize,
+    pub min_update_index: u64,
+    pub max_update_index: u64,
+    /// End of the ref blocks section.
+    ref_end: usize,
+    /// Position of the root ref index block, if any.
+    ref_index: Option<usize>,
+    /// Range of the log blocks section, if any.
+    logs: Option<Range<usize>>,
+    /// Position of the root log index block, if any.
+    log_index: Option<usize>,
+    footer_start: usize,
+}
+
+impl Table {
+    pub fn open(dir: &Path, name: &str) -> Result<Self, error::Read> {
+        let data = fs::read(dir.join(name))?;
+        Self::from_bytes(name.to_owned(), data).map_err(|reason| error::Read::Malformed {
+            table: name.to_owned(),
+            reason,
+        })
+    }
+
+    pub fn from_bytes(name: String, data: Vec<u8>) -> record::Result<Self> {
+        if data.len() < HEADER_LEN_V1 || &data[..4] != MAGIC {
+            return Err("not a reftable");
+        }
+        let header_len = match data[4] {
+            1 => HEADER_LEN_V1,
+            2 => {
+                if data.len() < HEADER_LEN_V2 || &data[24..28] != b"sha1" {
+                    return Err("unsupported hash function");
+                }
+                HEADER_LEN_V2
+            },
+            _ => return Err("unsupported version"),
+        };
+        let footer_len = header_len + FOOTER_EXTRA_LEN;
+        if data.len() < header_len + footer_len {
+            return Err("truncated table");
+        }
+        let footer_start = data.len() - footer_len;
+        let footer = &data[footer_start..];
+        if footer[..header_len] != data[..header_len] {
+            return Err("footer does not match header");
+        }
+        let crc = be_u32(&footer[footer_len - 4..]);
+        if crc != crc32(&footer[..footer_len - 4]) {
+            return Err("footer checksum mismatch");
+        }
+
+        let block_size = be_u24(&data[5..8]);
+        let min_update_index = be_u64(&data[8..16]);
+        let max_update_index = be_u64(&data[16..24]);
+
+        let offsets = &footer[header_len..footer_len - 4];
+        let ref_index = be_u64(&offsets[0..8]) as usize;
+        let obj = (be_u64(&offsets[8..16]) >> 5) as usize;
+        let obj_index = be_u64(&offsets[16..24]) as usize;
+        let log = be_u64(&offsets[24..32]) as usize;
+        let log_index = be_u64(&offsets[32..40]) as usize;
+        if [ref_index, obj, obj_index, log, log_index]
+            .iter()
+            .any(|ofs| *ofs > footer_start)
+        {
+            return Err("section offset out of bounds");
+        }
+
+        let ref_end = [ref_index, obj, obj_index, log, log_index]
+            .iter()
+            .copied()
+            .filter(|ofs| *ofs != 0)
+            .min()
+            .unwrap_or(footer_start);
+        let has_logs =
+            log != 0 || (ref_end == header_len && data.get(header_len) == Some(&BLOCK_LOG));
+        let logs = if has_logs {
+            let end = if log_index != 0 {
+                log_index
+            } else {
+                footer_start
+            };
+            if log > end {
+                return Err("section offset out of bounds");
+            }
+            Some(log..end)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name,
+            data,
+            header_len,
+            block_size,
+            min_update_index,
+            max_update_index,
+            ref_end,
+            ref_index: Some(ref_index).filter(|ofs| *ofs != 0),
+            logs,
+            log_index: Some(log_index).filter(|ofs| *ofs != 0),
+            footer_start,
+        })
+    }
+
+    /// Size in bytes of the table, excluding header and footer.
+    ///
+    /// This is the size git uses to decide which tables to compact.
+    pub fn size(&self) -> u64 {
+        let overhead = 2 * self.header_len + FOOTER_EXTRA_LEN;
+        self.data.len().saturating_sub(overhead) as u64
+    }
+
+    /// Find the ref record for `name`.
+    pub fn find(&self, name: &[u8]) -> record::Result<Option<RefRecord>> {
+        for rec in self.refs_from(name)? {
+            let rec = rec?;
+            if rec.name == name {
+                return Ok(Some(rec));
+            }
+            if rec.name.as_slice() > name {
+                break;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterate over the ref records whose name is greater than or equal to
+    /// `start`, in order.
+    pub fn refs_from(&self, start: &[u8]) -> record::Result<Refs<'_>> {
+        let block = if self.has_refs() {
+            self.seek_block(self.ref_index, 0, start)?
+        } else {
+            None
+        };
+        Ok(Refs {
+            table: self,
+            next_block: block,
+            current: None,
+            start: start.to_vec(),
+        })
+    }
+
+    /// All log records of the table, in key order (ie. by ref name, most
+    /// recent first).
+    pub fn logs(&self) -> Logs<'_> {
+        Logs {
+            table: self,
+            next_block: self.logs.as_ref().map(|logs| logs.start),
+            current: None,
+            start: Vec::new(),
+        }
+    }
+
+    /// Iterate over the log records whose key is greater than or equal to
+    /// `start`, in key order.
+    ///
+    /// Passing a ref name as `start` yields its log records first.
+    pub fn logs_from(&self, start: &[u8]) -> record::Result<Logs<'_>> {
+        let block = match &self.logs {
+            None => None,
+            Some(logs) => self.seek_block(self.log_index, logs.start, start)?,
+        };
+        Ok(Logs {
+            table: self,
+            next_block: block,
+            current: None,
+            start: start.to_vec(),
+        })
+    }
+
+    /// Offset of the first block which may contain `key`, found by descending
+    /// the index rooted at `index`. Without an index, this is `first`.
+    fn seek_block(
+        &self,
+        index: Option<usize>,
+        first: usize,
+        key: &[u8],
+    ) -> record::Result<Option<usize>> {
+        let mut ofs = match index {
+            None => return Ok(Some(first)),
+            Some(ofs) => ofs,
+        };
+        for _ in 0..MAX_INDEX_DEPTH {
+            let block = self.block(ofs, self.footer_start)?;
+            if block.typ != BLOCK_INDEX {
+                return Ok(Some(ofs));
+            }
+            let mut reader = Reader::new(block.records());
+            let mut last_key = Vec::new();
+            let mut found = None;
+            while reader.position() < block.records().len() {
+                reader.key(&mut last_key)?;
+                let pos = reader.varint()? as usize;
+                if last_key.as_slice() >= key {
+                    found = Some(pos);
+                    break;
+                }
+            }
+            match found {
+                None => return Ok(None),
+                Some(pos) => ofs = pos,
+            }
+        }
+
+        Err("index too deep")
+    }
+
+    fn has_refs(&self) -> bool {
+        self.ref_end > self.header_len && self.data.get(self.header_len) == Some(&BLOCK_REF)
+    }
+
+    /// Read the block at `ofs`. `end` bounds the section the block is in.
+    fn block(&self, ofs: usize, end: usize) -> record::Result<Block<'_>> {
+        let end = end.min(self.data.len());
+        let header = if ofs == 0 { self.header_len } else { ofs };
+        let body_start = header
+            .checked_add(4)
+            .filter(|start| *start <= end)
+            .ok_or("block out of bounds")?;
+        let block_header = &self.data[header..body_start];
+        let typ = block_header[0];
+        let len = be_u24(&block_header[1..4]);
+        // Restart offsets are relative to the start of the block, which for
+        // the first block is the start of the file
+        let restart_base = body_start - ofs;
+        if len < restart_base {
+            return Err("invalid block length");
+        }
+
+        // Log blocks are deflated, their length is the inflated length
+        if typ == BLOCK_LOG {
+            let mut inflated = vec![0u8; len - restart_base];
+            let input = &self.data[body_start..end];
+            let (_, consumed, produced) = zlib::Inflate::default()
+                .once(input, &mut inflated)
+                .map_err(|_| "failed to inflate log block")?;
+            if produced != inflated.len() {
+                return Err("truncated log block");
+            }
+            let restarts = restarts_len(&inflated)?;
+            return Ok(Block {
+                typ,
+                records: 0..inflated.len() - restarts,
+                buf: Cow::Owned(inflated),
+                restart_base,
+                next: body_start + consumed,
+            });
+        }
+
+        let block_end = ofs
+            .checked_add(len)
+            .filter(|block_end| *block_end <= end)
+            .ok_or("block exceeds section")?;
+        let body = &self.data[body_start..block_end];
+        let restarts = restarts_len(body)?;
+        // Blocks are either padded with NULs to the block size, or unaligned
+        let next = if self.block_size > 0 && self.data.get(block_end) == Some(&0) {
+            ofs.saturating_add(self.block_size)
+        } else {
+            block_end
+        };
+        Ok(Block {
+            typ,
+            records: 0..body.len() - restarts,
+            buf: Cow::Borrowed(body),
+            restart_base,
+            next,
+        })
+    }
+}
+
+struct Block<'a> {
+    typ: u8,
+    /// The block body: records, followed by the restart table.
+    buf: Cow<'a, [u8]>,
+    records: Range<usize>,
+    /// Offset of `buf` relative to the start of the block.
+    restart_base: usize,
+    next: usize,
+}
+
+impl Block<'_> {
+    fn records(&self) -> &[u8] {
+        &self.buf[self.records.clone()]
+    }
+
+    /// Positions of the restart points within [`Block::records`].
+    fn restarts(&self) -> record::Result<Vec<usize>> {
+        self.buf[self.records.end..self.buf.len() - 2]
+            .chunks(3)
+            .map(|ofs| {
+                be_u24(ofs)
+                    .checked_sub(self.restart_base)
+                    .filter(|pos| *pos < self.records.end)
+                    .ok_or("restart offset out of bounds")
+            })
+            .collect()
+    }
+}
+
+/// Length of the restart table at the end of a block body.
+fn restarts_len(body: &[u8]) -> record::Result<usize> {
+    if body.len() < 2 {
+        return Err("block too short");
+    }
+    let count = u16::from_be_bytes([body[body.len() - 2], body[body.len() - 1]]) as usize;
+    let len = 2 + 3 * count;
+    if len > body.len() {
+        return Err("invalid restart count");
+    }
+    Ok(len)
+}
+
+struct Cursor<'a> {
+    block: Block<'a>,
+    pos: usize,
+    last_key: Vec<u8>,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(block: Block<'a>) -> Self {
+        Self {
+            block,
+            pos: 0,
+            last_key: Vec::new(),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.block.records().len()
+    }
+
+    /// Skip ahead to the last restart point whose key is less than `key`.
+    ///
+    /// Restart points store the full key, so this is a binary search over the
+    /// restart table, which decodes only the keys it visits.
+    fn seek(&mut self, key: &[u8]) -> record::Result<()> {
+        let restarts = self.block.restarts()?;
+        let records = self.block.records();
+        let mut restart_key = Vec::new();
+        let (mut lo, mut hi) = (0, restarts.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            restart_key.clear();
+            Reader::new(&records[restarts[mid]..]).key(&mut restart_key)?;
+            if restart_key.as_slice() < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo > 0 && restarts[lo - 1] > self.pos {
+            self.pos = restarts[lo - 1];
+            self.last_key.clear();
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over the ref records of a [`Table`].
+pub(super) struct Refs<'a> {
+    table: &'a Table,
+    next_block: Option<usize>,
+    current: Option<Cursor<'a>>,
+    start: Vec<u8>,
+}
+
+impl Refs<'_> {
+    fn next_record(&mut self) -> record::Result<Option<RefRecord>> {
+        loop {
+            match &mut self.current {
+                Some(cursor) if !cursor.is_done() => {
+                    let mut reader = Reader::new(&cursor.block.records()[cursor.pos..]);
+                    let typ = reader.key(&mut cursor.last_key)?;
+                    let (delta, value) = reader.ref_value(typ)?;
+                    cursor.pos += reader.position();
+                    if cursor.last_key < self.start {
+                        continue;
+                    }
+                    // All subsequent records are greater
+                    self.start.clear();
+                    return Ok(Some(RefRecord {
+                        name: cursor.last_key.clone(),
+                        update_index: self.table.min_update_index + delta,
+                        value,
+                    }));
+                },
+                _ => {
+                    let ofs = match self.next_block {
+                        Some(ofs) if ofs < self.table.ref_end => ofs,
+                        _ => return Ok(None),
+                    };
+                    let block = self.table.block(ofs, self.table.ref_end)?;
+                    if block.typ != BLOCK_REF {
+                        return Ok(None);
+                    }
+                    self.next_block = Some(block.next);
+                    let mut cursor = Cursor::new(block);
+                    cursor.seek(&self.start)?;
+                    self.current = Some(cursor);
+                },
+            }
+        }
+    }
+}
+
+impl Iterator for Refs<'_> {
+    type Item = record::Result<RefRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(rec) => rec.map(Ok),
+            Err(e) => {
+                self.next_block = None;
+                self.current = None;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// Iterator over the log records of a [`Table`].
+pub(super) struct Logs<'a> {
+    table: &'a Table,
+    next_block: Option<usize>,
+    current: Option<Cursor<'a>>,
+    start: Vec<u8>,
+}
+
+impl Logs<'_> {
+    fn next_record(&mut self) -> record::Result<Option<LogRecord>> {
+        let end = match &self.table.logs {
+            None => return Ok(None),
+            Some(logs) => logs.end,
+        };
+        loop {
+            match &mut self.current {
+                Some(cursor) if !cursor.is_done() => {
+                    let mut reader = Reader::new(&cursor.block.records()[cursor.pos..]);
+                    let typ = reader.key(&mut cursor.last_key)?;
+                    let value = reader.log_value(typ)?;
+                    cursor.pos += reader.position();
+                    if cursor.last_key < self.start {
+                        continue;
+                    }
+                    self.start.clear();
+                    return LogRecord::from_key(&cursor.last_key, value).map(Some);
+                },
+                _ => {
+                    let ofs = match self.next_block {
+                        Some(ofs) if ofs < end => ofs,
+                        _ => return Ok(None),
+                    };
+                    let block = self.table.block(ofs, end)?;
+                    if block.typ != BLOCK_LOG {
+                        return Ok(None);
+                    }
+                    self.next_block = Some(block.next);
+                    let mut cursor = Cursor::new(block);
+                    cursor.seek(&self.start)?;
+                    self.current = Some(cursor);
+                },
+            }
+        }
+    }
+}
+
+impl Iterator for Logs<'_> {
+    type Item = record::Result<LogRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(rec) => rec.map(Ok),
+            Err(e) => {
+                self.next_block = None;
+                self.current = None;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+pub(super) fn be_u24(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | bytes[2] as usize
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().expect("slice of length 4"))
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().expect("slice of length 8"))
+}
+
+/// CRC-32 (IEEE), as used for the footer checksum.
+pub(super) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,292 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Writing a single reftable file.
+
+use super::{
+    record::{self, LogRecord, RefRecord},
+    table::{crc32, BLOCK_INDEX, BLOCK_LOG, BLOCK_REF, HEADER_LEN_V1, MAGIC},
+};
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+const MAX_BLOCK_SIZE: usize = (1 << 24) - 1;
+const RESTART_INTERVAL: usize = 16;
+/// Block header, one restart offset and the restart count.
+const BLOCK_OVERHEAD: usize = 4 + 3 + 2;
+/// The number of ref blocks from which on a ref index is written, as required
+/// by the format.
+const MIN_INDEXED_BLOCKS: usize = 4;
+
+/// Serialise a table with the given records, whose update indices must lie
+/// within `min_update_index..=max_update_index`.
+///
+/// `refs` must be sorted by name, and `logs` by [`LogRecord::key`]. Ref blocks
+/// are aligned to the block size, log blocks are not.
+pub(super) fn write(
+    min_update_index: u64,
+    max_update_index: u64,
+    refs: &[RefRecord],
+    logs: &[LogRecord],
+) -> Vec<u8> {
+    let refs = refs
+        .iter()
+        .map(|r| {
+            let mut value = Vec::new();
+            record::put_ref_value(&mut value, r.update_index - min_update_index, &r.value);
+            (r.name.clone(), record::ref_value_type(&r.value), value)
+        })
+        .collect::<Vec<_>>();
+    let logs = logs
+        .iter()
+        .map(|l| {
+            let mut value = Vec::new();
+            record::put_log_value(&mut value, &l.value);
+            (l.key(), record::log_value_type(&l.value), value)
+        })
+        .collect::<Vec<_>>();
+
+    // Make sure even the largest record fits into a block
+    let largest = refs
+        .iter()
+        .chain(&logs)
+        .map(|(key, _, value)| key.len() + value.len() + 2 * 10)
+        .max()
+        .unwrap_or(0);
+    let block_size = (HEADER_LEN_V1 + BLOCK_OVERHEAD + largest)
+        .next_power_of_two()
+        .clamp(DEFAULT_BLOCK_SIZE, MAX_BLOCK_SIZE);
+
+    let mut header = Vec::with_capacity(HEADER_LEN_V1);
+    header.extend_from_slice(MAGIC);
+    header.push(1);
+    header.extend_from_slice(&(block_size as u32).to_be_bytes()[1..]);
+    header.extend_from_slice(&min_update_index.to_be_bytes());
+    header.extend_from_slice(&max_update_index.to_be_bytes());
+
+    let mut out = header.clone();
+
+    // Ref blocks, padded to the block size
+    let mut blocks = Blocks::new(BLOCK_REF, block_size, HEADER_LEN_V1);
+    for (key, typ, value) in &refs {
+        blocks.push(key, *typ, value);
+    }
+    let mut ref_blocks = Vec::new();
+    for (i, (block, last_key)) in blocks.finish().into_iter().enumerate() {
+        if i == 0 {
+            // The first block includes the file header
+            ref_blocks.push((last_key, 0));
+            out.extend_from_slice(&block[HEADER_LEN_V1..]);
+        } else {
+            ref_blocks.push((last_key, out.len()));
+            out.extend_from_slice(&block);
+        }
+        pad(&mut out, block_size);
+    }
+
+    let ref_index_position = if ref_blocks.len() >= MIN_INDEXED_BLOCKS {
+        write_index(&mut out, block_size, ref_blocks)
+    } else {
+        0
+    };
+
+    // Log blocks, deflated
+    let log_position = if logs.is_empty() { 0 } else { out.len() };
+    let mut blocks = Blocks::new(BLOCK_LOG, block_size, 0);
+    for (key, typ, value) in &logs {
+        blocks.push(key, *typ, value);
+    }
+    for (block, _) in blocks.finish() {
+        out.extend_from_slice(&block[..4]);
+        out.extend_from_slice(&zlib_stored(&block[4..]));
+    }
+
+    // Footer
+    let footer_start = out.len();
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(ref_index_position as u64).to_be_bytes());
+    out.extend_from_slice(&0u64.to_be_bytes()); // obj
+    out.extend_from_slice(&0u64.to_be_bytes()); // obj index
+    out.extend_from_slice(&(log_position as u64).to_be_bytes());
+    out.extend_from_slice(&0u64.to_be_bytes()); // log index
+    let crc = crc32(&out[footer_start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+
+    out
+}
+
+/// Write an index over `blocks`, given as their last key and position, and
+/// return the position of the root index block.
+///
+/// If the index does not fit into a single block, the index blocks are indexed
+/// in turn, until a single root block remains.
+fn write_index(out: &mut Vec<u8>, block_size: usize, mut blocks: Vec<(Vec<u8>, usize)>) -> usize {
+    loop {
+        let mut index = Blocks::new(BLOCK_INDEX, block_size, 0);
+        for (last_key, position) in &blocks {
+            let mut value = Vec::new();
+            record::put_varint(&mut value, *position as u64);
+            index.push(last_key, 0, &value);
+        }
+        blocks = index
+            .finish()
+            .into_iter()
+            .map(|(block, last_key)| {
+                let position = out.len();
+                out.extend_from_slice(&block);
+                pad(out, block_size);
+                (last_key, position)
+            })
+            .collect();
+        if blocks.len() == 1 {
+            return blocks[0].1;
+        }
+    }
+}
+
+fn pad(out: &mut Vec<u8>, block_size: usize) {
+    let padded = (out.len() + block_size - 1) / block_size * block_size;
+    out.resize(padded, 0);
+}
+
+/// Accumulates records into blocks of at most `block_size` bytes.
+struct Blocks {
+    typ: u8,
+    block_size: usize,
+    /// Space reserved for the file header at the start of the first block.
+    reserved: usize,
+    /// Finished blocks, and the last key in each.
+    done: Vec<(Vec<u8>, Vec<u8>)>,
+    current: Vec<u8>,
+    restarts: Vec<u32>,
+    last_key: Vec<u8>,
+    since_restart: usize,
+}
+
+impl Blocks {
+    fn new(typ: u8, block_size: usize, reserved: usize) -> Self {
+        Self {
+            typ,
+            block_size,
+            reserved,
+            done: Vec::new(),
+            current: Vec::new(),
+            restarts: Vec::new(),
+            last_key: Vec::new(),
+            since_restart: 0,
+        }
+    }
+
+    fn push(&mut self, key: &[u8], typ: u8, value: &[u8]) {
+        if self.current.is_empty() {
+            let reserved = if self.done.is_empty() {
+                self.reserved
+            } else {
+                0
+            };
+            self.current.resize(reserved, 0);
+            self.current.extend_from_slice(&[self.typ, 0, 0, 0]);
+        }
+
+        let mut rec = self.encode(key, typ, value);
+        let restart = self.since_restart % RESTART_INTERVAL == 0;
+        let size = self.current.len() + rec.len() + 3 * (self.restarts.len() + 1) + 2;
+        // Start a new block, unless this is the only record in the block
+        if size > self.block_size && self.since_restart > 0 {
+            self.finish_block();
+            return self.push(key, typ, value);
+        }
+
+        if restart {
+            self.restarts.push(self.current.len() as u32);
+        }
+        self.current.append(&mut rec);
+        self.since_restart += 1;
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+    }
+
+    /// Restart points store the full key, all other records are
+    /// prefix-compressed against their predecessor.
+    fn encode(&self, key: &[u8], typ: u8, value: &[u8]) -> Vec<u8> {
+        let last_key: &[u8] = if self.since_restart % RESTART_INTERVAL == 0 {
+            &[]
+        } else {
+            &self.last_key
+        };
+        let mut rec = Vec::with_capacity(key.len() + value.len() + 4);
+        record::put_key(&mut rec, last_key, key, typ);
+        rec.extend_from_slice(value);
+        rec
+    }
+
+    fn finish_block(&mut self) {
+        if self.since_restart == 0 {
+            return;
+        }
+        let count = self.restarts.len() as u16;
+        for ofs in self.restarts.drain(..) {
+            self.current.extend_from_slice(&ofs.to_be_bytes()[1..]);
+        }
+        self.current.extend_from_slice(&count.to_be_bytes());
+
+        let mut block = std::mem::take(&mut self.current);
+        let reserved = if self.done.is_empty() {
+            self.reserved
+        } else {
+            0
+        };
+        let len = (block.len() as u32).to_be_bytes();
+        block[reserved + 1..reserved + 4].copy_from_slice(&len[1..]);
+        let last_key = std::mem::take(&mut self.last_key);
+        self.done.push((block, last_key));
+        self.since_restart = 0;
+    }
+
+    /// The finished blocks and their last keys. The first block includes the
+    /// reserved space.
+    fn finish(mut self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.finish_block();
+        self.done
+    }
+}
+
+/// Wrap `data` in a zlib stream using stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_STORED: usize = 0xffff;
+
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_STORED * 5 + 11);
+    // CMF (deflate, 32K window), FLG (no dict, fastest, check bits)
+    out.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(MAX_STORED).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        out.push(u8::from(last));
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
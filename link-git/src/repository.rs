@@ -0,0 +1,444 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! A [`Repository`] combines a [`Refdb`] and an [`Odb`], so refs can be
+//! peeled to the objects they (ultimately) point to, and revisions can be
+//! resolved.
+
+use std::io;
+
+use git_hash::{oid, ObjectId};
+use git_object::Kind;
+use git_pack::cache::DecodeEntry;
+use git_ref::Target;
+use git_ref_format::{
+    rev::{self, Resolve as _},
+    RefStr,
+    RefString,
+};
+
+use crate::{
+    hex,
+    odb::{self, index, window, Odb},
+    refdb::{self, Refdb},
+};
+
+/// Tags pointing to tags are followed at most this many times.
+const MAX_PEEL_DEPTH: usize = 32;
+
+pub mod error {
+    use super::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Peel {
+        #[error("object {0} not found")]
+        NotFound(ObjectId),
+
+        #[error("object {id} is a {kind}, which can not be peeled to a {target}")]
+        Mismatch {
+            id: ObjectId,
+            kind: Kind,
+            target: Kind,
+        },
+
+        #[error("malformed {kind} {id}: {reason}")]
+        Malformed {
+            id: ObjectId,
+            kind: Kind,
+            reason: &'static str,
+        },
+
+        #[error("max tag depth {0} exceeded")]
+        DepthLimitExceeded(usize),
+
+        #[error(transparent)]
+        Find(#[from] odb::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Resolve {
+        #[error("invalid revision {rev:?}")]
+        Syntax {
+            rev: String,
+            #[source]
+            source: rev::ParseError,
+        },
+
+        #[error("unknown revision {0:?}")]
+        Unknown(String),
+
+        #[error("unsupported revision {0:?}")]
+        Unsupported(String),
+
+        #[error("{name} has no reflog entry {n}")]
+        NoReflogEntry { name: RefString, n: usize },
+
+        #[error("failed to read reflog")]
+        Reflog(#[source] io::Error),
+
+        #[error(transparent)]
+        ReflogEntry(#[from] refdb::reflog::error::Parse),
+
+        #[error("commit {id} has no parent {n}")]
+        NoParent { id: ObjectId, n: usize },
+
+        #[error("path {path:?} does not exist in tree {tree}")]
+        NoPath { tree: ObjectId, path: String },
+
+        #[error(transparent)]
+        Peel(#[from] Peel),
+
+        #[error(transparent)]
+        Snapshot(#[from] refdb::error::Snapshot),
+
+        #[error(transparent)]
+        Follow(#[from] refdb::error::Follow),
+
+        #[error(transparent)]
+        Find(#[from] git_ref::file::find::Error),
+    }
+}
+
+/// A [`Refdb`] and an [`Odb`] of the same repository.
+pub struct Repository<I, D> {
+    refdb: Refdb,
+    odb: Odb<I, D>,
+}
+
+impl<I, D> Repository<I, D>
+where
+    I: index::Index,
+    D: window::Cache,
+{
+    pub fn new(refdb: Refdb, odb: Odb<I, D>) -> Self {
+        Self { refdb, odb }
+    }
+
+    pub fn refdb(&self) -> &Refdb {
+        &self.refdb
+    }
+
+    pub fn odb(&self) -> &Odb<I, D> {
+        &self.odb
+    }
+
+    /// Peel the object `id` to a commit, following annotated tags.
+    pub fn peel_to_commit(
+        &self,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<ObjectId, error::Peel> {
+        self.peel(id.as_ref(), Some(Kind::Commit), cache)
+    }
+
+    /// Peel the object `id` to a tree, following annotated tags and commits.
+    pub fn peel_to_tree(
+        &self,
+        id: impl AsRef<oid>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<ObjectId, error::Peel> {
+        self.peel(id.as_ref(), Some(Kind::Tree), cache)
+    }
+
+    /// Resolve the revision `rev` to an object id.
+    ///
+    /// `rev` is parsed as a [`rev::Rev`], whose base is either a full
+    /// hexadecimal object id, or a ref name, which is expanded like `git` would
+    /// (ie. `main` may resolve to `refs/heads/main`). Symbolic refs are
+    /// followed, and `@{n}` selects the `n`th prior value from the reflog of
+    /// the ref.
+    ///
+    /// All suffixes except `^{/<regex>}` are supported. Searching by commit
+    /// message, the index and the `@{...}` forms besides `@{n}` are not.
+    ///
+    /// Abbreviated object ids are not supported either, as the [`Odb`] can
+    /// not look up objects by prefix. They are treated as ref names, and thus
+    /// usually fail to resolve with [`error::Resolve::Unknown`].
+    pub fn resolve(
+        &self,
+        rev: &str,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<ObjectId, error::Resolve> {
+        let parsed = rev
+            .parse::<rev::Rev>()
+            .map_err(|source| error::Resolve::Syntax {
+                rev: rev.to_owned(),
+                source,
+            })?;
+        Resolver { repo: self, cache }.resolve(&parsed)
+    }
+
+    /// Resolve the ref `name`, optionally selecting a prior value from its
+    /// reflog.
+    ///
+    /// A `name` of 40 hex digits is taken to be an object id, unless a reflog
+    /// entry is requested.
+    fn resolve_name(
+        &self,
+        name: &RefStr,
+        reflog: Option<usize>,
+    ) -> Result<ObjectId, error::Resolve> {
+        if reflog.is_none() {
+            if let Some(id) = hex::object_id(name.as_bytes()) {
+                return Ok(id);
+            }
+        }
+
+        let snapshot = self.refdb.snapshot()?;
+        let r = snapshot
+            .find(name.as_str())?
+            .ok_or_else(|| error::Resolve::Unknown(name.to_string()))?;
+        if let Some(n) = reflog {
+            let no_entry = || error::Resolve::NoReflogEntry {
+                name: name.to_owned(),
+                n,
+            };
+            let entry = snapshot
+                .reflog(&r.name)
+                .map_err(error::Resolve::Reflog)?
+                .and_then(|log| log.iter().rev().nth(n))
+                .ok_or_else(no_entry)?;
+            return Ok(entry?.new);
+        }
+        match snapshot.follow(&r)?.target {
+            Target::Peeled(id) => Ok(id),
+            Target::Symbolic(_) => unreachable!("`follow` returns a direct ref"),
+        }
+    }
+
+    /// Peel `id` to an object of kind `target`, or until it is no longer a
+    /// tag if `target` is `None`.
+    fn peel(
+        &self,
+        id: &oid,
+        target: Option<Kind>,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<ObjectId, error::Peel> {
+        peel(&self.odb, id, target, &mut Vec::new(), cache)
+    }
+
+    /// The `n`th (1-based) parent of `commit`.
+    fn parent(
+        &self,
+        commit: &oid,
+        n: usize,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<ObjectId, error::Resolve> {
+        let mut buf = Vec::new();
+        let obj = self
+            .odb
+            .find(commit, &mut buf, cache)
+            .map_err(error::Peel::from)?
+            .ok_or_else(|| error::Peel::NotFound(commit.to_owned()))?;
+        let parent = obj
+            .data
+            .split(|b| *b == b'\n')
+            .skip(1)
+            .take_while(|line| line.starts_with(b"parent "))
+            .nth(n - 1)
+            .map(|line| hex::object_id(&line[b"parent ".len()..]));
+        match parent {
+            None => Err(error::Resolve::NoParent {
+                id: commit.to_owned(),
+                n,
+            }),
+            Some(Some(id)) => Ok(id),
+            Some(None) => Err(error::Peel::Malformed {
+                id: commit.to_owned(),
+                kind: Kind::Commit,
+                reason: "invalid parent",
+            }
+            .into()),
+        }
+    }
+
+    /// Look up the entry at `path` in `tree`.
+    fn lookup_path(
+        &self,
+        tree: ObjectId,
+        path: &str,
+        cache: &mut impl DecodeEntry,
+    ) -> Result<ObjectId, error::Resolve> {
+        let no_path = || error::Resolve::NoPath {
+            tree,
+            path: path.to_owned(),
+        };
+
+        let mut buf = Vec::new();
+        let mut current = tree;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            let obj = self
+                .odb
+                .find(current, &mut buf, cache)
+                .map_err(error::Peel::from)?
+                .ok_or(error::Peel::NotFound(current))?;
+            if obj.kind != Kind::Tree {
+                return Err(no_path());
+            }
+            let id = current;
+            current = tree_entry(obj.data, name.as_bytes())
+                .map_err(|reason| error::Peel::Malformed {
+                    id,
+                    kind: Kind::Tree,
+                    reason,
+                })?
+                .ok_or_else(no_path)?;
+        }
+
+        Ok(current)
+    }
+}
+
+/// Resolves [`rev::Rev`]s against a [`Repository`].
+struct Resolver<'a, I, D, C> {
+    repo: &'a Repository<I, D>,
+    cache: &'a mut C,
+}
+
+impl<I, D, C> rev::Resolve for Resolver<'_, I, D, C>
+where
+    I: index::Index,
+    D: window::Cache,
+    C: DecodeEntry,
+{
+    type Output = ObjectId;
+    type Error = error::Resolve;
+
+    fn base(
+        &mut self,
+        base: &rev::Base,
+        reflog: Option<&rev::Reflog>,
+    ) -> Result<Self::Output, Self::Error> {
+        let name = match base {
+            rev::Base::Name(name) => name,
+            _ => return Err(error::Resolve::Unsupported(base.to_string())),
+        };
+        match reflog {
+            None => self.repo.resolve_name(name, None),
+            Some(rev::Reflog::Entry(n)) => self.repo.resolve_name(name, Some(*n)),
+            Some(reflog) => Err(error::Resolve::Unsupported(format!(
+                "{}@{{{}}}",
+                name, reflog
+            ))),
+        }
+    }
+
+    fn parent(&mut self, rev: Self::Output, n: usize) -> Result<Self::Output, Self::Error> {
+        let commit = self.repo.peel_to_commit(rev, self.cache)?;
+        self.repo.parent(&commit, n, self.cache)
+    }
+
+    fn peel(&mut self, rev: Self::Output, peel: rev::Peel) -> Result<Self::Output, Self::Error> {
+        let target = match peel {
+            rev::Peel::Tags => None,
+            rev::Peel::Object => {
+                let mut buf = Vec::new();
+                return match self
+                    .repo
+                    .odb
+                    .find(rev, &mut buf, self.cache)
+                    .map_err(error::Peel::from)?
+                {
+                    Some(_) => Ok(rev),
+                    None => Err(error::Peel::NotFound(rev).into()),
+                };
+            },
+            rev::Peel::Commit => Some(Kind::Commit),
+            rev::Peel::Tree => Some(Kind::Tree),
+            rev::Peel::Blob => Some(Kind::Blob),
+            rev::Peel::Tag => Some(Kind::Tag),
+        };
+        Ok(self.repo.peel(&rev, target, self.cache)?)
+    }
+
+    fn search(&mut self, _: Self::Output, regex: &str) -> Result<Self::Output, Self::Error> {
+        Err(error::Resolve::Unsupported(format!("^{{/{}}}", regex)))
+    }
+
+    fn path(&mut self, rev: Self::Output, path: &str) -> Result<Self::Output, Self::Error> {
+        let tree = self.repo.peel_to_tree(rev, self.cache)?;
+        self.repo.lookup_path(tree, path, self.cache)
+    }
+}
+
+/// Peel `id` to an object of kind `target`, or until it is no longer a tag if
+/// `target` is `None`.
+pub(crate) fn peel<I, D>(
+    odb: &Odb<I, D>,
+    id: &oid,
+    target: Option<Kind>,
+    buf: &mut Vec<u8>,
+    cache: &mut impl DecodeEntry,
+) -> Result<ObjectId, error::Peel>
+where
+    I: index::Index,
+    D: window::Cache,
+{
+    let mut current = id.to_owned();
+    for _ in 0..MAX_PEEL_DEPTH {
+        let obj = odb
+            .find(current, buf, cache)?
+            .ok_or(error::Peel::NotFound(current))?;
+        let (id, kind) = (current, obj.kind);
+        let malformed = |reason| error::Peel::Malformed { id, kind, reason };
+        match (obj.kind, target) {
+            (kind, Some(target)) if kind == target => return Ok(current),
+            (Kind::Tag, _) => {
+                current =
+                    header_id(obj.data, b"object").ok_or_else(|| malformed("invalid object"))?;
+            },
+            (_, None) => return Ok(current),
+            (Kind::Commit, Some(Kind::Tree)) => {
+                return header_id(obj.data, b"tree").ok_or_else(|| malformed("invalid tree"));
+            },
+            (kind, Some(target)) => {
+                return Err(error::Peel::Mismatch {
+                    id: current,
+                    kind,
+                    target,
+                })
+            },
+        }
+    }
+
+    Err(error::Peel::DepthLimitExceeded(MAX_PEEL_DEPTH))
+}
+
+/// If `data` contains a header line `name SP <hex>`, return the id.
+///
+/// Headers are terminated by the first empty line.
+fn header_id(data: &[u8], name: &[u8]) -> Option<ObjectId> {
+    data.split(|b| *b == b'\n')
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(b" "))
+        .and_then(hex::object_id)
+}
+
+/// Find the entry `name` in the tree `data`.
+fn tree_entry(mut data: &[u8], name: &[u8]) -> Result<Option<ObjectId>, &'static str> {
+    while !data.is_empty() {
+        let nul = data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("truncated tree entry")?;
+        let entry = &data[..nul];
+        let rest = &data[nul + 1..];
+        if rest.len() < 20 {
+            return Err("truncated tree entry");
+        }
+        let sp = entry
+            .iter()
+            .position(|b| *b == b' ')
+            .ok_or("invalid tree entry")?;
+        if &entry[sp + 1..] == name {
+            let id = oid::try_from(&rest[..20]).map_err(|_| "invalid tree entry")?;
+            return Ok(Some(id.to_owned()));
+        }
+        data = &rest[20..];
+    }
+
+    Ok(None)
+}
@@ -3,4 +3,9 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod fixture;
+mod odb;
 mod protocol;
+mod refdb;
+mod reftable;
+mod repository;
@@ -0,0 +1,142 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Repositories set up using the `git` CLI.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bstr::ByteSlice as _;
+use link_git::{
+    actor::{Sign, Signature, Time},
+    hash::ObjectId,
+    odb::{
+        backend::{Loose, Packed},
+        index::Shared,
+        window,
+        Odb,
+    },
+};
+use tempfile::{tempdir, TempDir};
+
+pub type TestOdb = Odb<Shared<()>, window::Small<()>>;
+
+/// A non-bare repository with a `main` branch.
+pub struct Repo {
+    pub tmp: TempDir,
+}
+
+impl Repo {
+    pub fn new() -> Self {
+        let tmp = tempdir().unwrap();
+        git(tmp.path(), &["init", "--quiet", "--initial-branch=main"]);
+        Self { tmp }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.tmp.path()
+    }
+
+    pub fn git_dir(&self) -> PathBuf {
+        self.path().join(".git")
+    }
+
+    pub fn objects_dir(&self) -> PathBuf {
+        self.git_dir().join("objects")
+    }
+
+    pub fn git(&self, args: &[&str]) -> String {
+        git(self.path(), args)
+    }
+
+    /// Write `contents` to `file`, and commit it on the current branch.
+    pub fn commit(&self, file: &str, contents: &str) -> ObjectId {
+        std::fs::write(self.path().join(file), contents).unwrap();
+        self.git(&["add", file]);
+        self.git(&["commit", "--quiet", "-m", file]);
+        self.rev_parse("HEAD")
+    }
+
+    /// Create and check out `branch` at `start`.
+    pub fn branch(&self, branch: &str, start: &str) {
+        self.git(&["checkout", "--quiet", "-b", branch, start]);
+    }
+
+    pub fn checkout(&self, branch: &str) {
+        self.git(&["checkout", "--quiet", branch]);
+    }
+
+    /// Merge `other` into the current branch, creating a merge commit.
+    pub fn merge(&self, other: &str) -> ObjectId {
+        self.git(&["merge", "--quiet", "--no-ff", "--no-edit", other]);
+        self.rev_parse("HEAD")
+    }
+
+    pub fn odb(&self) -> TestOdb {
+        Odb {
+            loose: Loose::at(self.objects_dir()).unwrap(),
+            packed: Packed {
+                index: Shared::open(self.git_dir()).unwrap(),
+                data: window::Small::default(),
+            },
+        }
+    }
+
+    pub fn rev_parse(&self, rev: &str) -> ObjectId {
+        ObjectId::from_hex(self.git(&["rev-parse", rev]).as_bytes()).unwrap()
+    }
+}
+
+pub fn git(repo: &Path, args: &[&str]) -> String {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_AUTHOR_NAME", "apollo")
+        .env("GIT_AUTHOR_EMAIL", "apollo@cree.de")
+        .env("GIT_AUTHOR_DATE", "1640000000 +0100")
+        .env("GIT_COMMITTER_NAME", "apollo")
+        .env("GIT_COMMITTER_EMAIL", "apollo@cree.de")
+        .env("GIT_COMMITTER_DATE", "1640000000 +0100")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "git {:?} failed: {}",
+        args,
+        out.stderr.to_str_lossy()
+    );
+    out.stdout.to_str().unwrap().trim().to_owned()
+}
+
+pub fn committer() -> Signature {
+    Signature {
+        name: "apollo".into(),
+        email: "apollo@cree.de".into(),
+        time: Time {
+            time: 1_640_000_000,
+            offset: 3600,
+            sign: Sign::Plus,
+        },
+    }
+}
+
+/// Wait up to 10 seconds for `cond` to become `true`.
+pub fn eventually(mut cond: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if cond() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    cond()
+}
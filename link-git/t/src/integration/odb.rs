@@ -0,0 +1,14 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+mod alternates;
+mod commit_graph;
+mod decode;
+mod fsck;
+mod header;
+mod midx;
+mod nonblocking;
+mod openmetrics;
+mod watch;
@@ -0,0 +1,93 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use link_git::odb::{alternates, cache};
+
+use crate::integration::fixture::Repo;
+
+fn write_alternates(repo: &Repo, contents: &str) {
+    let info = repo.objects_dir().join("info");
+    fs::create_dir_all(&info).unwrap();
+    fs::write(info.join("alternates"), contents).unwrap();
+}
+
+fn canonical(path: impl AsRef<Path>) -> PathBuf {
+    fs::canonicalize(path).unwrap()
+}
+
+#[test]
+fn resolve() {
+    let a = Repo::new();
+    let b = Repo::new();
+    let c = Repo::new();
+
+    // c -> b (relative, quoted) -> a (absolute), a -> c is a cycle
+    write_alternates(
+        &c,
+        &format!(
+            "# comment\n\n\"{}\"\n/does/not/exist\n",
+            Path::new("../../..")
+                .join(b.path().file_name().unwrap())
+                .join(".git")
+                .join("objects")
+                .display()
+        ),
+    );
+    write_alternates(&b, &format!("{}\n", a.objects_dir().display()));
+    write_alternates(&a, &format!("{}\n", c.objects_dir().display()));
+
+    // The temp dirs are siblings, so the relative path resolves
+    assert_eq!(b.path().parent(), c.path().parent());
+    assert_eq!(
+        alternates::resolve(c.objects_dir()).unwrap(),
+        vec![canonical(b.objects_dir()), canonical(a.objects_dir())]
+    );
+    assert!(alternates::resolve(Repo::new().objects_dir())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn malformed() {
+    let repo = Repo::new();
+    write_alternates(&repo, "\"unterminated\n");
+    assert!(matches!(
+        alternates::resolve(repo.objects_dir()),
+        Err(alternates::error::Alternates::Malformed { .. })
+    ));
+}
+
+#[test]
+fn lookup() {
+    let a = Repo::new();
+    let packed = a.commit("a", "a");
+    a.git(&["repack", "-q", "-a", "-d"]);
+    let b = Repo::new();
+    let loose = b.commit("b", "b");
+    let c = Repo::new();
+    write_alternates(
+        &c,
+        &format!(
+            "{}\n{}\n",
+            a.objects_dir().display(),
+            b.objects_dir().display()
+        ),
+    );
+
+    let odb = c.odb();
+    assert_eq!(odb.loose.objects_dirs().count(), 3);
+    assert_eq!(odb.packed.index.len(), 1);
+
+    let mut buf = Vec::new();
+    for id in [packed, loose] {
+        assert!(odb.contains(id), "{} not found", id);
+        assert!(odb.find(id, &mut buf, &mut cache::Never).unwrap().is_some());
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{convert::TryInto as _, fs};
+
+use link_git::{
+    hash::ObjectId,
+    odb::{
+        cache,
+        commit_graph::{error, Graph, Position},
+    },
+};
+
+use crate::integration::fixture::Repo;
+
+/// ```text
+/// c1 - c2 - c3 ------ m
+///        \          /
+///         f1 ---- f2
+/// ```
+struct History {
+    repo: Repo,
+    c1: ObjectId,
+    c2: ObjectId,
+    c3: ObjectId,
+    f1: ObjectId,
+    f2: ObjectId,
+    m: ObjectId,
+}
+
+impl History {
+    fn new() -> Self {
+        let repo = Repo::new();
+        let c1 = repo.commit("c1", "c1");
+        let c2 = repo.commit("c2", "c2");
+        repo.branch("feature", "main");
+        let f1 = repo.commit("f1", "f1");
+        let f2 = repo.commit("f2", "f2");
+        repo.checkout("main");
+        let c3 = repo.commit("c3", "c3");
+        let m = repo.merge("feature");
+
+        Self {
+            repo,
+            c1,
+            c2,
+            c3,
+            f1,
+            f2,
+            m,
+        }
+    }
+
+    fn write_graph(&self, args: &[&str]) -> Graph {
+        let mut cmd = vec!["commit-graph", "write", "--reachable", "--no-progress"];
+        cmd.extend(args);
+        self.repo.git(&cmd);
+        Graph::open(self.repo.objects_dir()).unwrap().unwrap()
+    }
+
+    /// Overwrite the parent fields of `commit` in a single-file graph.
+    fn corrupt_parents(&self, commit: ObjectId, parent1: u32, parent2: u32) -> Graph {
+        let pos = self.write_graph(&[]).lookup(commit).unwrap();
+        let path = self.repo.objects_dir().join("info").join("commit-graph");
+        let mut data = fs::read(&path).unwrap();
+
+        // Chunk table entries are a 4-byte id followed by an 8-byte offset
+        let cdat = (0..data[6] as usize)
+            .map(|i| &data[8 + i * 12..8 + (i + 1) * 12])
+            .find(|entry| &entry[..4] == b"CDAT")
+            .map(|entry| u64::from_be_bytes(entry[4..].try_into().unwrap()) as usize)
+            .unwrap();
+        let parents = cdat + pos.0 as usize * 36 + 20;
+        data[parents..parents + 4].copy_from_slice(&parent1.to_be_bytes());
+        data[parents + 4..parents + 8].copy_from_slice(&parent2.to_be_bytes());
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, data).unwrap();
+
+        Graph::open(self.repo.objects_dir()).unwrap().unwrap()
+    }
+}
+
+#[test]
+fn no_graph() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    assert!(Graph::open(repo.objects_dir()).unwrap().is_none())
+}
+
+#[test]
+fn single_file() {
+    let h = History::new();
+    let graph = h.write_graph(&[]);
+    assert_eq!(graph.files().len(), 1);
+    assert_eq!(graph.len(), 6);
+
+    let pos = graph.lookup(h.m).unwrap();
+    assert_eq!(graph.id_at(pos).unwrap().to_owned(), h.m);
+    let m = graph.commit_at(pos).unwrap();
+    assert_eq!(h.repo.rev_parse("HEAD^{tree}"), m.tree);
+    assert_eq!(m.generation, 5);
+    assert_eq!(m.commit_time, 1_640_000_000);
+    let parents = m
+        .parents
+        .iter()
+        .map(|p| graph.id_at(*p).unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(parents, vec![h.c3, h.f2]);
+
+    let c1 = graph.commit_at(graph.lookup(h.c1).unwrap()).unwrap();
+    assert!(c1.parents.is_empty());
+    assert_eq!(c1.generation, 1);
+
+    assert!(!graph.contains(ObjectId::null_sha1()));
+}
+
+#[test]
+fn octopus() {
+    let h = History::new();
+    h.repo.branch("a", "main~1");
+    let a = h.repo.commit("a", "a");
+    h.repo.branch("b", "main~1");
+    let b = h.repo.commit("b", "b");
+    h.repo.checkout("main");
+    h.repo
+        .git(&["merge", "--quiet", "--no-ff", "--no-edit", "a", "b"]);
+    let octopus = h.repo.rev_parse("HEAD");
+
+    let graph = h.write_graph(&[]);
+    let commit = graph.commit_at(graph.lookup(octopus).unwrap()).unwrap();
+    let parents = commit
+        .parents
+        .iter()
+        .map(|p| graph.id_at(*p).unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(parents, vec![h.m, a, b]);
+}
+
+#[test]
+fn split_chain() {
+    let h = History::new();
+    h.write_graph(&["--split"]);
+    let tip = h.repo.commit("d", "d");
+    let graph = h.write_graph(&["--split=no-merge"]);
+    assert_eq!(graph.files().len(), 2);
+    assert_eq!(graph.len(), 7);
+
+    // Parents in the base layer are resolved from the top layer
+    let commit = graph.commit_at(graph.lookup(tip).unwrap()).unwrap();
+    assert_eq!(commit.generation, 6);
+    assert_eq!(commit.parents.len(), 1);
+    assert_eq!(graph.id_at(commit.parents[0]).unwrap().to_owned(), h.m);
+    for id in [h.c1, h.c2, h.c3, h.f1, h.f2, h.m, tip] {
+        assert_eq!(
+            graph.id_at(graph.lookup(id).unwrap()).unwrap().to_owned(),
+            id
+        );
+    }
+}
+
+fn reach(with_graph: bool) {
+    let h = History::new();
+    let graph = with_graph.then(|| h.write_graph(&[]));
+    // Not in the graph, if there is one
+    let tip = h.repo.commit("d", "d");
+
+    let odb = h.repo.odb();
+    let mut reach = odb.reach(graph.as_ref(), cache::Never);
+
+    assert!(reach.is_ancestor(h.c1, h.m).unwrap());
+    assert!(reach.is_ancestor(h.f1, tip).unwrap());
+    assert!(reach.is_ancestor(h.m, h.m).unwrap());
+    assert!(!reach.is_ancestor(h.f1, h.c3).unwrap());
+    assert!(!reach.is_ancestor(h.m, h.c1).unwrap());
+    assert!(!reach.is_ancestor(tip, h.m).unwrap());
+
+    assert_eq!(
+        reach.contains(h.f1, [h.c3, h.f2, h.m, tip]).unwrap(),
+        vec![false, true, true, true]
+    );
+
+    assert_eq!(reach.merge_base(h.c3, h.f2).unwrap(), Some(h.c2));
+    assert_eq!(reach.merge_base(h.f1, tip).unwrap(), Some(h.f1));
+    assert_eq!(reach.merge_base(h.c1, h.c1).unwrap(), Some(h.c1));
+    assert_eq!(reach.merge_bases(h.c3, h.f2).unwrap(), vec![h.c2]);
+
+    assert_eq!(reach.generation(h.c1).unwrap(), 1);
+    assert_eq!(reach.generation(h.m).unwrap(), 5);
+    assert_eq!(reach.generation(tip).unwrap(), 6);
+}
+
+#[test]
+fn reach_without_graph() {
+    reach(false)
+}
+
+#[test]
+fn reach_with_graph() {
+    reach(true)
+}
+
+#[test]
+fn malformed_commits() {
+    let h = History::new();
+    let graph = h.write_graph(&[]);
+    let end = Position(graph.len());
+    assert!(graph.id_at(end).is_none());
+    assert!(matches!(
+        graph.commit_at(end),
+        Err(error::Commit::OutOfBounds(_))
+    ));
+
+    let c1 = graph.lookup(h.c1).unwrap();
+    let graph = h.corrupt_parents(h.m, 1000, c1.0);
+    assert!(matches!(
+        graph.commit_at(graph.lookup(h.m).unwrap()),
+        Err(error::Commit::Malformed {
+            reason: "invalid parent position",
+            ..
+        })
+    ));
+    let graph = h.corrupt_parents(h.m, c1.0, 0x8000_0000 | 1000);
+    assert!(matches!(
+        graph.commit_at(graph.lookup(h.m).unwrap()),
+        Err(error::Commit::Malformed { .. })
+    ));
+
+    // Corrupt entries are looked up in the odb instead
+    let odb = h.repo.odb();
+    let mut reach = odb.reach(Some(&graph), cache::Never);
+    assert!(reach.is_ancestor(h.f1, h.m).unwrap());
+    assert!(reach.is_ancestor(h.c3, h.m).unwrap());
+    assert!(!reach.is_ancestor(h.m, h.c3).unwrap());
+    assert_eq!(reach.merge_base(h.m, h.f2).unwrap(), Some(h.f2));
+}
+
+#[test]
+fn criss_cross_merge_bases() {
+    let repo = Repo::new();
+    repo.commit("base", "base");
+    repo.branch("other", "main");
+    let x = repo.commit("x", "x");
+    repo.checkout("main");
+    let y = repo.commit("y", "y");
+    let a = repo.merge("other");
+    repo.checkout("other");
+    let b = repo.merge(&y.to_string());
+
+    let odb = repo.odb();
+    let mut reach = odb.reach(None, cache::Never);
+    let mut bases = reach.merge_bases(a, b).unwrap();
+    bases.sort();
+    let mut expected = vec![x, y];
+    expected.sort();
+    assert_eq!(bases, expected);
+}
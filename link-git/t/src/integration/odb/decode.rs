@@ -0,0 +1,197 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{sync::Arc, thread};
+
+use link_git::{
+    object::Kind,
+    odb::{
+        cache::DecodeEntry,
+        decode::{Handle, Limits, Sharded, Stats},
+    },
+};
+
+use crate::integration::fixture::Repo;
+
+fn cache(limits: Limits) -> Arc<Sharded<Stats>> {
+    Arc::new(Sharded::new(limits).with_stats())
+}
+
+fn get(handle: &mut Handle<Stats>, offset: u64) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    handle.get(0, offset, &mut out).map(|_| out)
+}
+
+#[test]
+fn hit_and_miss() {
+    let cache = cache(Limits::default());
+    let mut handle = cache.handle();
+
+    assert_eq!(get(&mut handle, 12), None);
+    handle.put(0, 12, b"base", Kind::Blob, 4);
+    let mut out = Vec::new();
+    assert_eq!(handle.get(0, 12, &mut out), Some((Kind::Blob, 4)));
+    assert_eq!(out, b"base");
+
+    let stats = cache.stats();
+    assert_eq!(stats.cache_hits, 1);
+    assert_eq!(stats.cache_misses, 1);
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.bytes, 4);
+}
+
+#[test]
+fn evicts_least_recently_used() {
+    let cache = cache(Limits {
+        bytes: 30,
+        max_entry: 30,
+        shards: 1,
+    });
+    let mut handle = cache.handle();
+
+    for offset in 0..3 {
+        handle.put(0, offset, &[offset as u8; 10], Kind::Blob, 10);
+    }
+    // Make 0 more recently used than 1
+    assert!(get(&mut handle, 0).is_some());
+    handle.put(0, 3, &[3; 10], Kind::Blob, 10);
+
+    assert_eq!(get(&mut handle, 1), None);
+    for offset in [0, 2, 3] {
+        assert_eq!(get(&mut handle, offset), Some(vec![offset as u8; 10]));
+    }
+
+    let stats = cache.stats();
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(stats.entries, 3);
+    assert_eq!(stats.bytes, 30);
+}
+
+#[test]
+fn byte_limits() {
+    let limits = Limits {
+        bytes: 64,
+        max_entry: 16,
+        shards: 8,
+    };
+    let cache = cache(limits);
+    let mut handle = cache.handle();
+
+    // Larger than `max_entry`
+    handle.put(0, 0, &[0; 17], Kind::Blob, 17);
+    assert_eq!(get(&mut handle, 0), None);
+    // Larger than the budget of a shard
+    handle.put(0, 1, &[0; 9], Kind::Blob, 9);
+    assert_eq!(get(&mut handle, 1), None);
+
+    for offset in 0..100 {
+        handle.put(0, offset, &[0; 8], Kind::Blob, 8);
+        assert!(cache.stats().bytes <= limits.bytes);
+    }
+    let stats = cache.stats();
+    assert!(stats.evictions > 0);
+    assert_eq!(stats.bytes, stats.entries as u64 * 8);
+}
+
+#[test]
+fn replacing_an_entry_does_not_leak_bytes() {
+    let cache = cache(Limits::default());
+    let mut handle = cache.handle();
+
+    handle.put(0, 0, &[0; 8], Kind::Blob, 8);
+    handle.put(0, 0, &[1; 4], Kind::Blob, 4);
+    assert_eq!(get(&mut handle, 0), Some(vec![1; 4]));
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.bytes, 4);
+}
+
+#[test]
+fn handles_do_not_share_entries() {
+    let cache = cache(Limits::default());
+    let mut a = cache.handle();
+    let mut b = cache.handle();
+    let mut a2 = a.clone();
+
+    a.put(7, 12, b"a", Kind::Blob, 1);
+    b.put(7, 12, b"b", Kind::Blob, 1);
+
+    assert_eq!(get_id(&mut a, 7, 12), Some(b"a".to_vec()));
+    assert_eq!(get_id(&mut a2, 7, 12), Some(b"a".to_vec()));
+    assert_eq!(get_id(&mut b, 7, 12), Some(b"b".to_vec()));
+    assert_eq!(get_id(&mut cache.handle(), 7, 12), None);
+
+    fn get_id(handle: &mut Handle<Stats>, pack_id: u32, offset: u64) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        handle.get(pack_id, offset, &mut out).map(|_| out)
+    }
+}
+
+#[test]
+fn shared_between_threads() {
+    const THREADS: u64 = 8;
+    const ENTRIES: u64 = 100;
+
+    let cache = cache(Limits::default());
+    let handle = cache.handle();
+    let threads = (0..THREADS)
+        .map(|t| {
+            let mut handle = handle.clone();
+            thread::spawn(move || {
+                for i in 0..ENTRIES {
+                    let offset = t * ENTRIES + i;
+                    handle.put(0, offset, &offset.to_be_bytes(), Kind::Blob, 8);
+                    assert_eq!(
+                        get(&mut handle, offset),
+                        Some(offset.to_be_bytes().to_vec())
+                    );
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let mut handle = handle;
+    for offset in 0..THREADS * ENTRIES {
+        assert_eq!(
+            get(&mut handle, offset),
+            Some(offset.to_be_bytes().to_vec())
+        );
+    }
+    let stats = cache.stats();
+    assert_eq!(stats.entries as u64, THREADS * ENTRIES);
+    assert_eq!(stats.cache_hits as u64, 2 * THREADS * ENTRIES);
+    assert_eq!(stats.cache_misses, 0);
+}
+
+#[test]
+fn odb_lookups() {
+    let repo = Repo::new();
+    let contents = (0..1000)
+        .map(|i| format!("line {}\n", i))
+        .collect::<String>();
+    repo.commit("file", &contents);
+    let new = format!("{}line 1000\n", contents);
+    repo.commit("file", &new);
+    repo.git(&["repack", "-q", "-a", "-d", "-f"]);
+    let odb = repo.odb();
+    let cache = cache(Limits::default());
+    let mut handle = cache.handle();
+
+    let mut buf = Vec::new();
+    for _ in 0..2 {
+        for (rev, expected) in [("HEAD:file", &new), ("HEAD~1:file", &contents)] {
+            let obj = odb
+                .find(repo.rev_parse(rev), &mut buf, &mut handle)
+                .unwrap()
+                .unwrap();
+            assert_eq!(obj.data, expected.as_bytes());
+        }
+    }
+    assert!(cache.stats().cache_hits > 0);
+}
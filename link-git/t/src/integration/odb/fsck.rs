@@ -0,0 +1,200 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fs, path::PathBuf};
+
+use link_git::{
+    hash::ObjectId,
+    odb::{
+        cache,
+        fsck::{error, Location, Options, Problem, Referrer},
+        pack,
+    },
+    refdb::Refdb,
+};
+
+use crate::integration::fixture::Repo;
+
+fn loose_path(repo: &Repo, id: &ObjectId) -> PathBuf {
+    let hex = id.to_string();
+    repo.objects_dir().join(&hex[..2]).join(&hex[2..])
+}
+
+#[test]
+fn ok() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    repo.git(&["repack", "-q", "-a", "-d"]);
+    repo.commit("b", "b");
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let snapshot = refdb.snapshot().unwrap();
+
+    let report = repo
+        .odb()
+        .verify(
+            Options {
+                connectivity: Some(&snapshot),
+                ..Options::default()
+            },
+            &mut cache::Never,
+        )
+        .unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.packs, 1);
+    assert_eq!(report.packed_objects, 3);
+    assert_eq!(report.loose_objects, 3);
+    assert_eq!(report.reachable, 6);
+}
+
+#[test]
+fn corrupted_loose_object() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    let a = repo.rev_parse("HEAD:a");
+    repo.commit("b", "b");
+    let b = repo.rev_parse("HEAD:b");
+
+    // Replace `a` with the (valid) contents of `b`
+    let path = loose_path(&repo, &a);
+    fs::remove_file(&path).unwrap();
+    fs::copy(loose_path(&repo, &b), &path).unwrap();
+
+    let report = repo
+        .odb()
+        .verify(Options::default(), &mut cache::Never)
+        .unwrap();
+    assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+    match &report.problems[0] {
+        Problem::Hash {
+            id,
+            actual,
+            location: Location::Loose { path: at },
+        } => {
+            assert_eq!(id, &a);
+            assert_eq!(actual, &b);
+            assert_eq!(at, &path);
+        },
+        other => panic!("unexpected problem: {:?}", other),
+    }
+}
+
+#[test]
+fn corrupted_pack() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    repo.git(&["repack", "-q", "-a", "-d"]);
+    let pack = fs::read_dir(repo.objects_dir().join("pack"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension() == Some("pack".as_ref()))
+        .unwrap();
+
+    // Flip a bit in the trailing checksum
+    let mut data = fs::read(&pack).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    fs::remove_file(&pack).unwrap();
+    fs::write(&pack, data).unwrap();
+
+    let report = repo
+        .odb()
+        .verify(
+            Options {
+                packed: false,
+                loose: false,
+                ..Options::default()
+            },
+            &mut cache::Never,
+        )
+        .unwrap();
+    assert!(
+        report
+            .problems
+            .iter()
+            .any(|p| matches!(p, Problem::PackChecksum { path, .. } if path == &pack)),
+        "{:?}",
+        report.problems
+    );
+}
+
+#[test]
+fn offset_out_of_bounds() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    repo.git(&["repack", "-q", "-a", "-d"]);
+    let idx = fs::read_dir(repo.objects_dir().join("pack"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension() == Some("idx".as_ref()))
+        .unwrap();
+
+    // Point the first object past the end of the pack. The offset table of a
+    // v2 index follows the header, fanout, ids and crcs.
+    let mut data = fs::read(&idx).unwrap();
+    let objects = u32::from_be_bytes(data[1028..1032].try_into().unwrap()) as usize;
+    let first = 8 + 256 * 4 + objects * (20 + 4);
+    data[first..first + 4].copy_from_slice(&0x7fff_0000u32.to_be_bytes());
+    fs::remove_file(&idx).unwrap();
+    fs::write(&idx, data).unwrap();
+
+    let report = repo
+        .odb()
+        .verify(
+            Options {
+                checksums: false,
+                loose: false,
+                ..Options::default()
+            },
+            &mut cache::Never,
+        )
+        .unwrap();
+    assert_eq!(report.packed_objects, objects);
+    assert!(
+        report.problems.iter().any(|p| matches!(
+            p,
+            Problem::Decode {
+                location: Location::Packed {
+                    ofs: 0x7fff_0000,
+                    ..
+                },
+                error: error::Decode::Entry(pack::error::Header::Malformed { .. }),
+                ..
+            }
+        )),
+        "{:?}",
+        report.problems
+    );
+}
+
+#[test]
+fn missing_object() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    let tree = repo.rev_parse("HEAD^{tree}");
+    let blob = repo.rev_parse("HEAD:a");
+    fs::remove_file(loose_path(&repo, &blob)).unwrap();
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let snapshot = refdb.snapshot().unwrap();
+
+    let report = repo
+        .odb()
+        .verify(
+            Options {
+                connectivity: Some(&snapshot),
+                ..Options::default()
+            },
+            &mut cache::Never,
+        )
+        .unwrap();
+    assert!(
+        report.problems.iter().any(|p| matches!(
+            p,
+            Problem::Missing { id, referrer: Referrer::Object(from) }
+                if id == &blob && from == &tree
+        )),
+        "{:?}",
+        report.problems
+    );
+}
@@ -0,0 +1,108 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use link_git::{
+    hash::{oid, ObjectId},
+    object::Kind,
+    odb::{pack, Header},
+};
+
+use crate::integration::fixture::{Repo, TestOdb};
+
+/// The header of `rev` as reported by `git cat-file`.
+fn expected(repo: &Repo, rev: &str) -> Header {
+    let kind = match repo.git(&["cat-file", "-t", rev]).as_str() {
+        "blob" => Kind::Blob,
+        "tree" => Kind::Tree,
+        "commit" => Kind::Commit,
+        "tag" => Kind::Tag,
+        other => panic!("unexpected object type {}", other),
+    };
+    let size = repo.git(&["cat-file", "-s", rev]).parse().unwrap();
+    Header { kind, size }
+}
+
+/// Two versions of a file large enough for git to store one as a delta of the
+/// other when packing.
+fn history(repo: &Repo) -> Vec<&'static str> {
+    let contents = (0..1000)
+        .map(|i| format!("line {}\n", i))
+        .collect::<String>();
+    repo.commit("file", &contents);
+    repo.git(&["tag", "-a", "-m", "v1", "v1"]);
+    repo.commit("file", &format!("{}line 1000\n", contents));
+    vec!["HEAD", "HEAD^{tree}", "HEAD:file", "HEAD~1:file", "v1"]
+}
+
+fn check(repo: &Repo, odb: &TestOdb, revs: &[&str]) {
+    for rev in revs {
+        let id = repo.rev_parse(rev);
+        assert_eq!(
+            odb.header(id).unwrap(),
+            Some(expected(repo, rev)),
+            "{}",
+            rev
+        );
+    }
+    assert_eq!(odb.header(ObjectId::null_sha1()).unwrap(), None);
+}
+
+#[test]
+fn loose() {
+    let repo = Repo::new();
+    let revs = history(&repo);
+    let odb = repo.odb();
+    check(&repo, &odb, &revs);
+}
+
+#[test]
+fn packed() {
+    let repo = Repo::new();
+    let revs = history(&repo);
+    repo.git(&["repack", "-q", "-a", "-d", "-f"]);
+    let odb = repo.odb();
+    assert!(!odb.packed.index.is_empty());
+    check(&repo, &odb, &revs);
+}
+
+/// A pack whose single entry is an ofs-delta pointing before the start of the
+/// pack, followed by a bogus trailer.
+fn malformed_pack(dir: &std::path::Path) -> pack::Data {
+    let mut data = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+    // ofs-delta of size 6, base distance 127
+    data.extend_from_slice(&[0x66, 0x7f]);
+    // Deflated delta: base size 3, result size 3, insert "abc"
+    data.extend_from_slice(&[120, 156, 99, 102, 102, 78, 76, 74, 6, 0, 2, 125, 1, 48]);
+    data.extend_from_slice(&[0; 20]);
+    std::fs::write(dir.join("pack-malformed.pack"), data).unwrap();
+    pack::Info::for_index(dir.join("pack-malformed.idx"))
+        .data()
+        .unwrap()
+}
+
+#[test]
+fn malformed_entries() {
+    let tmp = tempfile::tempdir().unwrap();
+    let data = malformed_pack(tmp.path());
+    let no_base = |_: &oid| None;
+
+    for ofs in [0, 11, 28, 1000] {
+        assert!(
+            matches!(
+                data.header(ofs, no_base),
+                Err(pack::error::Header::Malformed { .. })
+            ),
+            "{}",
+            ofs
+        );
+    }
+    assert!(matches!(
+        data.header(12, no_base),
+        Err(pack::error::Header::Malformed {
+            ofs: 12,
+            reason: "delta base offset out of bounds"
+        })
+    ));
+}
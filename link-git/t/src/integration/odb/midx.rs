@@ -0,0 +1,123 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use link_git::{
+    hash::ObjectId,
+    object::Kind,
+    odb::{
+        backend::{Loose, Packed},
+        cache,
+        index::{midx, MultiPack},
+        window,
+        Odb,
+    },
+};
+
+use crate::integration::fixture::Repo;
+
+/// A repository with two packs covered by a multi-pack-index, and a third
+/// pack which is not.
+struct Setup {
+    repo: Repo,
+    a: ObjectId,
+    b: ObjectId,
+    c: ObjectId,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let repo = Repo::new();
+        let a = repo.commit("a", "a");
+        repo.git(&["repack", "-q", "-d"]);
+        let b = repo.commit("b", "b");
+        repo.git(&["repack", "-q", "-d"]);
+        repo.git(&["multi-pack-index", "write", "--no-progress"]);
+        let c = repo.commit("c", "c");
+        repo.git(&["repack", "-q", "-d"]);
+
+        Self { repo, a, b, c }
+    }
+
+    fn odb(&self) -> Odb<MultiPack<()>, window::Small<()>> {
+        Odb {
+            loose: Loose::at(self.repo.objects_dir()).unwrap(),
+            packed: Packed {
+                index: MultiPack::open(self.repo.git_dir()).unwrap(),
+                data: window::Small::default(),
+            },
+        }
+    }
+}
+
+#[test]
+fn midx_file() {
+    let setup = Setup::new();
+    let midx = midx::File::open(setup.repo.objects_dir().join("pack"))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(midx.pack_names().len(), 2);
+    // Two commits, two trees, two blobs
+    assert_eq!(midx.num_objects(), 6);
+    assert_eq!(midx.iter().count(), 6);
+    assert!(midx.contains(setup.a));
+    assert!(midx.contains(setup.b));
+    assert!(!midx.contains(setup.c));
+
+    let ids = midx.iter().map(|(id, _)| id.to_owned()).collect::<Vec<_>>();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "not sorted");
+    for (id, entry) in midx.iter() {
+        assert_eq!(midx.lookup(id), Some(entry));
+        assert!((entry.pack_id as usize) < midx.pack_names().len());
+    }
+}
+
+#[test]
+fn lookup() {
+    let setup = Setup::new();
+    let odb = setup.odb();
+    assert!(odb.packed.index.has_midx());
+    assert_eq!(odb.packed.index.len(), 3);
+
+    let mut buf = Vec::new();
+    for id in [setup.a, setup.b, setup.c] {
+        assert!(odb.packed.contains(id), "{} not packed", id);
+        let obj = odb.find(id, &mut buf, &mut cache::Never).unwrap().unwrap();
+        assert_eq!(obj.kind, Kind::Commit);
+    }
+    let tree = setup.repo.rev_parse("HEAD^{tree}");
+    let obj = odb
+        .find(tree, &mut buf, &mut cache::Never)
+        .unwrap()
+        .unwrap();
+    assert_eq!(obj.kind, Kind::Tree);
+
+    assert!(odb
+        .find(ObjectId::null_sha1(), &mut buf, &mut cache::Never)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn reload() {
+    let setup = Setup::new();
+    let odb = setup.odb();
+
+    // Fold all packs into one, covered by a new multi-pack-index
+    let d = setup.repo.commit("d", "d");
+    setup.repo.git(&["repack", "-q", "-a", "-d"]);
+    setup
+        .repo
+        .git(&["multi-pack-index", "write", "--no-progress"]);
+
+    // Not found triggers a reload
+    assert!(odb.contains(d));
+    assert!(odb.packed.index.has_midx());
+    assert_eq!(odb.packed.index.len(), 1);
+    let mut buf = Vec::new();
+    for id in [setup.a, setup.b, setup.c, d] {
+        assert!(odb.find(id, &mut buf, &mut cache::Never).unwrap().is_some());
+    }
+}
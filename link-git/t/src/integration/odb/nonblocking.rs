@@ -0,0 +1,149 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::sync::Arc;
+
+use futures::{executor::block_on, future, StreamExt as _, TryStreamExt as _};
+use link_git::{
+    hash::ObjectId,
+    object::Kind,
+    odb::{
+        cache::Never,
+        index::Shared,
+        nonblocking::{Chunked, Odb},
+        window,
+    },
+};
+
+use crate::integration::fixture::Repo;
+
+type TestOdb = Odb<Shared<()>, window::Small<()>, fn() -> Never>;
+
+fn odb(repo: &Repo, max_concurrency: usize) -> TestOdb {
+    Odb::new(Arc::new(repo.odb()), max_concurrency, || Never)
+}
+
+/// Contents which don't compress into a single chunk of inflated data.
+fn large(seed: usize) -> String {
+    (0..100_000)
+        .map(|i| format!("{:x}\n", (i * 7919 + seed) % 65_521))
+        .collect()
+}
+
+fn collect(chunked: Chunked, chunk_size: usize) -> Vec<u8> {
+    block_on(chunked.try_fold(Vec::new(), |mut data, chunk| {
+        assert!(!chunk.is_empty() && chunk.len() <= chunk_size);
+        data.extend_from_slice(&chunk);
+        future::ready(Ok(data))
+    }))
+    .unwrap()
+}
+
+fn check_chunked(odb: &TestOdb, id: ObjectId, expected: &str) {
+    let chunked = block_on(odb.find_chunked(id, 4096)).unwrap().unwrap();
+    assert_eq!(chunked.id, id);
+    assert_eq!(chunked.header.kind, Kind::Blob);
+    assert_eq!(chunked.header.size, expected.len() as u64);
+    assert_eq!(collect(chunked, 4096), expected.as_bytes());
+    assert_eq!(
+        block_on(odb.find(id)).unwrap().unwrap().data,
+        expected.as_bytes()
+    );
+}
+
+#[test]
+fn find() {
+    let repo = Repo::new();
+    let head = repo.commit("file", "hello\n");
+    let odb = odb(&repo, 2);
+
+    let obj = block_on(odb.find(head)).unwrap().unwrap();
+    assert_eq!(obj.id, head);
+    assert_eq!(obj.kind, Kind::Commit);
+    assert!(block_on(odb.contains(head)));
+    assert_eq!(block_on(odb.find(ObjectId::null_sha1())).unwrap(), None);
+    assert!(block_on(odb.find_chunked(ObjectId::null_sha1(), 1))
+        .unwrap()
+        .is_none());
+
+    let many = block_on(odb.find_many(vec![head, ObjectId::null_sha1(), head])).unwrap();
+    assert_eq!(many.len(), 3);
+    assert_eq!(many[0].as_ref().map(|obj| obj.id), Some(head));
+    assert_eq!(many[1], None);
+    assert_eq!(many[0], many[2]);
+}
+
+#[test]
+fn find_stream() {
+    let repo = Repo::new();
+    let ids = (0..10)
+        .map(|i| repo.commit("file", &format!("{}\n", i)))
+        .collect::<Vec<_>>();
+    let odb = odb(&repo, 1);
+
+    let found = block_on(
+        odb.find_stream(ids.clone(), 2)
+            .map(|res| res.unwrap().1.unwrap().id)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(found, ids);
+}
+
+#[test]
+fn chunked_loose() {
+    let repo = Repo::new();
+    let contents = large(0);
+    repo.commit("file", &contents);
+    let odb = odb(&repo, 1);
+
+    check_chunked(&odb, repo.rev_parse("HEAD:file"), &contents);
+}
+
+#[test]
+fn chunked_packed() {
+    let repo = Repo::new();
+    let base = large(0);
+    repo.commit("file", &base);
+    let delta = format!("{}more\n", base);
+    repo.commit("file", &delta);
+    repo.git(&["repack", "-q", "-a", "-d", "-f"]);
+    let odb = odb(&repo, 1);
+    assert!(!odb.get_ref().packed.index.is_empty());
+
+    // One of these is deltified against the other
+    check_chunked(&odb, repo.rev_parse("HEAD~1:file"), &base);
+    check_chunked(&odb, repo.rev_parse("HEAD:file"), &delta);
+}
+
+#[test]
+fn chunked_does_not_hold_concurrency_slot() {
+    let repo = Repo::new();
+    let contents = large(1);
+    let head = repo.commit("file", &contents);
+    let odb = odb(&repo, 1);
+
+    let mut chunked = block_on(odb.find_chunked(repo.rev_parse("HEAD:file"), 1024))
+        .unwrap()
+        .unwrap();
+    assert!(block_on(chunked.next()).unwrap().is_ok());
+    // The stream is idle, so this must not wait for it to be consumed
+    assert!(block_on(odb.find(head)).unwrap().is_some());
+    let rest = collect(chunked, 1024);
+    assert_eq!(rest.len(), contents.len() - 1024);
+}
+
+#[test]
+fn concurrent_lookups() {
+    let repo = Repo::new();
+    let ids = (0..20)
+        .map(|i| repo.commit("file", &format!("{}\n", i)))
+        .collect::<Vec<_>>();
+    let odb = odb(&repo, 2);
+
+    let found = block_on(future::join_all(ids.iter().map(|id| odb.find(*id))));
+    for (id, obj) in ids.iter().zip(found) {
+        assert_eq!(obj.unwrap().unwrap().id, *id);
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fs, sync::Arc};
+
+use link_git::{
+    object::Kind,
+    odb::{
+        cache::DecodeEntry as _,
+        decode::{Limits, Sharded},
+        openmetrics::Registry,
+        pack,
+        window,
+    },
+};
+
+use crate::integration::fixture::Repo;
+
+const INDEX: &str = "\
+# TYPE link_git_odb_index_hits counter
+# HELP link_git_odb_index_hits Number of successful object lookups.
+link_git_odb_index_hits_total{repo=\"b\\\"\\n\"} 0
+# TYPE link_git_odb_index_misses counter
+# HELP link_git_odb_index_misses Number of unsuccessful object lookups.
+link_git_odb_index_misses_total{repo=\"b\\\"\\n\"} 0
+# TYPE link_git_odb_index_midx_hits counter
+# HELP link_git_odb_index_midx_hits Number of lookups answered by a multi-pack-index.
+link_git_odb_index_midx_hits_total{repo=\"b\\\"\\n\"} 0
+# TYPE link_git_odb_index_pushes counter
+# HELP link_git_odb_index_pushes Number of pack indices added explicitly.
+link_git_odb_index_pushes_total{repo=\"b\\\"\\n\"} 0
+# TYPE link_git_odb_index_reloads counter
+# HELP link_git_odb_index_reloads Number of reloads of the packs directory.
+link_git_odb_index_reloads_total{repo=\"b\\\"\\n\"} 0
+# TYPE link_git_odb_index_auto_reloads counter
+# HELP link_git_odb_index_auto_reloads Number of automatic reloads of the packs directory.
+link_git_odb_index_auto_reloads_total{repo=\"b\\\"\\n\"} 0
+# TYPE link_git_odb_index_indices gauge
+# HELP link_git_odb_index_indices Number of pack indices held.
+link_git_odb_index_indices{repo=\"b\\\"\\n\"} 0
+";
+
+const WINDOW_COUNTERS: &str = "\
+# TYPE link_git_odb_window_hits counter
+# HELP link_git_odb_window_hits Number of times pack data was found in the cache.
+link_git_odb_window_hits_total{repo=\"a\"} 1
+# TYPE link_git_odb_window_misses counter
+# HELP link_git_odb_window_misses Number of times pack data was not found in the cache.
+link_git_odb_window_misses_total{repo=\"a\"} 1
+# TYPE link_git_odb_window_loads counter
+# HELP link_git_odb_window_loads Number of attempts to load pack data from disk.
+link_git_odb_window_loads_total{repo=\"a\"} 1
+# TYPE link_git_odb_window_evictions counter
+# HELP link_git_odb_window_evictions Number of pack files evicted from the cache.
+link_git_odb_window_evictions_total{repo=\"a\"} 0
+";
+
+const DECODE: &str = "\
+# TYPE link_git_odb_decode_hits counter
+# HELP link_git_odb_decode_hits Number of times a delta base was found in the cache.
+link_git_odb_decode_hits_total{repo=\"a\"} 1
+# TYPE link_git_odb_decode_misses counter
+# HELP link_git_odb_decode_misses Number of times a delta base was not found in the cache.
+link_git_odb_decode_misses_total{repo=\"a\"} 0
+# TYPE link_git_odb_decode_evictions counter
+# HELP link_git_odb_decode_evictions Number of entries evicted from the cache.
+link_git_odb_decode_evictions_total{repo=\"a\"} 0
+# TYPE link_git_odb_decode_entries gauge
+# HELP link_git_odb_decode_entries Number of entries held by the cache.
+link_git_odb_decode_entries{repo=\"a\"} 1
+# TYPE link_git_odb_decode_bytes gauge
+# HELP link_git_odb_decode_bytes Total size in bytes of the entries held by the cache.
+link_git_odb_decode_bytes{repo=\"a\"} 4
+# EOF
+";
+
+#[test]
+fn golden() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    repo.git(&["repack", "-q", "-a", "-d"]);
+    let idx = fs::read_dir(repo.objects_dir().join("pack"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension() == Some("idx".as_ref()))
+        .unwrap();
+    let info = pack::Info::for_index(idx);
+
+    let registry = Registry::new();
+    registry.index("b\"\n");
+    let decode = Arc::new(Sharded::new(Limits::default()).with_metrics(registry.decode("a")));
+    let mut handle = decode.handle();
+    handle.put(0, 12, b"base", Kind::Blob, 4);
+    handle.get(0, 12, &mut Vec::new()).unwrap();
+    // Decode cache gauges are only observed when taking its `stats()`
+    decode.stats();
+    let cache = Arc::new(window::Lru::default().with_metrics(registry.window("a")));
+    registry.observe_window("a", &cache);
+    cache.get(&info).unwrap();
+    cache.get(&info).unwrap();
+
+    // The gauges are read from the cache, even though its `stats()` were
+    // never taken
+    let window_gauges = format!(
+        "\
+# TYPE link_git_odb_window_open_files gauge
+# HELP link_git_odb_window_open_files Number of pack files held by the cache.
+link_git_odb_window_open_files{{repo=\"a\"}} 1
+# TYPE link_git_odb_window_bytes gauge
+# HELP link_git_odb_window_bytes Total size in bytes of the pack files held by the cache.
+link_git_odb_window_bytes{{repo=\"a\"}} {}
+",
+        cache.bytes()
+    );
+    assert!(cache.bytes() > 0);
+    assert_eq!(
+        registry.render(),
+        [INDEX, WINDOW_COUNTERS, &window_gauges, DECODE].concat()
+    );
+
+    // Once the cache is gone, its size is no longer exported
+    drop(cache);
+    let window_gauges = "\
+# TYPE link_git_odb_window_open_files gauge
+# HELP link_git_odb_window_open_files Number of pack files held by the cache.
+link_git_odb_window_open_files{repo=\"a\"} 0
+# TYPE link_git_odb_window_bytes gauge
+# HELP link_git_odb_window_bytes Total size in bytes of the pack files held by the cache.
+";
+    assert_eq!(
+        registry.render(),
+        [INDEX, WINDOW_COUNTERS, window_gauges, DECODE].concat()
+    );
+}
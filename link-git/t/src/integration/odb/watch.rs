@@ -0,0 +1,88 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fs, sync::Arc, time::Duration};
+
+use link_git::{
+    hash::ObjectId,
+    odb::index::{watch::Mode, Index as _, Shared, Stats},
+};
+
+use crate::integration::fixture::{eventually, Repo};
+
+const POLL: Duration = Duration::from_millis(50);
+
+/// `true` if a currently loaded pack contains `id`.
+///
+/// Unlike `Index::contains`, this does not trigger a reload.
+fn loaded(index: &Shared<Stats>, id: &ObjectId) -> bool {
+    index.packs().unwrap().iter().any(|idx| idx.contains(id))
+}
+
+fn watched(mode: Mode) {
+    let repo = Repo::new();
+    let first = repo.commit("a", "a");
+    let index = Arc::new(Shared::open(repo.git_dir()).unwrap().with_stats());
+    assert!(index.is_empty());
+
+    let _watcher = index.watch(mode).unwrap();
+
+    repo.git(&["repack", "-q"]);
+    assert!(eventually(|| loaded(&index, &first)));
+    assert_eq!(index.len(), 1);
+
+    let second = repo.commit("b", "b");
+    repo.git(&["repack", "-q", "-a", "-d"]);
+    assert!(eventually(|| index.len() == 1 && loaded(&index, &second)));
+    assert!(loaded(&index, &first));
+
+    let stats = index.stats();
+    assert_eq!(stats.reloads, 0);
+    assert!(stats.auto_reloads > 0);
+}
+
+#[test]
+fn poll() {
+    watched(Mode::Poll(POLL))
+}
+
+#[test]
+fn auto() {
+    watched(Mode::Auto(POLL))
+}
+
+#[test]
+fn drops_index_without_pack() {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    repo.git(&["repack", "-q"]);
+    let index = Arc::new(Shared::open(repo.git_dir()).unwrap().with_stats());
+    assert_eq!(index.len(), 1);
+
+    let _watcher = index.watch(Mode::Poll(POLL)).unwrap();
+
+    let pack = fs::read_dir(repo.objects_dir().join("pack"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().unwrap_or_default() == "pack")
+        .unwrap();
+    fs::remove_file(&pack).unwrap();
+    assert!(eventually(|| index.is_empty()));
+    assert!(pack.with_extension("idx").is_file());
+}
+
+#[test]
+fn does_not_keep_index_alive() {
+    let repo = Repo::new();
+    let index = Arc::new(Shared::open(repo.git_dir()).unwrap());
+    let _watcher = index.watch(Mode::Poll(POLL)).unwrap();
+    let weak = Arc::downgrade(&index);
+    drop(index);
+    assert!(weak.upgrade().is_none());
+
+    // Events after the index is gone are ignored
+    repo.commit("a", "a");
+    repo.git(&["repack", "-q"]);
+}
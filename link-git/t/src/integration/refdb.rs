@@ -0,0 +1,38 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use link_git::{
+    hash::ObjectId,
+    refdb::{Edit, Expected, New},
+    refs::FullName,
+};
+
+mod glob;
+mod namespace;
+mod pack;
+mod reflog;
+mod update;
+mod watch;
+
+fn name(s: &str) -> FullName {
+    s.try_into().unwrap()
+}
+
+fn edit(name_: &str, expected: Expected, new: New) -> Edit {
+    Edit {
+        name: name(name_),
+        expected,
+        new,
+        reflog_message: "test".into(),
+    }
+}
+
+fn set(name: &str, expected: Expected, id: ObjectId) -> Edit {
+    edit(name, expected, New::Direct(id))
+}
+
+fn delete(name: &str, expected: Expected) -> Edit {
+    edit(name, expected, New::Delete)
+}
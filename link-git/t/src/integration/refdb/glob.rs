@@ -0,0 +1,93 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_ref_format::refspec::{pattern, PatternString};
+use link_git::refdb::Refdb;
+
+use super::name;
+use crate::integration::fixture::Repo;
+
+#[test]
+fn iter_matching() {
+    let repo = Repo::new();
+    let head = repo.commit("a", "a").to_string();
+    for r in [
+        "refs/remotes/a/heads/main",
+        "refs/remotes/a/b/heads/main",
+        "refs/remotes/a/heads/next",
+        "refs/remotesx/a/heads/main",
+        "refs/tags/v1",
+    ] {
+        repo.git(&["update-ref", r, &head]);
+    }
+    // Both packed and loose refs are matched
+    repo.git(&["pack-refs", "--all"]);
+    repo.git(&["update-ref", "refs/remotes/c/heads/main", &head]);
+
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let snapshot = refdb.snapshot().unwrap();
+    let matching = |pat: PatternString| {
+        let mut matches = snapshot
+            .iter_matching(&pat)
+            .unwrap()
+            .map(|m| {
+                let m = m.unwrap();
+                (
+                    m.reference.name.as_bstr().to_string(),
+                    m.capture.map(|c| c.to_string()),
+                )
+            })
+            .collect::<Vec<_>>();
+        matches.sort();
+        matches
+    };
+    let m = |name: &str, capture: Option<&str>| (name.to_owned(), capture.map(ToOwned::to_owned));
+
+    assert_eq!(
+        matching(pattern!("refs/remotes/*/heads/main")),
+        vec![
+            m("refs/remotes/a/b/heads/main", Some("a/b")),
+            m("refs/remotes/a/heads/main", Some("a")),
+            m("refs/remotes/c/heads/main", Some("c")),
+        ]
+    );
+    assert_eq!(
+        matching(pattern!("refs/remotes/a/heads/*")),
+        vec![
+            m("refs/remotes/a/heads/main", Some("main")),
+            m("refs/remotes/a/heads/next", Some("next")),
+        ]
+    );
+    assert_eq!(
+        matching(pattern!("refs/remotes/a/heads/ma*")),
+        vec![m("refs/remotes/a/heads/main", Some("in"))]
+    );
+    assert_eq!(
+        matching(pattern!("refs/tags/v1")),
+        vec![m("refs/tags/v1", None)]
+    );
+    assert!(matching(pattern!("refs/tags/v2")).is_empty());
+}
+
+#[test]
+fn iter_matching_packed() {
+    let repo = Repo::new();
+    let head = repo.commit("a", "a").to_string();
+    repo.git(&["update-ref", "refs/remotes/a/heads/main", &head]);
+    // Removes `refs/remotes/a/heads`, and `refs/remotes/a` along with it
+    repo.git(&["pack-refs", "--all"]);
+
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let snapshot = refdb.snapshot().unwrap();
+    let pat = pattern!("refs/remotes/a/heads/*");
+    let matches = snapshot
+        .iter_matching(&pat)
+        .unwrap()
+        .map(|m| m.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].reference.name, name("refs/remotes/a/heads/main"));
+    assert_eq!(matches[0].capture, Some("main".into()));
+}
@@ -0,0 +1,207 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_ref_format::{name::component, Qualified, RefString};
+use link_git::{
+    hash::ObjectId,
+    refdb::{namespace::error, Expected, New, Outcome, Refdb},
+    refs::Target,
+};
+
+use super::{edit, name, set};
+use crate::integration::fixture::{committer, Repo};
+
+fn qualified(s: &str) -> Qualified<'static> {
+    RefString::try_from(s).unwrap().into_qualified().unwrap()
+}
+
+/// `refs/heads/main` in the root namespace, in namespace `a`, and in the
+/// nested namespace `a/b`, each pointing to a different commit.
+struct Setup {
+    repo: Repo,
+    refdb: Refdb,
+    root: ObjectId,
+    a: ObjectId,
+    ab: ObjectId,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let repo = Repo::new();
+        let root = repo.commit("root", "root");
+        let a = repo.commit("a", "a");
+        let ab = repo.commit("ab", "ab");
+        for (name, id) in [
+            ("refs/heads/main", root),
+            ("refs/namespaces/a/refs/heads/main", a),
+            ("refs/namespaces/a/refs/namespaces/b/refs/heads/main", ab),
+        ] {
+            repo.git(&["update-ref", name, &id.to_string()]);
+        }
+        let refdb = Refdb::open(repo.git_dir()).unwrap();
+
+        Self {
+            repo,
+            refdb,
+            root,
+            a,
+            ab,
+        }
+    }
+}
+
+#[test]
+fn find() {
+    let setup = Setup::new();
+    let snapshot = setup.refdb.snapshot().unwrap();
+    let main = qualified("refs/heads/main");
+
+    let a = snapshot.namespaced(component!("a"));
+    assert_eq!(a.prefix().as_str(), "refs/namespaces/a");
+    let r = a.find(&main).unwrap().unwrap();
+    assert_eq!(r.name, name("refs/heads/main"));
+    assert_eq!(r.target, Target::Peeled(setup.a));
+
+    let ab = a.namespaced(component!("b"));
+    assert_eq!(ab.prefix().as_str(), "refs/namespaces/a/refs/namespaces/b");
+    assert_eq!(
+        ab.find(&main).unwrap().unwrap().target,
+        Target::Peeled(setup.ab)
+    );
+
+    assert!(snapshot
+        .namespaced(component!("c"))
+        .find(&main)
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        snapshot.find("refs/heads/main").unwrap().unwrap().target,
+        Target::Peeled(setup.root)
+    );
+}
+
+#[test]
+fn iter() {
+    let setup = Setup::new();
+    // A sibling namespace whose name has `a` as a prefix
+    setup.repo.git(&[
+        "update-ref",
+        "refs/namespaces/ab/refs/heads/main",
+        &setup.root.to_string(),
+    ]);
+    let snapshot = setup.refdb.snapshot().unwrap();
+    let a = snapshot.namespaced(component!("a"));
+
+    let names = |prefix: Option<&str>| {
+        let mut names = a
+            .iter(prefix)
+            .unwrap()
+            .map(|r| r.unwrap().name.as_bstr().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    assert_eq!(
+        names(None),
+        vec!["refs/heads/main", "refs/namespaces/b/refs/heads/main"]
+    );
+    assert_eq!(names(Some("refs/heads")), vec!["refs/heads/main"]);
+    assert!(names(Some("refs/tags")).is_empty());
+}
+
+#[test]
+fn iter_packed() {
+    let repo = Repo::new();
+    let head = repo.commit("a", "a").to_string();
+    repo.git(&[
+        "update-ref",
+        "refs/namespaces/a/refs/namespaces/b/refs/heads/main",
+        &head,
+    ]);
+    // Removes the loose refs directories of the namespaces
+    repo.git(&["pack-refs", "--all"]);
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let snapshot = refdb.snapshot().unwrap();
+    let ab = snapshot
+        .namespaced(component!("a"))
+        .namespaced(component!("b"));
+
+    let names = ab
+        .iter(Some("refs/heads"))
+        .unwrap()
+        .map(|r| r.unwrap().name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![name("refs/heads/main")]);
+    assert_eq!(ab.iter(None::<&str>).unwrap().count(), 1);
+}
+
+#[test]
+fn update() {
+    let setup = Setup::new();
+    let a = setup.refdb.snapshot().unwrap().namespaced(component!("a"));
+
+    let outcomes = a
+        .update(
+            vec![
+                set("refs/heads/next", Expected::Absent, setup.root),
+                edit(
+                    "refs/heads/sym",
+                    Expected::Absent,
+                    New::Symbolic(name("refs/heads/main")),
+                ),
+            ],
+            &committer(),
+        )
+        .unwrap();
+    assert_eq!(
+        outcomes,
+        vec![
+            Outcome::Created {
+                name: name("refs/heads/next"),
+                new: Target::Peeled(setup.root),
+            },
+            Outcome::Created {
+                name: name("refs/heads/sym"),
+                new: Target::Symbolic(name("refs/heads/main")),
+            },
+        ]
+    );
+
+    let snapshot = setup.refdb.snapshot().unwrap();
+    assert_eq!(
+        snapshot
+            .find("refs/namespaces/a/refs/heads/next")
+            .unwrap()
+            .unwrap()
+            .target,
+        Target::Peeled(setup.root)
+    );
+    assert_eq!(
+        snapshot
+            .find("refs/namespaces/a/refs/heads/sym")
+            .unwrap()
+            .unwrap()
+            .target,
+        Target::Symbolic(name("refs/namespaces/a/refs/heads/main"))
+    );
+    assert!(snapshot.find("refs/heads/next").unwrap().is_none());
+
+    let a = snapshot.namespaced(component!("a"));
+    let sym = a.find(&qualified("refs/heads/sym")).unwrap().unwrap();
+    assert_eq!(sym.target, Target::Symbolic(name("refs/heads/main")));
+    let main = a.follow(&sym).unwrap();
+    assert_eq!(main.name, name("refs/heads/main"));
+    assert_eq!(main.target, Target::Peeled(setup.a));
+}
+
+#[test]
+fn update_unqualified() {
+    let setup = Setup::new();
+    let a = setup.refdb.snapshot().unwrap().namespaced(component!("a"));
+    assert!(matches!(
+        a.update(Some(set("HEAD", Expected::Any, setup.root)), &committer()),
+        Err(error::Update::Name(error::Name::Unqualified(_)))
+    ));
+}
@@ -0,0 +1,125 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::fs;
+
+use link_git::{
+    odb::cache,
+    refdb::{Packing, Refdb},
+};
+
+use crate::integration::fixture::Repo;
+
+/// A repository with a branch in a nested directory, an annotated tag and a
+/// symbolic ref, all loose.
+fn setup() -> Repo {
+    let repo = Repo::new();
+    repo.commit("a", "a");
+    repo.branch("nested/topic", "main");
+    repo.git(&["tag", "-a", "-m", "v1", "v1"]);
+    repo.git(&["symbolic-ref", "refs/heads/sym", "refs/heads/main"]);
+    repo
+}
+
+fn pack(repo: &Repo, prefix: Option<&str>) -> Packing {
+    Refdb::open(repo.git_dir())
+        .unwrap()
+        .pack_refs(prefix, &repo.odb(), &mut cache::Never)
+        .unwrap()
+}
+
+fn packed_refs(repo: &Repo) -> String {
+    fs::read_to_string(repo.git_dir().join("packed-refs")).unwrap()
+}
+
+#[test]
+fn packs_and_prunes() {
+    let repo = setup();
+    let before = repo.git(&["for-each-ref"]);
+
+    assert_eq!(
+        pack(&repo, None),
+        Packing {
+            packed: 3,
+            pruned: 3
+        }
+    );
+    assert_eq!(repo.git(&["for-each-ref"]), before);
+
+    let refs = repo.git_dir().join("refs");
+    assert!(!refs.join("heads").join("main").exists());
+    assert!(!refs.join("heads").join("nested").exists());
+    assert!(refs.join("heads").exists());
+    assert!(!refs.join("tags").join("v1").exists());
+    assert!(refs.join("heads").join("sym").exists(), "symref was packed");
+    assert!(!packed_refs(&repo).contains("refs/heads/sym"));
+    repo.git(&["fsck", "--no-progress"]);
+}
+
+#[test]
+fn peels_annotated_tags() {
+    let repo = setup();
+    let commit = repo.rev_parse("main");
+    let tag = repo.rev_parse("refs/tags/v1");
+    pack(&repo, None);
+
+    let packed = packed_refs(&repo);
+    assert!(packed.starts_with("# pack-refs with: peeled fully-peeled sorted"));
+    assert!(
+        packed.contains(&format!("{} refs/tags/v1\n^{}\n", tag, commit)),
+        "{}",
+        packed
+    );
+    assert_eq!(repo.rev_parse("v1^{}"), commit);
+}
+
+#[test]
+fn peels_previously_packed() {
+    let repo = setup();
+    let commit = repo.rev_parse("main");
+    let tag = repo.rev_parse("refs/tags/v1");
+    fs::write(
+        repo.git_dir().join("packed-refs"),
+        format!("# pack-refs with: sorted \n{} refs/tags/v1\n", tag),
+    )
+    .unwrap();
+    fs::remove_file(repo.git_dir().join("refs").join("tags").join("v1")).unwrap();
+
+    pack(&repo, Some("refs/heads"));
+    assert!(packed_refs(&repo).contains(&format!("{} refs/tags/v1\n^{}\n", tag, commit)));
+}
+
+#[test]
+fn prefix() {
+    let repo = setup();
+    assert_eq!(
+        pack(&repo, Some("refs/heads/nested")),
+        Packing {
+            packed: 1,
+            pruned: 1
+        }
+    );
+    let refs = repo.git_dir().join("refs");
+    assert!(refs.join("heads").join("main").exists());
+    assert!(refs.join("tags").join("v1").exists());
+    assert!(packed_refs(&repo).contains("refs/heads/nested/topic"));
+}
+
+#[test]
+fn skips_locked_refs() {
+    let repo = setup();
+    let main = repo.git_dir().join("refs").join("heads").join("main");
+    fs::write(main.with_extension("lock"), b"").unwrap();
+
+    assert_eq!(
+        pack(&repo, None),
+        Packing {
+            packed: 2,
+            pruned: 2
+        }
+    );
+    assert!(main.exists());
+    assert!(!packed_refs(&repo).contains("refs/heads/main"));
+}
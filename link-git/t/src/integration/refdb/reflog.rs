@@ -0,0 +1,197 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    fs,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use link_git::{
+    actor::Signature,
+    hash::ObjectId,
+    refdb::{reflog::Expire, Expected, Refdb},
+};
+
+use super::{delete, name, set};
+use crate::integration::fixture::{committer, Repo};
+
+const REF: &str = "refs/heads/topic";
+
+/// The fixture committer, `secs` seconds later.
+fn later(secs: u32) -> Signature {
+    let mut sig = committer();
+    sig.time.time += secs;
+    sig
+}
+
+/// A repository where `REF` was set to three different commits, ten seconds
+/// apart.
+fn setup() -> (Repo, Refdb, [ObjectId; 3]) {
+    let repo = Repo::new();
+    let ids = [
+        repo.commit("a", "a"),
+        repo.commit("b", "b"),
+        repo.commit("c", "c"),
+    ];
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    for (i, id) in ids.iter().enumerate() {
+        refdb
+            .update(Some(set(REF, Expected::Any, *id)), &later(i as u32 * 10))
+            .unwrap();
+    }
+    (repo, refdb, ids)
+}
+
+fn entries(refdb: &Refdb) -> Vec<(ObjectId, ObjectId)> {
+    refdb
+        .snapshot()
+        .unwrap()
+        .reflog(&name(REF))
+        .unwrap()
+        .map(|log| {
+            log.iter()
+                .map(|e| e.map(|e| (e.previous, e.new)).unwrap())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn read() {
+    let (_repo, refdb, [a, b, c]) = setup();
+    let log = refdb
+        .snapshot()
+        .unwrap()
+        .reflog(&name(REF))
+        .unwrap()
+        .unwrap();
+    assert_eq!(log.name(), &name(REF));
+
+    let entries = log.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|e| (e.previous, e.new))
+            .collect::<Vec<_>>(),
+        vec![(ObjectId::null_sha1(), a), (a, b), (b, c)]
+    );
+    assert!(entries.iter().all(|e| e.message == "test"));
+    assert_eq!(entries[0].committer, committer());
+    assert_eq!(entries[2].committer, later(20));
+    assert_eq!(
+        entries[2].time(),
+        UNIX_EPOCH + Duration::from_secs(1_640_000_020)
+    );
+    assert_eq!(log.iter().next_back().unwrap().unwrap(), entries[2]);
+}
+
+#[test]
+fn absent() {
+    let (_repo, refdb, _) = setup();
+    assert!(refdb
+        .snapshot()
+        .unwrap()
+        .reflog(&name("refs/heads/nope"))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn expire_older_than() {
+    let (_repo, refdb, [_, b, c]) = setup();
+    let policy = Expire {
+        older_than: Some(UNIX_EPOCH + Duration::from_secs(1_640_000_015)),
+        ..Expire::default()
+    };
+    assert_eq!(refdb.expire_reflog(&name(REF), policy).unwrap(), 2);
+    assert_eq!(entries(&refdb), vec![(b, c)]);
+}
+
+#[test]
+fn expire_keep_latest() {
+    let (_repo, refdb, [a, b, c]) = setup();
+    let policy = Expire {
+        keep_latest: Some(2),
+        ..Expire::default()
+    };
+    assert_eq!(refdb.expire_reflog(&name(REF), policy).unwrap(), 1);
+    assert_eq!(entries(&refdb), vec![(a, b), (b, c)]);
+    assert_eq!(refdb.expire_reflog(&name(REF), policy).unwrap(), 0);
+}
+
+#[test]
+fn expire_all_refs() {
+    let (repo, refdb, _) = setup();
+    let policy = Expire {
+        keep_latest: Some(1),
+        ..Expire::default()
+    };
+    let count = |rev: &str| repo.git(&["reflog", "show", rev]).lines().count();
+    let before = count("HEAD") + count("main") + count(REF);
+    assert_eq!(refdb.expire_reflogs(policy).unwrap(), before - 3);
+    for rev in ["HEAD", "main", REF] {
+        assert_eq!(count(rev), 1, "{}", rev);
+    }
+}
+
+#[test]
+fn expire_drops_malformed_entries() {
+    let (repo, refdb, [a, b, c]) = setup();
+    let path = repo.git_dir().join("logs").join(REF);
+    let mut log = fs::read(&path).unwrap();
+    log.extend_from_slice(b"garbage\n");
+    fs::write(&path, log).unwrap();
+
+    let policy = Expire {
+        keep_latest: Some(2),
+        ..Expire::default()
+    };
+    assert_eq!(refdb.expire_reflog(&name(REF), policy).unwrap(), 2);
+    assert_eq!(entries(&refdb), vec![(a, b), (b, c)]);
+}
+
+#[test]
+fn expire_all_refs_skips_failures() {
+    let (repo, refdb, _) = setup();
+    // A stale lock prevents expiring the reflog of `REF`
+    let lock = repo.git_dir().join(format!("{}.lock", REF));
+    fs::write(&lock, b"").unwrap();
+
+    let policy = Expire {
+        keep_latest: Some(1),
+        ..Expire::default()
+    };
+    let count = |rev: &str| repo.git(&["reflog", "show", rev]).lines().count();
+    let before = count("HEAD") + count("main");
+    assert_eq!(refdb.expire_reflogs(policy).unwrap(), before - 2);
+    assert_eq!(count("HEAD"), 1);
+    assert_eq!(count("main"), 1);
+    assert_eq!(count(REF), 3);
+    fs::remove_file(lock).unwrap();
+}
+
+#[test]
+fn deleted_with_ref() {
+    let (repo, refdb, [a, ..]) = setup();
+    let nested = "refs/heads/nested/topic";
+    refdb
+        .update(Some(set(nested, Expected::Absent, a)), &committer())
+        .unwrap();
+    let logs = repo.git_dir().join("logs").join("refs").join("heads");
+    assert!(logs.join("nested").join("topic").is_file());
+
+    refdb
+        .update(Some(delete(nested, Expected::Present)), &committer())
+        .unwrap();
+    assert!(!logs.join("nested").exists());
+    // A leftover directory would prevent this
+    refdb
+        .update(
+            Some(set("refs/heads/nested", Expected::Absent, a)),
+            &committer(),
+        )
+        .unwrap();
+    assert!(logs.join("nested").is_file());
+}
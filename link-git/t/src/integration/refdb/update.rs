@@ -0,0 +1,197 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use link_git::{
+    hash::ObjectId,
+    refdb::{error, Edit, Expected, Outcome, Refdb},
+    refs::Target,
+};
+
+use super::{delete, name, set};
+use crate::integration::fixture::{committer, Repo};
+
+const REF: &str = "refs/heads/topic";
+
+struct Setup {
+    repo: Repo,
+    refdb: Refdb,
+    a: ObjectId,
+    b: ObjectId,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let repo = Repo::new();
+        let a = repo.commit("a", "a");
+        let b = repo.commit("b", "b");
+        let refdb = Refdb::open(repo.git_dir()).unwrap();
+        Self { repo, refdb, a, b }
+    }
+
+    /// A [`Setup`] where `REF` points to `a`.
+    fn existing() -> Self {
+        let setup = Self::new();
+        setup.repo.git(&["update-ref", REF, &setup.a.to_string()]);
+        setup
+    }
+
+    fn update(&self, edit: Edit) -> Result<Outcome, error::Update> {
+        self.refdb
+            .update(Some(edit), &committer())
+            .map(|mut outcomes| outcomes.remove(0))
+    }
+
+    fn current(&self) -> Option<Target> {
+        self.refdb
+            .snapshot()
+            .unwrap()
+            .find(REF)
+            .unwrap()
+            .map(|r| r.target)
+    }
+}
+
+#[test]
+fn created() {
+    let b = Target::Peeled(ObjectId::null_sha1());
+    for expected in [Expected::Any, Expected::Absent, Expected::AbsentOr(b)] {
+        let setup = Setup::new();
+        let outcome = setup.update(set(REF, expected.clone(), setup.a)).unwrap();
+        assert_eq!(
+            outcome,
+            Outcome::Created {
+                name: name(REF),
+                new: Target::Peeled(setup.a),
+            },
+            "{:?}",
+            expected
+        );
+        assert_eq!(setup.current(), Some(Target::Peeled(setup.a)));
+    }
+}
+
+/// All [`Expected`] values which match a ref pointing to `a`.
+fn present() -> [Expected; 4] {
+    let a = Target::Peeled(Setup::new().a);
+    [
+        Expected::Any,
+        Expected::Present,
+        Expected::Is(a.clone()),
+        Expected::AbsentOr(a),
+    ]
+}
+
+#[test]
+fn updated() {
+    for expected in present() {
+        let setup = Setup::existing();
+        let outcome = setup.update(set(REF, expected.clone(), setup.b)).unwrap();
+        assert_eq!(
+            outcome,
+            Outcome::Updated {
+                name: name(REF),
+                previous: Target::Peeled(setup.a),
+                new: Target::Peeled(setup.b),
+            },
+            "{:?}",
+            expected
+        );
+        assert_eq!(setup.current(), Some(Target::Peeled(setup.b)));
+    }
+}
+
+#[test]
+fn deleted() {
+    for expected in present() {
+        let setup = Setup::existing();
+        let outcome = setup.update(delete(REF, expected.clone())).unwrap();
+        assert_eq!(
+            outcome,
+            Outcome::Deleted {
+                name: name(REF),
+                previous: Some(Target::Peeled(setup.a)),
+            },
+            "{:?}",
+            expected
+        );
+        assert_eq!(setup.current(), None);
+    }
+}
+
+#[test]
+fn deleted_nonexistent() {
+    let b = Target::Peeled(ObjectId::null_sha1());
+    for expected in [Expected::Any, Expected::AbsentOr(b)] {
+        let setup = Setup::new();
+        let outcome = setup.update(delete(REF, expected.clone())).unwrap();
+        assert_eq!(
+            outcome,
+            Outcome::Deleted {
+                name: name(REF),
+                previous: None,
+            },
+            "{:?}",
+            expected
+        );
+    }
+}
+
+#[test]
+fn delete_absent_is_rejected() {
+    let setup = Setup::existing();
+    assert!(matches!(
+        setup.update(delete(REF, Expected::Absent)),
+        Err(error::Update::DeleteAbsent(n)) if n == name(REF)
+    ));
+    assert_eq!(setup.current(), Some(Target::Peeled(setup.a)));
+}
+
+#[test]
+fn expectation_not_met() {
+    let setup = Setup::new();
+    let a = Target::Peeled(setup.a);
+    for expected in [Expected::Present, Expected::Is(a)] {
+        assert!(matches!(
+            setup.update(set(REF, expected, setup.b)),
+            Err(error::Update::Prepare(_))
+        ));
+        assert_eq!(setup.current(), None);
+    }
+
+    let setup = Setup::existing();
+    let b = Target::Peeled(setup.b);
+    for expected in [
+        Expected::Absent,
+        Expected::Is(b.clone()),
+        Expected::AbsentOr(b.clone()),
+    ] {
+        assert!(matches!(
+            setup.update(set(REF, expected, setup.b)),
+            Err(error::Update::Prepare(_))
+        ));
+        assert_eq!(setup.current(), Some(Target::Peeled(setup.a)));
+    }
+}
+
+#[test]
+fn atomic() {
+    let setup = Setup::existing();
+    let res = setup.refdb.update(
+        vec![
+            set("refs/heads/other", Expected::Absent, setup.b),
+            set(REF, Expected::Is(Target::Peeled(setup.b)), setup.b),
+        ],
+        &committer(),
+    );
+    assert!(res.is_err());
+    assert!(setup
+        .refdb
+        .snapshot()
+        .unwrap()
+        .find("refs/heads/other")
+        .unwrap()
+        .is_none());
+    assert_eq!(setup.current(), Some(Target::Peeled(setup.a)));
+}
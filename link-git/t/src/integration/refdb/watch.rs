@@ -0,0 +1,125 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::time::Duration;
+
+use futures::{FutureExt as _, StreamExt as _};
+use link_git::{
+    refdb::{
+        watch::{Event, Subscription},
+        Refdb,
+    },
+    refs::Target,
+};
+
+use super::name;
+use crate::integration::fixture::{eventually, Repo};
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+fn next(sub: &mut Subscription) -> Event {
+    let mut event = None;
+    assert!(
+        eventually(|| {
+            event = sub.next().now_or_never().flatten();
+            event.is_some()
+        }),
+        "no event"
+    );
+    event.unwrap()
+}
+
+#[test]
+fn loose_and_packed() {
+    let repo = Repo::new();
+    let a = repo.commit("a", "a");
+    let b = repo.commit("b", "b");
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+
+    let mut sub = {
+        let watcher = refdb.watch(INTERVAL).unwrap();
+        watcher.subscribe("refs/heads/topic").unwrap()
+    };
+
+    repo.git(&["update-ref", "refs/heads/topic", &a.to_string()]);
+    assert_eq!(
+        next(&mut sub),
+        Event::Created {
+            name: name("refs/heads/topic"),
+            new: Target::Peeled(a),
+        }
+    );
+
+    repo.git(&["pack-refs", "--all"]);
+    repo.git(&["update-ref", "refs/heads/topic", &b.to_string()]);
+    assert_eq!(
+        next(&mut sub),
+        Event::Updated {
+            name: name("refs/heads/topic"),
+            old: Target::Peeled(a),
+            new: Target::Peeled(b),
+        }
+    );
+
+    repo.git(&["pack-refs", "--all"]);
+    repo.git(&["update-ref", "-d", "refs/heads/topic"]);
+    assert_eq!(
+        next(&mut sub),
+        Event::Deleted {
+            name: name("refs/heads/topic"),
+            old: Target::Peeled(b),
+        }
+    );
+}
+
+#[test]
+fn filters_by_prefix() {
+    let repo = Repo::new();
+    let a = repo.commit("a", "a");
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let watcher = refdb.watch(INTERVAL).unwrap();
+    let mut heads = watcher.subscribe("refs/heads/").unwrap();
+    let mut tags = watcher.subscribe("refs/tags/").unwrap();
+
+    repo.git(&["update-ref", "refs/tags/v1", &a.to_string()]);
+    assert_eq!(next(&mut tags).name(), &name("refs/tags/v1"));
+
+    repo.git(&["update-ref", "refs/heads/topic", &a.to_string()]);
+    assert_eq!(next(&mut heads).name(), &name("refs/heads/topic"));
+}
+
+#[test]
+fn dropped_subscription() {
+    let repo = Repo::new();
+    let a = repo.commit("a", "a");
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let watcher = refdb.watch(INTERVAL).unwrap();
+    let dropped = watcher.subscribe("refs/heads/").unwrap();
+    let mut sub = watcher.subscribe("refs/heads/").unwrap();
+    drop(dropped);
+
+    repo.git(&["update-ref", "refs/heads/topic", &a.to_string()]);
+    assert_eq!(next(&mut sub).name(), &name("refs/heads/topic"));
+}
+
+#[test]
+fn missing_prefix() {
+    let repo = Repo::new();
+    let a = repo.commit("a", "a");
+    let refdb = Refdb::open(repo.git_dir()).unwrap();
+    let watcher = refdb.watch(INTERVAL).unwrap();
+    // Neither the prefix nor its parent directory exist
+    let mut sub = watcher.subscribe("refs/namespaces/a/refs/heads").unwrap();
+
+    repo.git(&[
+        "update-ref",
+        "refs/namespaces/a/refs/heads/main",
+        &a.to_string(),
+    ]);
+    assert_eq!(
+        next(&mut sub).name(),
+        &name("refs/namespaces/a/refs/heads/main")
+    );
+}
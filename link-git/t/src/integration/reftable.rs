@@ -0,0 +1,597 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fs, path::Path, process::Command};
+
+use bstr::ByteSlice as _;
+use link_git::{
+    actor::{Sign, Signature, Time},
+    hash::ObjectId,
+    refdb::{Edit, Expected, New, Outcome},
+    refs::{reftable::Refdb, FullName, Target},
+};
+use tempfile::{tempdir, TempDir};
+
+fn committer() -> Signature {
+    Signature {
+        name: "apollo".into(),
+        email: "apollo@cree.de".into(),
+        time: Time {
+            time: 1_640_000_000,
+            offset: 3600,
+            sign: Sign::Plus,
+        },
+    }
+}
+
+fn name(s: &str) -> FullName {
+    s.try_into().unwrap()
+}
+
+fn oid(b: u8) -> ObjectId {
+    ObjectId::from([b; 20])
+}
+
+fn set(name_: &str, expected: Expected, new: New) -> Edit {
+    Edit {
+        name: name(name_),
+        expected,
+        new,
+        reflog_message: "test".into(),
+    }
+}
+
+/// An empty reftable stack, as far as [`Refdb`] is concerned.
+fn empty() -> TempDir {
+    let tmp = tempdir().unwrap();
+    fs::create_dir(tmp.path().join("reftable")).unwrap();
+    fs::write(tmp.path().join("reftable").join("tables.list"), b"").unwrap();
+    tmp
+}
+
+/// Whether the installed git supports the reftable format, ie. is version
+/// 2.45 or later.
+fn git_supports_reftable() -> bool {
+    let version = git(Path::new("."), &["--version"]);
+    // git version 2.45.1 <other optional tokens>
+    let mut parts = version
+        .split(' ')
+        .nth(2)
+        .unwrap_or_default()
+        .split('.')
+        .map(|n| n.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= (2, 45)
+}
+
+/// `git init --ref-format=reftable`, which requires git 2.45 or later.
+///
+/// Returns `None` if the installed git is too old.
+fn git_init() -> Option<TempDir> {
+    if !git_supports_reftable() {
+        eprintln!("skipping: git 2.45 or later is required for reftable support");
+        return None;
+    }
+    let tmp = tempdir().unwrap();
+    let status = Command::new("git")
+        .args(["init", "--quiet", "--ref-format=reftable"])
+        .arg(tmp.path())
+        .status()
+        .unwrap();
+    assert!(status.success(), "git init --ref-format=reftable failed");
+    Some(tmp)
+}
+
+fn tables(tmp: &TempDir) -> Vec<String> {
+    fs::read_to_string(tmp.path().join("reftable").join("tables.list"))
+        .unwrap()
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+fn git(repo: &Path, args: &[&str]) -> String {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", "apollo")
+        .env("GIT_AUTHOR_EMAIL", "apollo@cree.de")
+        .env("GIT_COMMITTER_NAME", "apollo")
+        .env("GIT_COMMITTER_EMAIL", "apollo@cree.de")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "git {:?} failed", args);
+    out.stdout.to_str().unwrap().trim().to_owned()
+}
+
+#[test]
+fn roundtrip() {
+    let tmp = empty();
+    let db = Refdb::open(tmp.path()).unwrap();
+
+    let outcomes = db
+        .update(
+            vec![
+                set("refs/heads/main", Expected::Absent, New::Direct(oid(1))),
+                set(
+                    "HEAD",
+                    Expected::Any,
+                    New::Symbolic(name("refs/heads/main")),
+                ),
+            ],
+            &committer(),
+        )
+        .unwrap();
+    assert_eq!(
+        outcomes[0],
+        Outcome::Created {
+            name: name("refs/heads/main"),
+            new: Target::Peeled(oid(1))
+        }
+    );
+
+    db.update(
+        Some(set(
+            "refs/heads/main",
+            Expected::Is(Target::Peeled(oid(1))),
+            New::Direct(oid(2)),
+        )),
+        &committer(),
+    )
+    .unwrap();
+
+    let snap = db.snapshot().unwrap();
+    let main = snap.find("refs/heads/main").unwrap().unwrap();
+    assert_eq!(main.target, Target::Peeled(oid(2)));
+    let head = snap.find("HEAD").unwrap().unwrap();
+    assert_eq!(snap.follow(&head).unwrap().target, Target::Peeled(oid(2)));
+
+    let log = snap.reflog(&name("refs/heads/main")).unwrap().unwrap();
+    assert_eq!(log.name(), &name("refs/heads/main"));
+    let entries = log.iter().map(|e| (e.previous, e.new)).collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        vec![(ObjectId::null_sha1(), oid(1)), (oid(1), oid(2))]
+    );
+    assert!(log.iter().all(|e| e.committer == committer()));
+}
+
+#[test]
+fn iter_prefix_and_deletion() {
+    let tmp = empty();
+    let db = Refdb::open(tmp.path()).unwrap();
+    db.update(
+        ["a", "b", "c"]
+            .iter()
+            .map(|b| {
+                set(
+                    &format!("refs/namespaces/x/refs/heads/{}", b),
+                    Expected::Absent,
+                    New::Direct(oid(1)),
+                )
+            })
+            .chain(Some(set(
+                "refs/namespaces/y/refs/heads/a",
+                Expected::Absent,
+                New::Direct(oid(1)),
+            ))),
+        &committer(),
+    )
+    .unwrap();
+    db.update(
+        Some(set(
+            "refs/namespaces/x/refs/heads/b",
+            Expected::Present,
+            New::Delete,
+        )),
+        &committer(),
+    )
+    .unwrap();
+
+    let snap = db.snapshot().unwrap();
+    let names = snap
+        .iter(Some("refs/namespaces/x/"))
+        .unwrap()
+        .map(|r| r.unwrap().name.as_bstr().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "refs/namespaces/x/refs/heads/a",
+            "refs/namespaces/x/refs/heads/c",
+        ]
+    );
+    assert!(snap
+        .reflog(&name("refs/namespaces/x/refs/heads/b"))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn rejected_update_is_atomic() {
+    let tmp = empty();
+    let db = Refdb::open(tmp.path()).unwrap();
+    db.update(
+        Some(set(
+            "refs/heads/main",
+            Expected::Absent,
+            New::Direct(oid(1)),
+        )),
+        &committer(),
+    )
+    .unwrap();
+
+    let res = db.update(
+        vec![
+            set("refs/heads/next", Expected::Absent, New::Direct(oid(2))),
+            set("refs/heads/main", Expected::Absent, New::Direct(oid(2))),
+        ],
+        &committer(),
+    );
+    assert!(res.is_err());
+    let snap = db.snapshot().unwrap();
+    assert!(snap.find("refs/heads/next").unwrap().is_none());
+    assert_eq!(
+        snap.find("refs/heads/main").unwrap().unwrap().target,
+        Target::Peeled(oid(1))
+    );
+}
+
+#[test]
+fn compaction() {
+    let tmp = empty();
+    let db = Refdb::open(tmp.path()).unwrap();
+    for i in 1..=64u8 {
+        db.update(
+            vec![
+                set("refs/heads/main", Expected::Any, New::Direct(oid(i))),
+                set(
+                    &format!("refs/tags/v{}", i),
+                    Expected::Absent,
+                    New::Direct(oid(i)),
+                ),
+            ],
+            &committer(),
+        )
+        .unwrap();
+        if i % 2 == 0 {
+            db.update(
+                Some(set(
+                    &format!("refs/tags/v{}", i - 1),
+                    Expected::Present,
+                    New::Delete,
+                )),
+                &committer(),
+            )
+            .unwrap();
+        }
+    }
+
+    let tables = tables(&tmp);
+    assert!(tables.len() <= 8, "stack not compacted: {:?}", tables);
+    let on_disk = fs::read_dir(tmp.path().join("reftable"))
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension() == Some("ref".as_ref()))
+        .count();
+    assert_eq!(on_disk, tables.len(), "compacted tables not removed");
+
+    let snap = db.snapshot().unwrap();
+    assert_eq!(
+        snap.find("refs/heads/main").unwrap().unwrap().target,
+        Target::Peeled(oid(64))
+    );
+    let tags = snap
+        .iter(Some("refs/tags/"))
+        .unwrap()
+        .map(|r| r.unwrap().name)
+        .collect::<Vec<_>>();
+    assert_eq!(tags.len(), 32);
+    assert!(tags.iter().all(|tag| {
+        let n: u8 = tag.as_bstr().to_str().unwrap()["refs/tags/v".len()..]
+            .parse()
+            .unwrap();
+        n % 2 == 0
+    }));
+    let log = snap.reflog(&name("refs/heads/main")).unwrap().unwrap();
+    assert_eq!(log.iter().count(), 64);
+    assert_eq!(log.iter().next_back().unwrap().new, oid(64));
+    assert!(snap.reflog(&name("refs/tags/v1")).unwrap().is_none());
+}
+
+#[test]
+fn read_git_reftable() {
+    let tmp = match git_init() {
+        None => return,
+        Some(tmp) => tmp,
+    };
+    let repo = tmp.path();
+    git(
+        repo,
+        &["commit", "--quiet", "--allow-empty", "-m", "initial"],
+    );
+    git(repo, &["branch", "next"]);
+    let head = git(repo, &["rev-parse", "HEAD"]);
+    let branch = git(repo, &["symbolic-ref", "HEAD"]);
+
+    let db = Refdb::open(repo.join(".git")).unwrap();
+    let snap = db.snapshot().unwrap();
+    let main = snap.find(&branch).unwrap().unwrap();
+    assert_eq!(
+        main.target,
+        Target::Peeled(ObjectId::from_hex(head.as_bytes()).unwrap())
+    );
+    let followed = snap.follow(&snap.find("HEAD").unwrap().unwrap()).unwrap();
+    assert_eq!(followed.name, main.name);
+    assert_eq!(
+        snap.iter(Some("refs/heads/")).unwrap().count(),
+        2,
+        "expected {} and refs/heads/next",
+        branch
+    );
+    assert!(snap.reflog(&name(&branch)).unwrap().is_some());
+}
+
+#[test]
+fn git_reads_our_reftable() {
+    let tmp = match git_init() {
+        None => return,
+        Some(tmp) => tmp,
+    };
+    let repo = tmp.path();
+    git(
+        repo,
+        &["commit", "--quiet", "--allow-empty", "-m", "initial"],
+    );
+    let head = ObjectId::from_hex(git(repo, &["rev-parse", "HEAD"]).as_bytes()).unwrap();
+
+    let db = Refdb::open(repo.join(".git")).unwrap();
+    db.update(
+        Some(set("refs/tags/v1", Expected::Absent, New::Direct(head))),
+        &committer(),
+    )
+    .unwrap();
+
+    assert_eq!(git(repo, &["rev-parse", "refs/tags/v1"]), head.to_string());
+    git(repo, &["fsck", "--no-progress"]);
+}
+
+#[test]
+fn git_reads_our_indexed_reftable() {
+    let tmp = match git_init() {
+        None => return,
+        Some(tmp) => tmp,
+    };
+    let repo = tmp.path();
+    git(
+        repo,
+        &["commit", "--quiet", "--allow-empty", "-m", "initial"],
+    );
+    let head = ObjectId::from_hex(git(repo, &["rev-parse", "HEAD"]).as_bytes()).unwrap();
+
+    let db = Refdb::open(repo.join(".git")).unwrap();
+    db.update(
+        (0..2000).map(|i| {
+            set(
+                &format!("refs/tags/{}", tag(i)),
+                Expected::Absent,
+                New::Direct(head),
+            )
+        }),
+        &committer(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        git(repo, &["for-each-ref", "refs/tags/"]).lines().count(),
+        2000
+    );
+    assert_eq!(
+        git(repo, &["rev-parse", &format!("refs/tags/{}", tag(1234))]),
+        head.to_string()
+    );
+}
+
+/// A tag name long enough to fill ref blocks quickly.
+fn tag(i: usize) -> String {
+    format!("{}-{:05}", "x".repeat(80), i)
+}
+
+/// Like [`tag`], but sorting in a different order than `i`, such that
+/// prefix compression is not effective.
+fn scattered_tag(i: u32) -> String {
+    format!("{:08x}-{}", i.wrapping_mul(2_654_435_761), "x".repeat(80))
+}
+
+/// Position of the root ref index block, as recorded in the footer of the
+/// (version 1) table `name`.
+fn ref_index_position(tmp: &TempDir, name: &str) -> u64 {
+    let data = fs::read(tmp.path().join("reftable").join(name)).unwrap();
+    let footer = &data[data.len() - 68..];
+    u64::from_be_bytes(footer[24..32].try_into().unwrap())
+}
+
+#[test]
+fn ref_index() {
+    let tmp = empty();
+    let db = Refdb::open(tmp.path()).unwrap();
+    db.update(
+        (0..1000).map(|i| {
+            set(
+                &format!("refs/tags/{}", tag(i)),
+                Expected::Absent,
+                New::Direct(oid(1)),
+            )
+        }),
+        &committer(),
+    )
+    .unwrap();
+
+    let tables = tables(&tmp);
+    assert_eq!(tables.len(), 1);
+    assert_ne!(ref_index_position(&tmp, &tables[0]), 0);
+
+    let snap = db.snapshot().unwrap();
+    for i in [0, 1, 499, 998, 999] {
+        let name = format!("refs/tags/{}", tag(i));
+        assert_eq!(
+            snap.find(&name).unwrap().map(|r| r.target),
+            Some(Target::Peeled(oid(1))),
+            "{}",
+            name
+        );
+    }
+    assert!(snap.find("refs/tags/nope").unwrap().is_none());
+    assert!(snap.find("refs/tags/zzz").unwrap().is_none());
+    assert_eq!(snap.iter(Some("refs/tags/")).unwrap().count(), 1000);
+    let prefix = format!("refs/tags/{}", &tag(500)[..85]);
+    assert_eq!(snap.iter(Some(prefix.as_str())).unwrap().count(), 10);
+    assert!(snap
+        .reflog(&name(&format!("refs/tags/{}", tag(777))))
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn multi_level_ref_index() {
+    let tmp = empty();
+    let db = Refdb::open(tmp.path()).unwrap();
+    db.update(
+        (0..20_000).map(|i| {
+            set(
+                &format!("refs/tags/{}", scattered_tag(i)),
+                Expected::Absent,
+                New::Direct(oid(1)),
+            )
+        }),
+        &committer(),
+    )
+    .unwrap();
+
+    // The root index block is preceded by the index blocks it indexes
+    let tables = tables(&tmp);
+    let data = fs::read(tmp.path().join("reftable").join(&tables[0])).unwrap();
+    let root = ref_index_position(&tmp, &tables[0]) as usize;
+    assert_eq!(data[root], b'i');
+    assert_eq!(data[root - 4096], b'i');
+
+    let snap = db.snapshot().unwrap();
+    for i in [0, 4321, 19_999] {
+        let name = format!("refs/tags/{}", scattered_tag(i));
+        assert!(snap.find(&name).unwrap().is_some(), "{}", name);
+        assert_eq!(
+            snap.reflog(&self::name(&name))
+                .unwrap()
+                .unwrap()
+                .iter()
+                .count(),
+            1
+        );
+    }
+    assert!(snap
+        .find(format!("refs/tags/{}", scattered_tag(20_000)))
+        .unwrap()
+        .is_none());
+    assert_eq!(snap.iter(Some("refs/tags/")).unwrap().count(), 20_000);
+}
+
+/// CRC-32 (IEEE), for the table footer.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// A single block of type `typ`, with one restart point at the first record.
+/// `start` is the position of the block, or the header length for the first
+/// block.
+fn block(typ: u8, start: usize, record: &[u8], len: Option<u32>) -> Vec<u8> {
+    let len = len.unwrap_or((start + 4 + record.len() + 3 + 2) as u32);
+    let mut block = vec![typ];
+    block.extend_from_slice(&len.to_be_bytes()[1..]);
+    block.extend_from_slice(record);
+    block.extend_from_slice(&((start + 4) as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(&1u16.to_be_bytes());
+    block
+}
+
+/// A table whose single ref block is followed by an index block at
+/// `index_position`, whose single record points at `index_target`.
+fn malformed_table(ref_block_len: Option<u32>, index_target: u8) -> Vec<u8> {
+    let mut header = b"REFT\x01\x00\x10\x00".to_vec();
+    header.extend_from_slice(&1u64.to_be_bytes());
+    header.extend_from_slice(&1u64.to_be_bytes());
+
+    let key = b"refs/heads/x";
+    // prefix length, suffix length and value type, suffix, update index delta
+    let mut record = vec![0, (key.len() << 3) as u8 | 1];
+    record.extend_from_slice(key);
+    record.push(0);
+    record.extend_from_slice(&[1; 20]);
+
+    let mut table = header.clone();
+    table.extend(block(b'r', header.len(), &record, ref_block_len));
+    let index_position = table.len();
+    let mut record = vec![0, (key.len() << 3) as u8];
+    record.extend_from_slice(key);
+    record.push(index_target);
+    table.extend(block(b'i', 0, &record, None));
+
+    let footer_start = table.len();
+    table.extend_from_slice(&header);
+    table.extend_from_slice(&(index_position as u64).to_be_bytes());
+    table.extend_from_slice(&[0; 32]);
+    let crc = crc32(&table[footer_start..]);
+    table.extend_from_slice(&crc.to_be_bytes());
+    table
+}
+
+fn with_table(data: Vec<u8>) -> TempDir {
+    let tmp = empty();
+    let name = "0x000000000001-0x000000000001-00000000.ref";
+    fs::write(tmp.path().join("reftable").join(name), data).unwrap();
+    fs::write(
+        tmp.path().join("reftable").join("tables.list"),
+        format!("{}\n", name),
+    )
+    .unwrap();
+    tmp
+}
+
+#[test]
+fn malformed_tables() {
+    // Sanity check: the index points at the ref block
+    let tmp = with_table(malformed_table(None, 0));
+    let snap = Refdb::open(tmp.path()).unwrap().snapshot().unwrap();
+    assert_eq!(
+        snap.find("refs/heads/x").unwrap().unwrap().target,
+        Target::Peeled(ObjectId::from([1; 20]))
+    );
+
+    // The index points at itself
+    let tmp = with_table(malformed_table(None, 68));
+    let snap = Refdb::open(tmp.path()).unwrap().snapshot().unwrap();
+    assert!(snap.find("refs/heads/x").is_err());
+
+    // The index points past the end of the file
+    let tmp = with_table(malformed_table(None, 0x7f));
+    let snap = Refdb::open(tmp.path()).unwrap().snapshot().unwrap();
+    assert!(snap.find("refs/heads/x").is_err());
+
+    // The ref block exceeds the file
+    let tmp = with_table(malformed_table(Some(0xff_ffff), 0));
+    let snap = Refdb::open(tmp.path()).unwrap().snapshot().unwrap();
+    assert!(snap.find("refs/heads/x").is_err());
+    match snap.iter(None::<&str>) {
+        Err(_) => {},
+        Ok(mut iter) => assert!(iter.any(|r| r.is_err())),
+    }
+}
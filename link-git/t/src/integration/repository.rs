@@ -0,0 +1,133 @@
+// Copyright © 2021 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use link_git::{
+    hash::ObjectId,
+    odb::{cache, index::Shared, window},
+    refdb::Refdb,
+    repository::{error, Repository},
+};
+
+use crate::integration::fixture::Repo;
+
+/// `main`: c1 - c2, with `v1` an annotated tag of c1.
+struct Setup {
+    repo: Repo,
+    c1: ObjectId,
+    c2: ObjectId,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let repo = Repo::new();
+        let c1 = repo.commit("a", "a");
+        repo.git(&["tag", "-a", "-m", "v1", "v1"]);
+        std::fs::create_dir(repo.path().join("dir")).unwrap();
+        let c2 = repo.commit("dir/b", "b");
+        Self { repo, c1, c2 }
+    }
+
+    fn repository(&self) -> Repository<Shared<()>, window::Small<()>> {
+        Repository::new(Refdb::open(self.repo.git_dir()).unwrap(), self.repo.odb())
+    }
+
+    fn resolve(&self, rev: &str) -> Result<ObjectId, error::Resolve> {
+        self.repository().resolve(rev, &mut cache::Never)
+    }
+}
+
+#[test]
+fn resolve() {
+    let setup = Setup::new();
+    for (rev, expected) in [
+        ("main", setup.c2),
+        ("refs/heads/main", setup.c2),
+        ("heads/main", setup.c2),
+        ("HEAD", setup.c2),
+        ("@", setup.c2),
+        ("HEAD~1", setup.c1),
+        ("HEAD^", setup.c1),
+        ("main@{1}", setup.c1),
+        ("v1^{}", setup.c1),
+        ("v1^{commit}", setup.c1),
+        (&setup.c1.to_string(), setup.c1),
+    ] {
+        assert_eq!(setup.resolve(rev).unwrap(), expected, "{}", rev);
+    }
+
+    for rev in [
+        "v1",
+        "HEAD^{tree}",
+        "HEAD:a",
+        "HEAD:dir/b",
+        "HEAD~1:a",
+        "v1:a",
+    ] {
+        assert_eq!(
+            setup.resolve(rev).unwrap(),
+            setup.repo.rev_parse(rev),
+            "{}",
+            rev
+        );
+    }
+}
+
+#[test]
+fn resolve_errors() {
+    let setup = Setup::new();
+    assert!(matches!(
+        setup.resolve("nope"),
+        Err(error::Resolve::Unknown(_))
+    ));
+    assert!(matches!(
+        setup.resolve("HEAD~2"),
+        Err(error::Resolve::NoParent { n: 1, .. })
+    ));
+    assert!(matches!(
+        setup.resolve("HEAD:nope"),
+        Err(error::Resolve::NoPath { .. })
+    ));
+    assert!(matches!(
+        setup.resolve("main@{5}"),
+        Err(error::Resolve::NoReflogEntry { n: 5, .. })
+    ));
+    assert!(matches!(
+        setup.resolve("HEAD^{/message}"),
+        Err(error::Resolve::Unsupported(_))
+    ));
+    assert!(matches!(
+        setup.resolve("HEAD^{tag}"),
+        Err(error::Resolve::Peel(error::Peel::Mismatch { .. }))
+    ));
+    assert!(matches!(
+        setup.resolve("main..next"),
+        Err(error::Resolve::Syntax { .. })
+    ));
+}
+
+#[test]
+fn resolve_abbreviated() {
+    let setup = Setup::new();
+    let abbrev = &setup.c1.to_string()[..7];
+    assert!(matches!(
+        setup.resolve(abbrev),
+        Err(error::Resolve::Unknown(_))
+    ));
+}
+
+/// A ref name of 40 characters which is not hex is a ref name, not an object
+/// id.
+#[test]
+fn resolve_non_hex_name() {
+    let setup = Setup::new();
+    let name = "z".repeat(40);
+    assert!(matches!(
+        setup.resolve(&name),
+        Err(error::Resolve::Unknown(_))
+    ));
+
+    setup.repo.git(&["branch", &name, &setup.c1.to_string()]);
+    assert_eq!(setup.resolve(&name).unwrap(), setup.c1);
+}